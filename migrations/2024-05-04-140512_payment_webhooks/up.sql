@@ -0,0 +1,19 @@
+CREATE TABLE linked_account (
+    provider VARCHAR(32) NOT NULL,
+    external_id VARCHAR(255) NOT NULL,
+    user_id NUMERIC(20, 0) NOT NULL,
+    creation_timestamp TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (provider, external_id)
+);
+
+CREATE INDEX linked_account_user_id_idx ON linked_account (user_id);
+
+CREATE TABLE payment_event (
+    provider VARCHAR(32) NOT NULL,
+    event_id VARCHAR(255) NOT NULL,
+    external_id VARCHAR(255) NOT NULL,
+    event_type VARCHAR(255) NOT NULL,
+    user_id NUMERIC(20, 0),
+    creation_timestamp TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (provider, event_id)
+);
@@ -0,0 +1,2 @@
+DROP TABLE account_link_code;
+ALTER TABLE linked_account DROP COLUMN verified;
@@ -0,0 +1,12 @@
+-- existing links have been created manually and are considered verified
+ALTER TABLE linked_account ADD COLUMN verified BOOLEAN NOT NULL DEFAULT FALSE;
+UPDATE linked_account SET verified = TRUE;
+
+CREATE TABLE account_link_code (
+    code_hash VARCHAR(64) NOT NULL,
+    user_id NUMERIC(20, 0) NOT NULL,
+    provider VARCHAR(32) NOT NULL,
+    expiration_timestamp TIMESTAMP WITH TIME ZONE NOT NULL,
+    creation_timestamp TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (code_hash)
+);
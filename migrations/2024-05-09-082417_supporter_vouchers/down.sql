@@ -0,0 +1,4 @@
+DROP TABLE voucher_redemption;
+DROP TABLE voucher;
+ALTER TABLE aiode_supporter DROP COLUMN expiration_timestamp;
+ALTER TABLE aiode_supporter DROP COLUMN tier;
@@ -0,0 +1,26 @@
+ALTER TABLE aiode_supporter ADD COLUMN tier VARCHAR(32);
+-- NULL for supporters that are granted permanently, e.g. through the supporter role or payments
+ALTER TABLE aiode_supporter ADD COLUMN expiration_timestamp TIMESTAMP WITH TIME ZONE;
+
+CREATE TABLE voucher (
+    pk SERIAL NOT NULL,
+    code_hash VARCHAR(64) NOT NULL UNIQUE,
+    batch VARCHAR(255) NOT NULL,
+    tier VARCHAR(32) NOT NULL,
+    duration_days INTEGER NOT NULL,
+    max_redemptions INTEGER NOT NULL,
+    redemption_count INTEGER NOT NULL DEFAULT 0,
+    revoked BOOLEAN NOT NULL DEFAULT FALSE,
+    expiration_timestamp TIMESTAMP WITH TIME ZONE,
+    creation_timestamp TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (pk)
+);
+
+CREATE INDEX voucher_batch_idx ON voucher (batch);
+
+CREATE TABLE voucher_redemption (
+    voucher_pk INTEGER NOT NULL REFERENCES voucher (pk) ON DELETE CASCADE,
+    user_id NUMERIC(20, 0) NOT NULL,
+    creation_timestamp TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (voucher_pk, user_id)
+);
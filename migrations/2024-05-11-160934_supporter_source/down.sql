@@ -0,0 +1 @@
+ALTER TABLE aiode_supporter DROP COLUMN source;
@@ -0,0 +1,2 @@
+ALTER TABLE aiode_supporter ADD COLUMN source VARCHAR(32) NOT NULL DEFAULT 'role';
+UPDATE aiode_supporter SET source = 'voucher' WHERE expiration_timestamp IS NOT NULL;
@@ -0,0 +1,2 @@
+-- set when the user lost the supporter role, supporter status is revoked after this point unless the role is re-granted
+ALTER TABLE aiode_supporter ADD COLUMN revocation_deadline TIMESTAMP WITH TIME ZONE;
@@ -0,0 +1 @@
+DROP TABLE supporter_gift;
@@ -0,0 +1,14 @@
+CREATE TABLE supporter_gift (
+    pk SERIAL NOT NULL,
+    -- NULL if the gift has been granted by an admin
+    gifter_user_id NUMERIC(20, 0),
+    recipient_user_id NUMERIC(20, 0) NOT NULL,
+    tier VARCHAR(32) NOT NULL,
+    duration_days INTEGER NOT NULL,
+    expiration_timestamp TIMESTAMP WITH TIME ZONE NOT NULL,
+    creation_timestamp TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (pk)
+);
+
+CREATE INDEX supporter_gift_gifter_user_id_idx ON supporter_gift (gifter_user_id, creation_timestamp);
+CREATE INDEX supporter_gift_recipient_user_id_idx ON supporter_gift (recipient_user_id);
@@ -0,0 +1,7 @@
+CREATE TABLE supporter_announcement (
+    announcement_key VARCHAR(255) NOT NULL,
+    -- NULL for announcements that do not concern a single user, e.g. supporter count milestones
+    user_id NUMERIC(20, 0),
+    creation_timestamp TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (announcement_key)
+);
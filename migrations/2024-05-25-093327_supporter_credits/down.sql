@@ -0,0 +1 @@
+DROP TABLE supporter_credits_opt_in;
@@ -0,0 +1,6 @@
+-- kept separately from aiode_supporter so that the preference survives lapses in supporter status
+CREATE TABLE supporter_credits_opt_in (
+    user_id NUMERIC(20, 0) NOT NULL,
+    creation_timestamp TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_id)
+);
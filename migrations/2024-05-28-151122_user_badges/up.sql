@@ -0,0 +1,8 @@
+CREATE TABLE user_badge (
+    user_id NUMERIC(20, 0) NOT NULL,
+    badge VARCHAR(32) NOT NULL,
+    creation_timestamp TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_id, badge)
+);
+
+CREATE INDEX user_badge_badge_idx ON user_badge (badge);
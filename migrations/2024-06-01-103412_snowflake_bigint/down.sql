@@ -0,0 +1,39 @@
+ALTER TABLE account_link_code
+    ALTER COLUMN user_id TYPE NUMERIC(20, 0)
+    USING CASE WHEN user_id < 0 THEN user_id::NUMERIC + 18446744073709551616 ELSE user_id::NUMERIC END;
+
+ALTER TABLE aiode_supporter
+    ALTER COLUMN user_id TYPE NUMERIC(20, 0)
+    USING CASE WHEN user_id < 0 THEN user_id::NUMERIC + 18446744073709551616 ELSE user_id::NUMERIC END;
+
+ALTER TABLE linked_account
+    ALTER COLUMN user_id TYPE NUMERIC(20, 0)
+    USING CASE WHEN user_id < 0 THEN user_id::NUMERIC + 18446744073709551616 ELSE user_id::NUMERIC END;
+
+ALTER TABLE payment_event
+    ALTER COLUMN user_id TYPE NUMERIC(20, 0)
+    USING CASE WHEN user_id < 0 THEN user_id::NUMERIC + 18446744073709551616 ELSE user_id::NUMERIC END;
+
+ALTER TABLE supporter_announcement
+    ALTER COLUMN user_id TYPE NUMERIC(20, 0)
+    USING CASE WHEN user_id < 0 THEN user_id::NUMERIC + 18446744073709551616 ELSE user_id::NUMERIC END;
+
+ALTER TABLE supporter_credits_opt_in
+    ALTER COLUMN user_id TYPE NUMERIC(20, 0)
+    USING CASE WHEN user_id < 0 THEN user_id::NUMERIC + 18446744073709551616 ELSE user_id::NUMERIC END;
+
+ALTER TABLE supporter_gift
+    ALTER COLUMN gifter_user_id TYPE NUMERIC(20, 0)
+    USING CASE WHEN gifter_user_id < 0 THEN gifter_user_id::NUMERIC + 18446744073709551616 ELSE gifter_user_id::NUMERIC END;
+
+ALTER TABLE supporter_gift
+    ALTER COLUMN recipient_user_id TYPE NUMERIC(20, 0)
+    USING CASE WHEN recipient_user_id < 0 THEN recipient_user_id::NUMERIC + 18446744073709551616 ELSE recipient_user_id::NUMERIC END;
+
+ALTER TABLE user_badge
+    ALTER COLUMN user_id TYPE NUMERIC(20, 0)
+    USING CASE WHEN user_id < 0 THEN user_id::NUMERIC + 18446744073709551616 ELSE user_id::NUMERIC END;
+
+ALTER TABLE voucher_redemption
+    ALTER COLUMN user_id TYPE NUMERIC(20, 0)
+    USING CASE WHEN user_id < 0 THEN user_id::NUMERIC + 18446744073709551616 ELSE user_id::NUMERIC END;
@@ -0,0 +1,42 @@
+-- Discord snowflakes are stored as BIGINT by reinterpreting the bits of the u64, i.e. ids of 2^63 and above
+-- are stored as negative numbers.
+
+ALTER TABLE account_link_code
+    ALTER COLUMN user_id TYPE BIGINT
+    USING (CASE WHEN user_id >= 9223372036854775808 THEN user_id - 18446744073709551616 ELSE user_id END)::BIGINT;
+
+ALTER TABLE aiode_supporter
+    ALTER COLUMN user_id TYPE BIGINT
+    USING (CASE WHEN user_id >= 9223372036854775808 THEN user_id - 18446744073709551616 ELSE user_id END)::BIGINT;
+
+ALTER TABLE linked_account
+    ALTER COLUMN user_id TYPE BIGINT
+    USING (CASE WHEN user_id >= 9223372036854775808 THEN user_id - 18446744073709551616 ELSE user_id END)::BIGINT;
+
+ALTER TABLE payment_event
+    ALTER COLUMN user_id TYPE BIGINT
+    USING (CASE WHEN user_id >= 9223372036854775808 THEN user_id - 18446744073709551616 ELSE user_id END)::BIGINT;
+
+ALTER TABLE supporter_announcement
+    ALTER COLUMN user_id TYPE BIGINT
+    USING (CASE WHEN user_id >= 9223372036854775808 THEN user_id - 18446744073709551616 ELSE user_id END)::BIGINT;
+
+ALTER TABLE supporter_credits_opt_in
+    ALTER COLUMN user_id TYPE BIGINT
+    USING (CASE WHEN user_id >= 9223372036854775808 THEN user_id - 18446744073709551616 ELSE user_id END)::BIGINT;
+
+ALTER TABLE supporter_gift
+    ALTER COLUMN gifter_user_id TYPE BIGINT
+    USING (CASE WHEN gifter_user_id >= 9223372036854775808 THEN gifter_user_id - 18446744073709551616 ELSE gifter_user_id END)::BIGINT;
+
+ALTER TABLE supporter_gift
+    ALTER COLUMN recipient_user_id TYPE BIGINT
+    USING (CASE WHEN recipient_user_id >= 9223372036854775808 THEN recipient_user_id - 18446744073709551616 ELSE recipient_user_id END)::BIGINT;
+
+ALTER TABLE user_badge
+    ALTER COLUMN user_id TYPE BIGINT
+    USING (CASE WHEN user_id >= 9223372036854775808 THEN user_id - 18446744073709551616 ELSE user_id END)::BIGINT;
+
+ALTER TABLE voucher_redemption
+    ALTER COLUMN user_id TYPE BIGINT
+    USING (CASE WHEN user_id >= 9223372036854775808 THEN user_id - 18446744073709551616 ELSE user_id END)::BIGINT;
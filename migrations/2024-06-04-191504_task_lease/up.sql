@@ -0,0 +1,5 @@
+CREATE TABLE task_lease (
+    task_id VARCHAR(64) PRIMARY KEY,
+    holder VARCHAR(64) NOT NULL,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
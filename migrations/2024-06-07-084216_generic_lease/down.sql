@@ -0,0 +1,2 @@
+ALTER TABLE lease RENAME COLUMN name TO task_id;
+ALTER TABLE lease RENAME TO task_lease;
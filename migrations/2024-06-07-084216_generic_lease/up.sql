@@ -0,0 +1,2 @@
+ALTER TABLE task_lease RENAME TO lease;
+ALTER TABLE lease RENAME COLUMN task_id TO name;
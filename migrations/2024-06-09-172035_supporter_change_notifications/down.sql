@@ -0,0 +1,7 @@
+DROP TRIGGER user_badge_notify_truncate ON user_badge;
+DROP TRIGGER user_badge_notify ON user_badge;
+DROP TRIGGER supporter_credits_opt_in_notify_truncate ON supporter_credits_opt_in;
+DROP TRIGGER supporter_credits_opt_in_notify ON supporter_credits_opt_in;
+DROP TRIGGER aiode_supporter_notify_truncate ON aiode_supporter;
+DROP TRIGGER aiode_supporter_notify ON aiode_supporter;
+DROP FUNCTION notify_supporter_change();
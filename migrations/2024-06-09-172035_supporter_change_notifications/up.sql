@@ -0,0 +1,35 @@
+-- notifies listeners on the supporter_changes channel of changes to supporter tables, including manual edits
+CREATE FUNCTION notify_supporter_change() RETURNS TRIGGER AS $$
+DECLARE
+    changed_user_id TEXT;
+BEGIN
+    IF TG_LEVEL = 'ROW' AND TG_OP = 'DELETE' THEN
+        changed_user_id := OLD.user_id::TEXT;
+    ELSIF TG_LEVEL = 'ROW' THEN
+        changed_user_id := NEW.user_id::TEXT;
+    END IF;
+
+    -- changed_user_id is NULL if the table has been truncated
+    PERFORM pg_notify('supporter_changes', json_build_object(
+        'table', TG_TABLE_NAME,
+        'operation', TG_OP,
+        'user_id', changed_user_id
+    )::TEXT);
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER aiode_supporter_notify AFTER INSERT OR UPDATE OR DELETE ON aiode_supporter
+    FOR EACH ROW EXECUTE FUNCTION notify_supporter_change();
+CREATE TRIGGER aiode_supporter_notify_truncate AFTER TRUNCATE ON aiode_supporter
+    FOR EACH STATEMENT EXECUTE FUNCTION notify_supporter_change();
+
+CREATE TRIGGER supporter_credits_opt_in_notify AFTER INSERT OR UPDATE OR DELETE ON supporter_credits_opt_in
+    FOR EACH ROW EXECUTE FUNCTION notify_supporter_change();
+CREATE TRIGGER supporter_credits_opt_in_notify_truncate AFTER TRUNCATE ON supporter_credits_opt_in
+    FOR EACH STATEMENT EXECUTE FUNCTION notify_supporter_change();
+
+CREATE TRIGGER user_badge_notify AFTER INSERT OR UPDATE OR DELETE ON user_badge
+    FOR EACH ROW EXECUTE FUNCTION notify_supporter_change();
+CREATE TRIGGER user_badge_notify_truncate AFTER TRUNCATE ON user_badge
+    FOR EACH STATEMENT EXECUTE FUNCTION notify_supporter_change();
@@ -0,0 +1 @@
+ALTER TABLE user_badge DROP COLUMN revocation_deadline;
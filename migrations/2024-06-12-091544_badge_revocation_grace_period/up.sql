@@ -0,0 +1,2 @@
+-- set when the user lost all roles granting the badge, the badge is removed after this point unless a role is re-granted
+ALTER TABLE user_badge ADD COLUMN revocation_deadline TIMESTAMP WITH TIME ZONE;
@@ -0,0 +1,3 @@
+DROP INDEX payment_event_pending_idx;
+ALTER TABLE payment_event DROP COLUMN pending_grant_days;
+ALTER TABLE payment_event DROP COLUMN pending_action;
@@ -0,0 +1,5 @@
+-- set for events of payers that have not linked their account yet, applied once the account is linked
+ALTER TABLE payment_event ADD COLUMN pending_action VARCHAR(16);
+ALTER TABLE payment_event ADD COLUMN pending_grant_days INTEGER;
+
+CREATE INDEX payment_event_pending_idx ON payment_event (provider, external_id) WHERE pending_action IS NOT NULL;
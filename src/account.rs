@@ -0,0 +1,304 @@
+use chrono::{DateTime, Utc};
+use diesel::{ExpressionMethods, OptionalExtension, QueryDsl};
+use diesel_async::{scoped_futures::ScopedFutureExt, AsyncConnection, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use warp::{reject::Rejection, reply::Reply};
+
+use crate::{
+    acquire_db_connection,
+    error::{Error, ErrorResponse},
+    model::{LinkedAccount, NewAccountLinkCode, NewLinkedAccount},
+    payment::{apply_pending_payment_events, PROVIDER_KOFI, PROVIDER_PATREON, PROVIDER_STRIPE},
+    schema::{account_link_code, linked_account},
+    snowflake::Snowflake,
+    util::{generate_code, hash_code},
+};
+
+pub const PROVIDER_AIODE: &str = "aiode";
+pub const PROVIDER_GITHUB: &str = "github";
+
+/// Providers users can link their discord account with.
+pub const LINKABLE_PROVIDERS: [&str; 5] = [
+    PROVIDER_AIODE,
+    PROVIDER_GITHUB,
+    PROVIDER_KOFI,
+    PROVIDER_PATREON,
+    PROVIDER_STRIPE,
+];
+
+const LINK_CODE_LENGTH: usize = 8;
+pub const LINK_CODE_VALIDITY_MINUTES: i64 = 15;
+
+#[derive(Deserialize, ToSchema)]
+pub struct VerifyLinkedAccountRequest {
+    /// The link code the user has been given by the `/link` command.
+    pub code: String,
+    pub external_id: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct LinkedAccountResponse {
+    pub user_id: String,
+    pub provider: String,
+    pub external_id: String,
+    pub verified: bool,
+    pub linked_since: DateTime<Utc>,
+}
+
+impl From<LinkedAccount> for LinkedAccountResponse {
+    fn from(linked_account: LinkedAccount) -> Self {
+        Self {
+            user_id: linked_account.user_id.to_string(),
+            provider: linked_account.provider,
+            external_id: linked_account.external_id,
+            verified: linked_account.verified,
+            linked_since: linked_account.creation_timestamp,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct LinkedAccountsResponse {
+    pub linked_accounts: Vec<LinkedAccountResponse>,
+}
+
+/// Generates a one-time code the user has to submit to the external side in order to prove they own
+/// the discord account. Only the hash of the code is persisted.
+pub async fn create_link_code(user_id: u64, provider: &str) -> Result<String, Error> {
+    if !LINKABLE_PROVIDERS.contains(&provider) {
+        return Err(Error::InvalidPayloadError(format!(
+            "Unknown provider {provider}"
+        )));
+    }
+
+    let code = generate_code(LINK_CODE_LENGTH);
+
+    let mut connection = acquire_db_connection().await?;
+    diesel::insert_into(account_link_code::table)
+        .values(NewAccountLinkCode {
+            code_hash: hash_code(&code),
+            user_id: user_id.into(),
+            provider: provider.to_string(),
+            expiration_timestamp: Utc::now()
+                + chrono::Duration::minutes(LINK_CODE_VALIDITY_MINUTES),
+        })
+        .execute(&mut connection)
+        .await?;
+
+    Ok(code)
+}
+
+/// Called by the external side to confirm a link code submitted by the user, consuming the code and
+/// creating a verified link between the discord user and the external identity. Requires the admin
+/// token, as the link decides who receives the supporter status granted by payment webhooks.
+#[utoipa::path(
+    post,
+    path = "/linked-accounts/verify",
+    tag = "accounts",
+    request_body = VerifyLinkedAccountRequest,
+    security(("admin_token" = [])),
+    responses(
+        (status = 200, description = "The verified link", body = LinkedAccountResponse),
+        (status = 400, description = "Invalid or expired code, or malformed request", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 409, description = "The external identity is linked to another discord user", body = ErrorResponse),
+        (status = 413, description = "Request body too large", body = ErrorResponse),
+        (status = 500, description = "Code could not be verified", body = ErrorResponse),
+    ),
+)]
+pub async fn verify_linked_account_handler(
+    request: VerifyLinkedAccountRequest,
+) -> Result<impl Reply, Rejection> {
+    let mut connection = acquire_db_connection().await?;
+
+    let linked_account = connection
+        .transaction::<_, Error, _>(|connection| {
+            async move {
+                let (user_id, provider) = diesel::delete(account_link_code::table)
+                    .filter(account_link_code::code_hash.eq(hash_code(&request.code)))
+                    .filter(account_link_code::expiration_timestamp.gt(Utc::now()))
+                    .returning((account_link_code::user_id, account_link_code::provider))
+                    .get_result::<(Snowflake, String)>(connection)
+                    .await
+                    .optional()?
+                    .ok_or_else(|| {
+                        Error::InvalidPayloadError(String::from("Invalid or expired code"))
+                    })?;
+                let external_id = normalise_external_id(&provider, &request.external_id);
+
+                let existing = linked_account::table
+                    .filter(linked_account::provider.eq(&provider))
+                    .filter(linked_account::external_id.eq(&external_id))
+                    .for_update()
+                    .get_result::<LinkedAccount>(connection)
+                    .await
+                    .optional()?;
+                match existing {
+                    Some(existing) if existing.user_id != user_id => {
+                        // rolls back, so the code stays valid
+                        return Err(Error::ConflictError(format!(
+                            "The {provider} account is already linked to another discord user"
+                        )));
+                    }
+                    Some(existing) => return Ok(existing),
+                    None => {}
+                }
+
+                diesel::insert_into(linked_account::table)
+                    .values(NewLinkedAccount {
+                        provider,
+                        external_id,
+                        user_id,
+                        verified: true,
+                    })
+                    .on_conflict_do_nothing()
+                    .get_result::<LinkedAccount>(connection)
+                    .await
+                    .optional()?
+                    // linked by a concurrent request
+                    .ok_or_else(|| {
+                        Error::ConflictError(String::from("The account has just been linked"))
+                    })
+            }
+            .scope_boxed()
+        })
+        .await?;
+
+    tracing::info!(
+        "User {} has linked {} account {}",
+        linked_account.user_id,
+        linked_account.provider,
+        linked_account.external_id
+    );
+
+    // payments received before the account was linked
+    if let Err(e) = apply_pending_payment_events(
+        &linked_account.provider,
+        &linked_account.external_id,
+        linked_account.user_id.get(),
+    )
+    .await
+    {
+        tracing::error!(
+            "Failed to apply pending {} events of account {}: {e}",
+            linked_account.provider,
+            linked_account.external_id
+        );
+    }
+
+    Ok(warp::reply::json(&LinkedAccountResponse::from(
+        linked_account,
+    )))
+}
+
+#[utoipa::path(
+    get,
+    path = "/linked-accounts/discord/{user_id}",
+    tag = "accounts",
+    params(("user_id" = u64, Path, description = "Discord user id")),
+    security(("admin_token" = [])),
+    responses(
+        (status = 200, description = "Accounts linked by the user, oldest first", body = LinkedAccountsResponse),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 500, description = "Linked accounts could not be loaded", body = ErrorResponse),
+    ),
+)]
+pub async fn get_linked_accounts_by_user_handler(user_id: u64) -> Result<impl Reply, Rejection> {
+    let mut connection = acquire_db_connection().await?;
+
+    let linked_accounts = linked_account::table
+        .filter(linked_account::user_id.eq(Snowflake::from(user_id)))
+        .order(linked_account::creation_timestamp)
+        .load::<LinkedAccount>(&mut connection)
+        .await
+        .map_err(Error::from)?;
+
+    Ok(warp::reply::json(&LinkedAccountsResponse {
+        linked_accounts: linked_accounts
+            .into_iter()
+            .map(LinkedAccountResponse::from)
+            .collect(),
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/linked-accounts/{provider}/{external_id}",
+    tag = "accounts",
+    params(
+        ("provider" = String, Path, description = "Provider of the external identity, e.g. `github`"),
+        ("external_id" = String, Path, description = "Id of the user on the provider"),
+    ),
+    security(("admin_token" = [])),
+    responses(
+        (status = 200, description = "The discord user linked to the external identity", body = LinkedAccountResponse),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 404, description = "No discord user is linked to the external identity", body = ErrorResponse),
+        (status = 500, description = "Linked account could not be loaded", body = ErrorResponse),
+    ),
+)]
+pub async fn get_linked_account_handler(
+    provider: String,
+    external_id: String,
+) -> Result<impl Reply, Rejection> {
+    let mut connection = acquire_db_connection().await?;
+
+    let linked_account = linked_account::table
+        .filter(linked_account::provider.eq(&provider))
+        .filter(linked_account::external_id.eq(normalise_external_id(&provider, &external_id)))
+        .get_result::<LinkedAccount>(&mut connection)
+        .await
+        .optional()
+        .map_err(Error::from)?
+        .ok_or_else(warp::reject::not_found)?;
+
+    Ok(warp::reply::json(&LinkedAccountResponse::from(
+        linked_account,
+    )))
+}
+
+/// Normalises the id of an external identity as it is stored in the linked_account table. Ko-fi
+/// identifies payers by email, which is compared case-insensitively.
+pub fn normalise_external_id(provider: &str, external_id: &str) -> String {
+    if provider == PROVIDER_KOFI {
+        external_id.to_lowercase()
+    } else {
+        external_id.to_string()
+    }
+}
+
+/// Resolves the discord user linked to the given external identity, only considering verified links.
+pub async fn find_linked_user(provider: &str, external_id: &str) -> Result<Option<u64>, Error> {
+    let mut connection = acquire_db_connection().await?;
+
+    let user_id = linked_account::table
+        .filter(linked_account::provider.eq(provider))
+        .filter(linked_account::external_id.eq(external_id))
+        .filter(linked_account::verified.eq(true))
+        .select(linked_account::user_id)
+        .get_result::<Snowflake>(&mut connection)
+        .await
+        .optional()?;
+
+    Ok(user_id.map(Snowflake::get))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kofi_emails_are_lowercased() {
+        assert_eq!(
+            normalise_external_id(PROVIDER_KOFI, "Payer@Example.com"),
+            "payer@example.com"
+        );
+    }
+
+    #[test]
+    fn other_external_ids_are_kept() {
+        assert_eq!(normalise_external_id(PROVIDER_STRIPE, "cus_AbC"), "cus_AbC");
+        assert_eq!(normalise_external_id(PROVIDER_GITHUB, "Octocat"), "Octocat");
+    }
+}
@@ -1,31 +1,1345 @@
-use bigdecimal::BigDecimal;
 use chrono::{DateTime, Utc};
 use diesel::{ExpressionMethods, OptionalExtension, QueryDsl};
-use diesel_async::RunQueryDsl;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
 use serde::Serialize;
+use serenity::all::{GuildId, GuildMemberUpdateEvent, Member, RoleId, Timestamp, UserId};
+use utoipa::ToSchema;
 use warp::{reject::Rejection, reply::Reply};
 
-use crate::{acquire_db_connection, error::Error, model::AiodeSupporter, schema::aiode_supporter};
+use crate::{
+    acquire_db_connection,
+    context::app_context,
+    discord::DiscordApi,
+    error::{Error, ErrorResponse},
+    gift::find_active_gift,
+    model::{AiodeSupporter, NewAiodeSupporter, NewTimeLimitedAiodeSupporter},
+    schema::aiode_supporter,
+    snowflake::Snowflake,
+    store::{PgSupporterStore, SupporterStore},
+    supporter_cache::{self, CachedSupporter, SupporterLookup},
+    AIODE_BOOSTERS_ARE_SUPPORTERS, AIODE_SUPPORTER_ROLE_ID, AIODE_SUPPORT_GUILD_ID,
+    SUPPORTER_REVOCATION_GRACE_PERIOD_HOURS, SUPPORTER_TIERS,
+};
 
-#[derive(Serialize)]
+/// The user holds the supporter role on the support guild.
+pub const SUPPORTER_SOURCE_ROLE: &str = "role";
+/// The user boosts the support guild, only used if `GLYPH_AIODE_BOOSTERS_ARE_SUPPORTERS` is enabled.
+pub const SUPPORTER_SOURCE_BOOSTER: &str = "booster";
+/// Supporter status has been granted by a payment provider webhook.
+pub const SUPPORTER_SOURCE_PAYMENT: &str = "payment";
+/// Supporter status has been granted by redeeming a voucher.
+pub const SUPPORTER_SOURCE_VOUCHER: &str = "voucher";
+/// Supporter status has been gifted by another supporter or an admin.
+pub const SUPPORTER_SOURCE_GIFT: &str = "gift";
+/// Sources tracked via the support guild, only supporters of these sources are removed when losing
+/// the supporter role or ending a boost.
+pub const GUILD_SUPPORTER_SOURCES: [&str; 2] = [SUPPORTER_SOURCE_ROLE, SUPPORTER_SOURCE_BOOSTER];
+pub const ALL_SUPPORTER_SOURCES: [&str; 5] = [
+    SUPPORTER_SOURCE_ROLE,
+    SUPPORTER_SOURCE_BOOSTER,
+    SUPPORTER_SOURCE_PAYMENT,
+    SUPPORTER_SOURCE_VOUCHER,
+    SUPPORTER_SOURCE_GIFT,
+];
+
+/// Tier of supporters whose entry does not specify one, e.g. supporters tracked via the supporter role.
+pub const DEFAULT_SUPPORTER_TIER: &str = "default";
+
+#[derive(Serialize, ToSchema)]
 pub struct CheckIsAiodeSupporterResponse {
     pub is_supporter: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub supporter_since: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub supporter_until: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tier: Option<String>,
+    /// How supporter status has been granted: `role`, `booster`, `payment`, `voucher` or `gift`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pending_revocation_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gift: Option<SupporterGiftResponse>,
+    /// True if the status has been served from the supporter cache while it may not reflect the latest
+    /// changes, e.g. during a database outage.
+    pub stale: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SupporterGiftResponse {
+    /// `None` if the gift has been granted by an admin.
+    pub gifted_by: Option<String>,
+    pub tier: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// How supporters are tracked on the support guild, see `GLYPH_AIODE_SUPPORT_GUILD_ID`,
+/// `GLYPH_AIODE_SUPPORTER_ROLE_ID`, `GLYPH_AIODE_BOOSTERS_ARE_SUPPORTERS` and
+/// `GLYPH_SUPPORTER_REVOCATION_GRACE_PERIOD_HOURS`.
+#[derive(Clone, Debug)]
+pub struct SupportGuildConfig {
+    pub guild_id: Option<GuildId>,
+    pub supporter_role_id: Option<RoleId>,
+    pub boosters_are_supporters: bool,
+    pub revocation_grace_period: chrono::Duration,
+}
+
+impl SupportGuildConfig {
+    pub fn from_env() -> Self {
+        Self {
+            guild_id: AIODE_SUPPORT_GUILD_ID.map(GuildId::new),
+            supporter_role_id: AIODE_SUPPORTER_ROLE_ID.map(RoleId::new),
+            boosters_are_supporters: *AIODE_BOOSTERS_ARE_SUPPORTERS,
+            revocation_grace_period: chrono::Duration::hours(
+                (*SUPPORTER_REVOCATION_GRACE_PERIOD_HOURS).into(),
+            ),
+        }
+    }
+
+    /// Returns the source of the member's supporter status on the support guild, if any. The
+    /// supporter role takes precedence over boosting.
+    pub fn supporter_source(
+        &self,
+        roles: &[RoleId],
+        premium_since: Option<Timestamp>,
+    ) -> Option<&'static str> {
+        if self
+            .supporter_role_id
+            .is_some_and(|role_id| roles.contains(&role_id))
+        {
+            Some(SUPPORTER_SOURCE_ROLE)
+        } else if self.boosters_are_supporters && premium_since.is_some() {
+            Some(SUPPORTER_SOURCE_BOOSTER)
+        } else {
+            None
+        }
+    }
+}
+
+/// Returns the source of the member's supporter status on the support guild, if any.
+pub fn supporter_source(
+    roles: &[RoleId],
+    premium_since: Option<Timestamp>,
+) -> Option<&'static str> {
+    SupportGuildConfig::from_env().supporter_source(roles, premium_since)
 }
 
+/// Serves the supporter status from the supporter cache, only querying the database if the cache has
+/// not been loaded yet.
+#[utoipa::path(
+    get,
+    path = "/is-aiode-supporter/{user_id}",
+    tag = "supporters",
+    params(("user_id" = u64, Path, description = "Discord user id")),
+    responses(
+        (status = 200, description = "Supporter status of the user", body = CheckIsAiodeSupporterResponse),
+        (status = 500, description = "Supporter status could not be loaded", body = ErrorResponse),
+    ),
+)]
 pub async fn check_is_aiode_supporter_handler(user_id: u64) -> Result<impl Reply, Rejection> {
+    let (supporter, gift, stale) = match supporter_cache::lookup_supporter(user_id) {
+        Some(SupporterLookup { supporter, stale }) => match supporter {
+            Some(CachedSupporter { supporter, gift }) => (Some(supporter), gift, stale),
+            None => (None, None, stale),
+        },
+        None => {
+            let supporter = find_aiode_supporter(user_id).await?;
+            let gift = if supporter
+                .as_ref()
+                .is_some_and(|s| s.source == SUPPORTER_SOURCE_GIFT)
+            {
+                find_active_gift(user_id).await?
+            } else {
+                None
+            };
+            (supporter, gift, false)
+        }
+    };
+
+    let gift = gift.map(|gift| SupporterGiftResponse {
+        gifted_by: gift.gifter_user_id.map(|gifter| gifter.to_string()),
+        tier: gift.tier,
+        expires_at: gift.expiration_timestamp,
+    });
+
+    Ok(warp::reply::json(&CheckIsAiodeSupporterResponse {
+        is_supporter: supporter.is_some(),
+        supporter_since: supporter.as_ref().map(|s| s.creation_timestamp),
+        supporter_until: supporter.as_ref().and_then(|s| s.expiration_timestamp),
+        tier: supporter.as_ref().and_then(|s| s.tier.clone()),
+        source: supporter.as_ref().map(|s| s.source.clone()),
+        pending_revocation_at: supporter.and_then(|s| s.revocation_deadline),
+        gift,
+        stale,
+    }))
+}
+
+pub async fn find_aiode_supporter(user_id: u64) -> Result<Option<AiodeSupporter>, Error> {
     let mut connection = acquire_db_connection().await?;
 
     let supporter = aiode_supporter::table
-        .filter(aiode_supporter::user_id.eq::<BigDecimal>(user_id.into()))
+        .filter(aiode_supporter::user_id.eq(Snowflake::from(user_id)))
         .get_result::<AiodeSupporter>(&mut connection)
         .await
-        .optional()
-        .map_err(Error::from)?;
+        .optional()?;
 
-    Ok(warp::reply::json(&CheckIsAiodeSupporterResponse {
-        is_supporter: supporter.is_some(),
-        supporter_since: supporter.map(|s| s.creation_timestamp),
-    }))
+    Ok(supporter)
+}
+
+/// Adds the user to the aiode_supporter table and assigns the supporter role on the support guild.
+/// Replaces any time limit of an existing entry, making it permanent.
+///
+/// Used for payment provider webhooks, the entry is recorded with [`SUPPORTER_SOURCE_PAYMENT`].
+///
+/// Failing to assign the role (e.g. because the user is not a member of the support guild) is only
+/// logged, the aiode_supporter entry is what grants the perks.
+pub async fn grant_aiode_supporter(user_id: u64, reason: &str) -> Result<(), Error> {
+    let mut connection = acquire_db_connection().await?;
+    diesel::insert_into(aiode_supporter::table)
+        .values(NewAiodeSupporter {
+            user_id: user_id.into(),
+            source: SUPPORTER_SOURCE_PAYMENT.to_string(),
+        })
+        .on_conflict(aiode_supporter::user_id)
+        .do_update()
+        .set((
+            aiode_supporter::expiration_timestamp.eq(None::<DateTime<Utc>>),
+            aiode_supporter::source.eq(SUPPORTER_SOURCE_PAYMENT),
+            aiode_supporter::revocation_deadline.eq(None::<DateTime<Utc>>),
+        ))
+        .execute(&mut connection)
+        .await?;
+
+    tracing::info!(
+        "User {} has been granted aiode supporter status: {reason}",
+        user_id
+    );
+
+    drop(connection);
+    assign_aiode_supporter_role(user_id, reason).await;
+    Ok(())
+}
+
+/// Grants time limited supporter status of the given tier to the user, extending the time limit of an
+/// existing entry, see [`time_limited_status`]. Permanent entries are left untouched unless their
+/// revocation is pending.
+///
+/// Returns the new expiration timestamp, or `None` if the user already is a permanent supporter.
+pub async fn grant_time_limited_aiode_supporter(
+    user_id: u64,
+    tier: &str,
+    duration: chrono::Duration,
+    source: &str,
+    reason: &str,
+) -> Result<Option<DateTime<Utc>>, Error> {
+    let expiration_timestamp = PgSupporterStore
+        .grant_time_limited_supporter(user_id.into(), tier, duration, source)
+        .await?;
+
+    let Some(expiration_timestamp) = expiration_timestamp else {
+        tracing::info!("User {user_id} already is a permanent aiode supporter");
+        return Ok(None);
+    };
+
+    tracing::info!(
+        "User {} has been granted {tier} aiode supporter status until {expiration_timestamp}: {reason}",
+        user_id
+    );
+
+    assign_aiode_supporter_role(user_id, reason).await;
+    Ok(Some(expiration_timestamp))
+}
+
+/// The aiode_supporter entry of a user after being granted time limited supporter status.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TimeLimitedStatus {
+    pub tier: String,
+    pub expiration_timestamp: DateTime<Utc>,
+    pub source: String,
+}
+
+/// Returns the entry of the user after being granted time limited supporter status on top of the
+/// existing entry, or `None` if the user already is a permanent supporter. An entry pending revocation
+/// is replaced rather than extended, so that the grant outlives the revocation. Extending an entry keeps
+/// its source and the higher of both tiers, see `GLYPH_SUPPORTER_TIERS`.
+pub fn time_limited_status(
+    existing: Option<&AiodeSupporter>,
+    tier: &str,
+    duration: chrono::Duration,
+    source: &str,
+    now: DateTime<Utc>,
+) -> Option<TimeLimitedStatus> {
+    match existing {
+        Some(AiodeSupporter {
+            revocation_deadline: Some(_),
+            ..
+        })
+        | None => Some(TimeLimitedStatus {
+            tier: tier.to_string(),
+            expiration_timestamp: now + duration,
+            source: source.to_string(),
+        }),
+        Some(AiodeSupporter {
+            expiration_timestamp: None,
+            ..
+        }) => None,
+        Some(
+            existing @ AiodeSupporter {
+                expiration_timestamp: Some(expiration_timestamp),
+                ..
+            },
+        ) => Some(TimeLimitedStatus {
+            tier: higher_tier(
+                &SUPPORTER_TIERS,
+                existing.tier.as_deref().unwrap_or(DEFAULT_SUPPORTER_TIER),
+                tier,
+            )
+            .to_string(),
+            expiration_timestamp: (*expiration_timestamp).max(now) + duration,
+            source: existing.source.clone(),
+        }),
+    }
+}
+
+/// Returns the granted tier if it ranks above the existing one in `tiers`, ordered from lowest to
+/// highest, otherwise the existing tier. Tiers that are not listed rank below listed ones.
+fn higher_tier<'a>(tiers: &[String], existing: &'a str, granted: &'a str) -> &'a str {
+    let rank = |tier: &str| tiers.iter().position(|t| t == tier);
+    if rank(granted) > rank(existing) {
+        granted
+    } else {
+        existing
+    }
+}
+
+/// Writes the aiode_supporter entry of [`grant_time_limited_aiode_supporter`] on the given connection,
+/// e.g. within a transaction, without assigning the supporter role.
+pub async fn upsert_time_limited_supporter(
+    connection: &mut AsyncPgConnection,
+    user_id: u64,
+    tier: &str,
+    duration: chrono::Duration,
+    source: &str,
+) -> Result<Option<DateTime<Utc>>, Error> {
+    let existing = aiode_supporter::table
+        .filter(aiode_supporter::user_id.eq(Snowflake::from(user_id)))
+        .for_update()
+        .get_result::<AiodeSupporter>(connection)
+        .await
+        .optional()?;
+
+    let Some(status) = time_limited_status(existing.as_ref(), tier, duration, source, Utc::now())
+    else {
+        return Ok(None);
+    };
+
+    diesel::insert_into(aiode_supporter::table)
+        .values(NewTimeLimitedAiodeSupporter {
+            user_id: user_id.into(),
+            tier: Some(status.tier.clone()),
+            expiration_timestamp: status.expiration_timestamp,
+            source: status.source.clone(),
+        })
+        .on_conflict(aiode_supporter::user_id)
+        .do_update()
+        .set((
+            aiode_supporter::tier.eq(&status.tier),
+            aiode_supporter::expiration_timestamp.eq(status.expiration_timestamp),
+            aiode_supporter::source.eq(&status.source),
+            aiode_supporter::revocation_deadline.eq(None::<DateTime<Utc>>),
+        ))
+        .execute(connection)
+        .await?;
+
+    Ok(Some(status.expiration_timestamp))
+}
+
+/// Assigns the supporter role on the support guild, failures are only logged.
+pub async fn assign_aiode_supporter_role(user_id: u64, reason: &str) {
+    assign_supporter_role(
+        &*app_context().http,
+        &SupportGuildConfig::from_env(),
+        user_id.into(),
+        reason,
+    )
+    .await;
+}
+
+/// Removes the user from the aiode_supporter table and removes the supporter role on the support guild
+/// once time limited supporter status has expired. Supporters whose status has been renewed since
+/// `now` are kept.
+pub async fn expire_aiode_supporter(user_id: u64, now: DateTime<Utc>) -> Result<(), Error> {
+    expire_supporter(
+        &*app_context().http,
+        &PgSupporterStore,
+        &SupportGuildConfig::from_env(),
+        user_id.into(),
+        now,
+    )
+    .await
+}
+
+async fn expire_supporter(
+    discord: &dyn DiscordApi,
+    store: &dyn SupporterStore,
+    config: &SupportGuildConfig,
+    user_id: UserId,
+    now: DateTime<Utc>,
+) -> Result<(), Error> {
+    if store.delete_expired_supporter(user_id.into(), now).await? {
+        let reason = "Supporter status expired";
+        tracing::info!("User {user_id} has been removed from the aiode_supporter table: {reason}");
+        remove_supporter_role(discord, config, user_id, reason).await;
+    }
+
+    Ok(())
+}
+
+/// Revokes supporter status granted by a payment provider webhook. If a grace period is configured,
+/// the supporter is marked as pending revocation like supporters losing the supporter role and keeps
+/// the role until [`finalise_supporter_revocations`] runs, a renewed payment cancels the revocation.
+pub async fn revoke_payment_supporter(user_id: u64, reason: &str) -> Result<(), Error> {
+    revoke_payment(
+        &*app_context().http,
+        &PgSupporterStore,
+        &SupportGuildConfig::from_env(),
+        user_id.into(),
+        reason,
+    )
+    .await
+}
+
+async fn revoke_payment(
+    discord: &dyn DiscordApi,
+    store: &dyn SupporterStore,
+    config: &SupportGuildConfig,
+    user_id: UserId,
+    reason: &str,
+) -> Result<(), Error> {
+    if config.revocation_grace_period > chrono::Duration::zero() {
+        let revocation_deadline = Utc::now() + config.revocation_grace_period;
+        if store
+            .schedule_revocation(
+                user_id.into(),
+                &[SUPPORTER_SOURCE_PAYMENT],
+                revocation_deadline,
+            )
+            .await?
+        {
+            tracing::info!(
+                "Supporter status of user {user_id} will be revoked at {revocation_deadline}: {reason}"
+            );
+        }
+
+        return Ok(());
+    }
+
+    revoke_supporter(
+        discord,
+        store,
+        config,
+        user_id,
+        &[SUPPORTER_SOURCE_PAYMENT],
+        reason,
+    )
+    .await
+}
+
+/// Removes the supporter if its source is one of `sources` and, if it has been removed, the supporter
+/// role. Supporters of other sources keep the role they are entitled to.
+async fn revoke_supporter(
+    discord: &dyn DiscordApi,
+    store: &dyn SupporterStore,
+    config: &SupportGuildConfig,
+    user_id: UserId,
+    sources: &[&str],
+    reason: &str,
+) -> Result<(), Error> {
+    if store.delete_supporter(user_id.into(), sources).await? {
+        tracing::info!("User {user_id} has been removed from the aiode_supporter table: {reason}");
+        remove_supporter_role(discord, config, user_id, reason).await;
+    }
+
+    Ok(())
+}
+
+/// Revokes supporter status of users whose revocation grace period has passed. Supporters tracked via
+/// the support guild keep their status if they have regained the supporter role or boost in the
+/// meantime, supporters whose payment has been revoked lose the supporter role.
+pub async fn finalise_supporter_revocations() -> Result<(), Error> {
+    finalise_revocations(
+        &*app_context().http,
+        &PgSupporterStore,
+        &SupportGuildConfig::from_env(),
+        Utc::now(),
+    )
+    .await
+}
+
+async fn finalise_revocations(
+    discord: &dyn DiscordApi,
+    store: &dyn SupporterStore,
+    config: &SupportGuildConfig,
+    now: DateTime<Utc>,
+) -> Result<(), Error> {
+    let mut sources = GUILD_SUPPORTER_SOURCES.to_vec();
+    sources.push(SUPPORTER_SOURCE_PAYMENT);
+
+    for supporter in store.due_revocations(&sources, now).await? {
+        let user_id = match UserId::try_from(supporter.user_id) {
+            Ok(user_id) => user_id,
+            Err(e) => {
+                tracing::warn!("Skipping pending supporter revocation: {e}");
+                continue;
+            }
+        };
+        if supporter.source == SUPPORTER_SOURCE_PAYMENT {
+            if store
+                .delete_revoked_supporter(supporter.user_id, now)
+                .await?
+            {
+                tracing::info!("User {user_id} has been removed from the aiode_supporter table after the revocation grace period");
+                remove_supporter_role(discord, config, user_id, "Payment revoked").await;
+            }
+            continue;
+        }
+
+        let member = match config.guild_id {
+            Some(guild_id) => discord.get_member(guild_id, user_id).await?,
+            None => None,
+        };
+        if member.is_some_and(|member| {
+            config
+                .supporter_source(&member.roles, member.premium_since)
+                .is_some()
+        }) {
+            if store.cancel_revocations(&[supporter.user_id]).await? > 0 {
+                tracing::info!("Cancelled pending supporter revocation for user {user_id}");
+            }
+        } else if store
+            .delete_revoked_supporter(supporter.user_id, now)
+            .await?
+        {
+            tracing::info!("User {user_id} has been removed from the aiode_supporter table after the revocation grace period");
+        }
+    }
+
+    Ok(())
+}
+
+async fn assign_supporter_role(
+    discord: &dyn DiscordApi,
+    config: &SupportGuildConfig,
+    user_id: UserId,
+    reason: &str,
+) {
+    if let (Some(guild_id), Some(role_id)) = (config.guild_id, config.supporter_role_id) {
+        if let Err(e) = discord
+            .add_member_role(guild_id, user_id, role_id, reason)
+            .await
+        {
+            tracing::warn!("Failed to assign supporter role to user {user_id}: {e}");
+        }
+    }
+}
+
+async fn remove_supporter_role(
+    discord: &dyn DiscordApi,
+    config: &SupportGuildConfig,
+    user_id: UserId,
+    reason: &str,
+) {
+    if let (Some(guild_id), Some(role_id)) = (config.guild_id, config.supporter_role_id) {
+        if let Err(e) = discord
+            .remove_member_role(guild_id, user_id, role_id, reason)
+            .await
+        {
+            tracing::warn!("Failed to remove supporter role from user {user_id}: {e}");
+        }
+    }
+}
+
+/// Updates the aiode_supporter table after a member of the support guild changed their roles or
+/// started or stopped boosting.
+pub async fn handle_member_update(
+    old: Option<&Member>,
+    new: Option<&Member>,
+    event: &GuildMemberUpdateEvent,
+) -> Result<(), Error> {
+    apply_member_update(
+        &PgSupporterStore,
+        &SupportGuildConfig::from_env(),
+        old,
+        new,
+        event,
+    )
+    .await
+}
+
+async fn apply_member_update(
+    store: &dyn SupporterStore,
+    config: &SupportGuildConfig,
+    old: Option<&Member>,
+    new: Option<&Member>,
+    event: &GuildMemberUpdateEvent,
+) -> Result<(), Error> {
+    let user_id = event.user.id;
+    let new_source = config.supporter_source(&event.roles, event.premium_since);
+
+    let old_source = match (old, new) {
+        (Some(old), Some(_)) => config.supporter_source(&old.roles, old.premium_since),
+        // the previous state is unknown, make sure the current state is persisted
+        _ => {
+            if let Some(source) = new_source {
+                add_supporter(store, user_id, source).await?;
+            } else {
+                remove_supporter(store, config, user_id).await?;
+            }
+            return Ok(());
+        }
+    };
+
+    match (old_source, new_source) {
+        (Some(_), None) => remove_supporter(store, config, user_id).await?,
+        (None, Some(source)) => add_supporter(store, user_id, source).await?,
+        (Some(old_source), Some(new_source)) if old_source != new_source => {
+            // e.g. the user lost the supporter role but still boosts the guild
+            store
+                .set_supporter_source(user_id.into(), new_source)
+                .await?;
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+async fn add_supporter(
+    store: &dyn SupporterStore,
+    user_id: UserId,
+    source: &str,
+) -> Result<(), Error> {
+    let res = store
+        .insert_supporters(&[NewAiodeSupporter {
+            user_id: user_id.into(),
+            source: source.to_string(),
+        }])
+        .await?;
+
+    if res > 0 {
+        tracing::info!(
+            "User {} has been added to the aiode_supporter table ({source})",
+            user_id
+        );
+    } else if store.cancel_revocations(&[user_id.into()]).await? > 0 {
+        tracing::info!(
+            "Cancelled pending supporter revocation for user {}",
+            user_id
+        );
+    }
+
+    Ok(())
+}
+
+/// Removes the user from the aiode_supporter table or, if a grace period is configured, marks the
+/// supporter as pending revocation, see [`crate::task::finalise_supporter_revocations`]. Supporters
+/// whose status has not been granted via the support guild are left untouched.
+async fn remove_supporter(
+    store: &dyn SupporterStore,
+    config: &SupportGuildConfig,
+    user_id: UserId,
+) -> Result<(), Error> {
+    if config.revocation_grace_period > chrono::Duration::zero() {
+        let revocation_deadline = Utc::now() + config.revocation_grace_period;
+        if store
+            .schedule_revocation(
+                user_id.into(),
+                &GUILD_SUPPORTER_SOURCES,
+                revocation_deadline,
+            )
+            .await?
+        {
+            tracing::info!(
+                "Supporter status of user {} will be revoked at {revocation_deadline}",
+                user_id
+            );
+        }
+
+        return Ok(());
+    }
+
+    if store
+        .delete_supporter(user_id.into(), &GUILD_SUPPORTER_SOURCES)
+        .await?
+    {
+        tracing::info!(
+            "User {} has been removed from the aiode_supporter table",
+            user_id
+        );
+    }
+
+    Ok(())
+}
+
+/// Reconciles the aiode_supporter table with the current members of the support guild, catching up
+/// on member updates that were missed while the bot was offline.
+pub async fn sync_supporters(
+    store: &dyn SupporterStore,
+    config: &SupportGuildConfig,
+    members: &[Member],
+) -> Result<(), Error> {
+    let mut supporters: Vec<NewAiodeSupporter> = Vec::new();
+    let mut boosters: Vec<Snowflake> = Vec::new();
+
+    for member in members {
+        let Some(source) = config.supporter_source(&member.roles, member.premium_since) else {
+            continue;
+        };
+
+        if source == SUPPORTER_SOURCE_BOOSTER {
+            boosters.push(member.user.id.into());
+        }
+        supporters.push(NewAiodeSupporter {
+            user_id: member.user.id.into(),
+            source: source.to_string(),
+        });
+    }
+
+    if !supporters.is_empty() {
+        let res = store.insert_supporters(&supporters).await?;
+        if res > 0 {
+            tracing::info!("Added {} supporters to the aiode_supporter table", res);
+        }
+
+        // cancel pending revocations of users that have regained supporter status
+        let supporter_ids = supporters
+            .iter()
+            .map(|supporter| supporter.user_id)
+            .collect::<Vec<_>>();
+        let res = store.cancel_revocations(&supporter_ids).await?;
+        if res > 0 {
+            tracing::info!("Cancelled {} pending supporter revocations", res);
+        }
+    }
+
+    if config.boosters_are_supporters {
+        // boosters are not tracked via the supporter role, so missed boost ends are reconciled here,
+        // honouring the grace period like losing the supporter role
+        if config.revocation_grace_period > chrono::Duration::zero() {
+            let revocation_deadline = Utc::now() + config.revocation_grace_period;
+            let res = store
+                .schedule_booster_revocations_except(&boosters, revocation_deadline)
+                .await?;
+            if res > 0 {
+                tracing::info!(
+                    "Supporter status of {} former boosters will be revoked at {revocation_deadline}",
+                    res
+                );
+            }
+        } else {
+            let res = store.delete_boosters_except(&boosters).await?;
+            if res > 0 {
+                tracing::info!(
+                    "Removed {} former boosters from the aiode_supporter table",
+                    res
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{discord::fake::*, store::fake::InMemorySupporterStore};
+
+    const SUPPORTER_ROLE: u64 = 10;
+    const OTHER_ROLE: u64 = 11;
+
+    fn config(revocation_grace_period_hours: i64) -> SupportGuildConfig {
+        SupportGuildConfig {
+            guild_id: Some(GuildId::new(1)),
+            supporter_role_id: Some(RoleId::new(SUPPORTER_ROLE)),
+            boosters_are_supporters: true,
+            revocation_grace_period: chrono::Duration::hours(revocation_grace_period_hours),
+        }
+    }
+
+    fn booster(user_id: u64) -> Member {
+        let mut member = member(user_id, &[]);
+        member.premium_since = Some(Timestamp::now());
+        member
+    }
+
+    fn supporter(user_id: u64, source: &str) -> AiodeSupporter {
+        AiodeSupporter {
+            user_id: user_id.into(),
+            creation_timestamp: Utc::now(),
+            tier: None,
+            expiration_timestamp: None,
+            source: source.to_string(),
+            revocation_deadline: None,
+        }
+    }
+
+    fn update_event(member: &Member) -> GuildMemberUpdateEvent {
+        serde_json::from_value(serde_json::json!({
+            "guild_id": "1",
+            "nick": null,
+            "joined_at": Timestamp::now(),
+            "roles": member.roles,
+            "user": member.user,
+            "premium_since": member.premium_since,
+        }))
+        .expect("valid guild member update event")
+    }
+
+    async fn update(
+        store: &InMemorySupporterStore,
+        config: &SupportGuildConfig,
+        old: Option<&Member>,
+        new: &Member,
+    ) {
+        apply_member_update(store, config, old, Some(new), &update_event(new))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn gaining_supporter_role_adds_supporter() {
+        let store = InMemorySupporterStore::default();
+        let old = member(2, &[OTHER_ROLE]);
+        let new = member(2, &[OTHER_ROLE, SUPPORTER_ROLE]);
+
+        update(&store, &config(0), Some(&old), &new).await;
+
+        assert_eq!(store.get(2).unwrap().source, SUPPORTER_SOURCE_ROLE);
+    }
+
+    #[tokio::test]
+    async fn unrelated_role_change_is_ignored() {
+        let store = InMemorySupporterStore::default();
+        let old = member(2, &[]);
+        let new = member(2, &[OTHER_ROLE]);
+
+        update(&store, &config(0), Some(&old), &new).await;
+
+        assert!(store.get(2).is_none());
+    }
+
+    #[tokio::test]
+    async fn losing_supporter_role_removes_supporter() {
+        let store = InMemorySupporterStore::with_supporters([supporter(2, SUPPORTER_SOURCE_ROLE)]);
+        let old = member(2, &[SUPPORTER_ROLE]);
+        let new = member(2, &[]);
+
+        update(&store, &config(0), Some(&old), &new).await;
+
+        assert!(store.get(2).is_none());
+    }
+
+    #[tokio::test]
+    async fn losing_supporter_role_schedules_revocation_during_grace_period() {
+        let store = InMemorySupporterStore::with_supporters([supporter(2, SUPPORTER_SOURCE_ROLE)]);
+        let old = member(2, &[SUPPORTER_ROLE]);
+        let new = member(2, &[]);
+
+        update(&store, &config(24), Some(&old), &new).await;
+
+        let revocation_deadline = store.get(2).unwrap().revocation_deadline.unwrap();
+        assert!(revocation_deadline > Utc::now() + chrono::Duration::hours(23));
+    }
+
+    #[tokio::test]
+    async fn regaining_supporter_role_cancels_revocation() {
+        let mut pending = supporter(2, SUPPORTER_SOURCE_ROLE);
+        pending.revocation_deadline = Some(Utc::now() + chrono::Duration::hours(1));
+        let store = InMemorySupporterStore::with_supporters([pending]);
+        let old = member(2, &[]);
+        let new = member(2, &[SUPPORTER_ROLE]);
+
+        update(&store, &config(24), Some(&old), &new).await;
+
+        assert!(store.get(2).unwrap().revocation_deadline.is_none());
+    }
+
+    #[tokio::test]
+    async fn losing_supporter_role_while_boosting_changes_source() {
+        let store = InMemorySupporterStore::with_supporters([supporter(2, SUPPORTER_SOURCE_ROLE)]);
+        let mut old = booster(2);
+        old.roles.push(RoleId::new(SUPPORTER_ROLE));
+        let new = booster(2);
+
+        update(&store, &config(0), Some(&old), &new).await;
+
+        assert_eq!(store.get(2).unwrap().source, SUPPORTER_SOURCE_BOOSTER);
+    }
+
+    #[tokio::test]
+    async fn uncached_member_with_supporter_role_is_added() {
+        let store = InMemorySupporterStore::default();
+        let new = member(2, &[SUPPORTER_ROLE]);
+
+        update(&store, &config(0), None, &new).await;
+
+        assert_eq!(store.get(2).unwrap().source, SUPPORTER_SOURCE_ROLE);
+    }
+
+    #[tokio::test]
+    async fn uncached_member_without_supporter_role_is_removed() {
+        let store = InMemorySupporterStore::with_supporters([supporter(2, SUPPORTER_SOURCE_ROLE)]);
+        let new = member(2, &[OTHER_ROLE]);
+
+        update(&store, &config(0), None, &new).await;
+
+        assert!(store.get(2).is_none());
+    }
+
+    #[tokio::test]
+    async fn uncached_member_without_supporter_role_is_left_alone_if_not_a_supporter() {
+        let store = InMemorySupporterStore::default();
+        let new = member(2, &[]);
+
+        update(&store, &config(24), None, &new).await;
+
+        assert!(store.get(2).is_none());
+    }
+
+    const GRANTED_SOURCES: [&str; 3] = [
+        SUPPORTER_SOURCE_PAYMENT,
+        SUPPORTER_SOURCE_VOUCHER,
+        SUPPORTER_SOURCE_GIFT,
+    ];
+
+    #[tokio::test]
+    async fn losing_supporter_role_keeps_granted_supporters() {
+        for grace_period_hours in [0, 24] {
+            for source in GRANTED_SOURCES {
+                let store = InMemorySupporterStore::with_supporters([supporter(2, source)]);
+                let old = member(2, &[SUPPORTER_ROLE]);
+                let new = member(2, &[]);
+
+                update(&store, &config(grace_period_hours), Some(&old), &new).await;
+
+                let supporter = store.get(2).unwrap();
+                assert_eq!(supporter.source, source);
+                assert!(supporter.revocation_deadline.is_none());
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn ending_boost_keeps_granted_supporters() {
+        for source in GRANTED_SOURCES {
+            let store = InMemorySupporterStore::with_supporters([supporter(2, source)]);
+            let old = booster(2);
+            let new = member(2, &[]);
+
+            update(&store, &config(0), Some(&old), &new).await;
+
+            assert_eq!(store.get(2).unwrap().source, source);
+        }
+    }
+
+    #[tokio::test]
+    async fn switching_between_role_and_boost_keeps_source_of_granted_supporters() {
+        for source in GRANTED_SOURCES {
+            let store = InMemorySupporterStore::with_supporters([supporter(2, source)]);
+            let mut old = booster(2);
+            old.roles.push(RoleId::new(SUPPORTER_ROLE));
+            let new = booster(2);
+
+            update(&store, &config(0), Some(&old), &new).await;
+
+            assert_eq!(store.get(2).unwrap().source, source);
+        }
+    }
+
+    #[tokio::test]
+    async fn uncached_member_without_supporter_role_keeps_granted_supporters() {
+        for source in GRANTED_SOURCES {
+            let store = InMemorySupporterStore::with_supporters([supporter(2, source)]);
+            let new = member(2, &[OTHER_ROLE]);
+
+            update(&store, &config(0), None, &new).await;
+
+            assert_eq!(store.get(2).unwrap().source, source);
+        }
+    }
+
+    #[tokio::test]
+    async fn sync_keeps_granted_supporters() {
+        let store = InMemorySupporterStore::with_supporters(
+            GRANTED_SOURCES
+                .iter()
+                .zip(2..)
+                .map(|(source, user_id)| supporter(user_id, source)),
+        );
+        let members = [member(2, &[SUPPORTER_ROLE]), booster(3), member(4, &[])];
+
+        sync_supporters(&store, &config(24), &members)
+            .await
+            .unwrap();
+
+        for (source, user_id) in GRANTED_SOURCES.iter().zip(2..) {
+            assert_eq!(store.get(user_id).unwrap().source, *source);
+        }
+    }
+
+    #[tokio::test]
+    async fn revoke_removes_supporter_role_and_entry() {
+        let discord = FakeDiscord::with_members([member(2, &[OTHER_ROLE, SUPPORTER_ROLE])]);
+        let store =
+            InMemorySupporterStore::with_supporters([supporter(2, SUPPORTER_SOURCE_PAYMENT)]);
+
+        revoke_supporter(
+            &discord,
+            &store,
+            &config(24),
+            UserId::new(2),
+            &ALL_SUPPORTER_SOURCES,
+            "test",
+        )
+        .await
+        .unwrap();
+
+        assert!(store.get(2).is_none());
+        assert_eq!(discord.roles(UserId::new(2)), vec![RoleId::new(OTHER_ROLE)]);
+    }
+
+    #[tokio::test]
+    async fn revoke_without_supporter_role_configured_leaves_roles_alone() {
+        let discord = FakeDiscord::with_members([member(2, &[SUPPORTER_ROLE])]);
+        let store =
+            InMemorySupporterStore::with_supporters([supporter(2, SUPPORTER_SOURCE_PAYMENT)]);
+        let config = SupportGuildConfig {
+            supporter_role_id: None,
+            ..config(0)
+        };
+
+        revoke_supporter(
+            &discord,
+            &store,
+            &config,
+            UserId::new(2),
+            &ALL_SUPPORTER_SOURCES,
+            "test",
+        )
+        .await
+        .unwrap();
+
+        assert!(store.get(2).is_none());
+        assert_eq!(
+            discord.roles(UserId::new(2)),
+            vec![RoleId::new(SUPPORTER_ROLE)]
+        );
+    }
+
+    #[tokio::test]
+    async fn expiry_keeps_renewed_supporters() {
+        let now = Utc::now();
+        let discord =
+            FakeDiscord::with_members([member(2, &[SUPPORTER_ROLE]), member(3, &[SUPPORTER_ROLE])]);
+        let mut expired = supporter(2, SUPPORTER_SOURCE_VOUCHER);
+        expired.expiration_timestamp = Some(now - chrono::Duration::minutes(1));
+        // renewed after the expired supporters have been loaded
+        let mut renewed = supporter(3, SUPPORTER_SOURCE_VOUCHER);
+        renewed.expiration_timestamp = Some(now + chrono::Duration::days(30));
+        let store = InMemorySupporterStore::with_supporters([expired, renewed]);
+
+        for user_id in [2, 3] {
+            expire_supporter(&discord, &store, &config(24), UserId::new(user_id), now)
+                .await
+                .unwrap();
+        }
+
+        assert!(store.get(2).is_none());
+        assert!(discord.roles(UserId::new(2)).is_empty());
+        assert!(store.get(3).is_some());
+        assert_eq!(
+            discord.roles(UserId::new(3)),
+            vec![RoleId::new(SUPPORTER_ROLE)]
+        );
+    }
+
+    fn pending(user_id: u64, source: &str, deadline: DateTime<Utc>) -> AiodeSupporter {
+        let mut supporter = supporter(user_id, source);
+        supporter.revocation_deadline = Some(deadline);
+        supporter
+    }
+
+    #[tokio::test]
+    async fn revoking_payment_schedules_revocation_during_grace_period() {
+        let discord = FakeDiscord::with_members([member(2, &[SUPPORTER_ROLE])]);
+        let store =
+            InMemorySupporterStore::with_supporters([supporter(2, SUPPORTER_SOURCE_PAYMENT)]);
+
+        revoke_payment(&discord, &store, &config(24), UserId::new(2), "test")
+            .await
+            .unwrap();
+
+        assert!(store.get(2).unwrap().revocation_deadline.is_some());
+        assert_eq!(
+            discord.roles(UserId::new(2)),
+            vec![RoleId::new(SUPPORTER_ROLE)]
+        );
+    }
+
+    #[tokio::test]
+    async fn revoking_payment_keeps_supporters_of_other_sources() {
+        let discord = FakeDiscord::with_members([member(2, &[SUPPORTER_ROLE])]);
+        let store = InMemorySupporterStore::with_supporters([
+            supporter(2, SUPPORTER_SOURCE_ROLE),
+            supporter(3, SUPPORTER_SOURCE_VOUCHER),
+        ]);
+
+        for grace_period_hours in [0, 24] {
+            for user_id in [2, 3] {
+                revoke_payment(
+                    &discord,
+                    &store,
+                    &config(grace_period_hours),
+                    UserId::new(user_id),
+                    "test",
+                )
+                .await
+                .unwrap();
+            }
+        }
+
+        assert!(store.get(2).unwrap().revocation_deadline.is_none());
+        assert!(store.get(3).unwrap().revocation_deadline.is_none());
+        assert_eq!(
+            discord.roles(UserId::new(2)),
+            vec![RoleId::new(SUPPORTER_ROLE)]
+        );
+    }
+
+    #[tokio::test]
+    async fn finalising_revocations_removes_former_guild_supporters() {
+        let now = Utc::now();
+        let discord = FakeDiscord::with_members([member(2, &[]), member(3, &[SUPPORTER_ROLE])]);
+        let store = InMemorySupporterStore::with_supporters([
+            pending(2, SUPPORTER_SOURCE_ROLE, now),
+            pending(3, SUPPORTER_SOURCE_ROLE, now),
+            // no longer a member of the support guild
+            pending(4, SUPPORTER_SOURCE_BOOSTER, now),
+            pending(5, SUPPORTER_SOURCE_ROLE, now + chrono::Duration::hours(1)),
+        ]);
+
+        finalise_revocations(&discord, &store, &config(24), now)
+            .await
+            .unwrap();
+
+        assert!(store.get(2).is_none());
+        // regained the supporter role during the grace period
+        assert!(store.get(3).unwrap().revocation_deadline.is_none());
+        assert!(store.get(4).is_none());
+        assert!(store.get(5).unwrap().revocation_deadline.is_some());
+    }
+
+    #[tokio::test]
+    async fn finalising_revocations_removes_revoked_payment_supporters_with_their_role() {
+        let now = Utc::now();
+        let discord = FakeDiscord::with_members([member(2, &[OTHER_ROLE, SUPPORTER_ROLE])]);
+        let store = InMemorySupporterStore::with_supporters([
+            pending(2, SUPPORTER_SOURCE_PAYMENT, now),
+            // not a member of the support guild, which does not matter for payments
+            pending(3, SUPPORTER_SOURCE_PAYMENT, now),
+        ]);
+
+        finalise_revocations(&discord, &store, &config(24), now)
+            .await
+            .unwrap();
+
+        assert!(store.get(2).is_none());
+        assert!(store.get(3).is_none());
+        assert_eq!(discord.roles(UserId::new(2)), vec![RoleId::new(OTHER_ROLE)]);
+    }
+
+    #[tokio::test]
+    async fn finalising_revocations_ignores_voucher_and_gift_supporters() {
+        let now = Utc::now();
+        let discord = FakeDiscord::default();
+        let store = InMemorySupporterStore::with_supporters([
+            pending(2, SUPPORTER_SOURCE_VOUCHER, now),
+            pending(3, SUPPORTER_SOURCE_GIFT, now),
+        ]);
+
+        finalise_revocations(&discord, &store, &config(24), now)
+            .await
+            .unwrap();
+
+        assert!(store.get(2).is_some());
+        assert!(store.get(3).is_some());
+    }
+
+    #[tokio::test]
+    async fn time_limited_grant_outlives_pending_revocation() {
+        let discord = FakeDiscord::with_members([member(2, &[SUPPORTER_ROLE])]);
+        let store =
+            InMemorySupporterStore::with_supporters([supporter(2, SUPPORTER_SOURCE_PAYMENT)]);
+        let config = config(24);
+
+        revoke_payment(&discord, &store, &config, UserId::new(2), "test")
+            .await
+            .unwrap();
+        let expiration_timestamp = store
+            .grant_time_limited_supporter(
+                2.into(),
+                "gold",
+                chrono::Duration::days(30),
+                SUPPORTER_SOURCE_VOUCHER,
+            )
+            .await
+            .unwrap();
+        finalise_revocations(
+            &discord,
+            &store,
+            &config,
+            Utc::now() + chrono::Duration::hours(25),
+        )
+        .await
+        .unwrap();
+
+        let supporter = store.get(2).unwrap();
+        assert_eq!(supporter.source, SUPPORTER_SOURCE_VOUCHER);
+        assert_eq!(supporter.tier.as_deref(), Some("gold"));
+        assert_eq!(supporter.expiration_timestamp, expiration_timestamp);
+        assert!(supporter.revocation_deadline.is_none());
+        assert_eq!(
+            discord.roles(UserId::new(2)),
+            vec![RoleId::new(SUPPORTER_ROLE)]
+        );
+    }
+
+    #[test]
+    fn time_limited_grant_leaves_permanent_supporters_alone() {
+        let existing = supporter(2, SUPPORTER_SOURCE_PAYMENT);
+
+        let status = time_limited_status(
+            Some(&existing),
+            "gold",
+            chrono::Duration::days(30),
+            SUPPORTER_SOURCE_VOUCHER,
+            Utc::now(),
+        );
+
+        assert!(status.is_none());
+    }
+
+    #[tokio::test]
+    async fn gift_to_premium_payment_supporter_only_extends_expiry() {
+        let expiration_timestamp = Utc::now() + chrono::Duration::days(10);
+        let mut existing = supporter(2, SUPPORTER_SOURCE_PAYMENT);
+        existing.tier = Some(String::from("premium"));
+        existing.expiration_timestamp = Some(expiration_timestamp);
+        let store = InMemorySupporterStore::with_supporters([existing]);
+
+        store
+            .grant_time_limited_supporter(
+                2.into(),
+                DEFAULT_SUPPORTER_TIER,
+                chrono::Duration::days(30),
+                SUPPORTER_SOURCE_GIFT,
+            )
+            .await
+            .unwrap();
+
+        let supporter = store.get(2).unwrap();
+        assert_eq!(supporter.source, SUPPORTER_SOURCE_PAYMENT);
+        assert_eq!(supporter.tier.as_deref(), Some("premium"));
+        assert_eq!(
+            supporter.expiration_timestamp,
+            Some(expiration_timestamp + chrono::Duration::days(30))
+        );
+    }
+
+    #[test]
+    fn higher_tier_follows_configured_order() {
+        let tiers = ["default", "gold", "premium"].map(String::from);
+
+        assert_eq!(higher_tier(&tiers, "premium", "gold"), "premium");
+        assert_eq!(higher_tier(&tiers, "gold", "premium"), "premium");
+        assert_eq!(higher_tier(&tiers, "unknown", "default"), "default");
+        assert_eq!(higher_tier(&tiers, "gold", "unknown"), "gold");
+        assert_eq!(higher_tier(&[], "gold", "premium"), "gold");
+    }
+
+    #[tokio::test]
+    async fn assigning_role_to_non_member_is_not_an_error() {
+        let discord = FakeDiscord::default();
+
+        assign_supporter_role(&discord, &config(0), UserId::new(2), "test").await;
+
+        assert!(discord.members.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn sync_reconciles_supporters_with_members() {
+        let mut pending = supporter(2, SUPPORTER_SOURCE_ROLE);
+        pending.revocation_deadline = Some(Utc::now() + chrono::Duration::hours(1));
+        let store = InMemorySupporterStore::with_supporters([
+            pending,
+            supporter(3, SUPPORTER_SOURCE_BOOSTER),
+            supporter(4, SUPPORTER_SOURCE_BOOSTER),
+            supporter(5, SUPPORTER_SOURCE_PAYMENT),
+        ]);
+        let members = [
+            member(1, &[SUPPORTER_ROLE]),
+            member(2, &[SUPPORTER_ROLE]),
+            booster(3),
+            member(6, &[OTHER_ROLE]),
+        ];
+
+        sync_supporters(&store, &config(0), &members).await.unwrap();
+
+        assert_eq!(store.get(1).unwrap().source, SUPPORTER_SOURCE_ROLE);
+        assert!(store.get(2).unwrap().revocation_deadline.is_none());
+        assert!(store.get(3).is_some());
+        // the former booster is removed, supporters tracked by other means are kept
+        assert!(store.get(4).is_none());
+        assert!(store.get(5).is_some());
+        assert!(store.get(6).is_none());
+    }
+
+    #[tokio::test]
+    async fn sync_schedules_revocation_of_former_boosters_during_grace_period() {
+        let deadline = Utc::now() + chrono::Duration::hours(1);
+        let store = InMemorySupporterStore::with_supporters([
+            supporter(3, SUPPORTER_SOURCE_BOOSTER),
+            supporter(4, SUPPORTER_SOURCE_BOOSTER),
+            pending(5, SUPPORTER_SOURCE_BOOSTER, deadline),
+        ]);
+
+        sync_supporters(&store, &config(24), &[booster(3)])
+            .await
+            .unwrap();
+
+        assert!(store.get(3).unwrap().revocation_deadline.is_none());
+        assert!(store
+            .get(4)
+            .unwrap()
+            .revocation_deadline
+            .is_some_and(|deadline| deadline > Utc::now() + chrono::Duration::hours(23)));
+        // an already scheduled revocation is not postponed
+        assert_eq!(store.get(5).unwrap().revocation_deadline, Some(deadline));
+    }
+
+    #[tokio::test]
+    async fn sync_keeps_boosters_if_boosters_are_not_supporters() {
+        let store =
+            InMemorySupporterStore::with_supporters([supporter(3, SUPPORTER_SOURCE_BOOSTER)]);
+        let config = SupportGuildConfig {
+            boosters_are_supporters: false,
+            ..config(0)
+        };
+
+        sync_supporters(&store, &config, &[]).await.unwrap();
+
+        assert!(store.get(3).is_some());
+    }
 }
@@ -0,0 +1,378 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use lazy_static::lazy_static;
+use serenity::all::{
+    ChannelId, CreateAllowedMentions, CreateEmbed, CreateEmbedFooter, CreateMessage, RoleId,
+};
+use tokio::sync::mpsc;
+
+use crate::{
+    context::app_context, util::Worker, ALERT_DEDUP_WINDOW_MINUTES, ALERT_RATE_LIMIT_PER_HOUR,
+    INSTANCE_ID, OPS_ALERT_ROLE_ID, OPS_CHANNEL_ID, TASK_FAILURE_ALERT_THRESHOLD,
+};
+
+const ERROR_EMBED_COLOUR: u32 = 0xED4245;
+const ESCALATION_EMBED_COLOUR: u32 = 0x992D22;
+const RECOVERY_EMBED_COLOUR: u32 = 0x57F287;
+/// Interval at which summaries of suppressed alerts are posted.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(60 * 60);
+/// Error details are truncated to keep alerts readable and within the embed description limit.
+const MAX_DETAIL_LENGTH: usize = 1500;
+
+static ALERT_POSTER: Worker<Alert> = Worker::new("Alert poster");
+
+lazy_static! {
+    /// Number of consecutive failures of each task on this instance. Runs of a task on other instances
+    /// are not counted, so with several instances a task failing everywhere is escalated once one
+    /// instance has run it `GLYPH_TASK_FAILURE_ALERT_THRESHOLD` times in a row, and the counts restart
+    /// from zero when the instance restarts.
+    static ref TASK_FAILURES: Mutex<HashMap<&'static str, u32>> = Mutex::new(HashMap::new());
+}
+
+enum Alert {
+    /// Deduplicated by key and subject to the rate limit.
+    Error { key: String, message: String },
+    /// Always posted, pinging `GLYPH_OPS_ALERT_ROLE_ID` if set.
+    Escalation { message: String },
+    /// Always posted.
+    Recovery { message: String },
+}
+
+/// Starts posting alerts to `GLYPH_OPS_CHANNEL_ID`, if set. Alerts reported before or without calling
+/// this are dropped, the reported errors are logged by the callers either way.
+pub fn start_alerts() {
+    let Some(channel_id) = *OPS_CHANNEL_ID else {
+        return;
+    };
+
+    tracing::info!("Posting alerts to ops channel {channel_id}");
+    ALERT_POSTER.start(|alerts| post_alerts(ChannelId::new(channel_id), alerts));
+}
+
+/// Reports an error to the ops channel. Errors with the same key are only posted once per
+/// `GLYPH_ALERT_DEDUP_WINDOW_MINUTES`, further occurrences are summarised afterwards.
+pub fn report_error(key: impl Into<String>, message: impl Into<String>) {
+    send(Alert::Error {
+        key: key.into(),
+        message: message.into(),
+    });
+}
+
+/// Reports a failed run of the task, escalating once it failed `GLYPH_TASK_FAILURE_ALERT_THRESHOLD`
+/// times in a row.
+pub fn report_task_failure(task_id: &'static str, message: &str) {
+    let consecutive_failures = {
+        let mut task_failures = TASK_FAILURES.lock().expect("task failures lock poisoned");
+        let consecutive_failures = task_failures.entry(task_id).or_default();
+        *consecutive_failures += 1;
+        *consecutive_failures
+    };
+
+    report_error(
+        format!("task:{task_id}"),
+        format!("Task `{task_id}` failed: {message}"),
+    );
+    if consecutive_failures == *TASK_FAILURE_ALERT_THRESHOLD {
+        send(Alert::Escalation {
+            message: format!(
+                "Task `{task_id}` failed {consecutive_failures} times in a row, latest error: {message}"
+            ),
+        });
+    }
+}
+
+/// Resets the consecutive failures of the task, posting a recovery message if its failures have been
+/// reported.
+pub fn report_task_success(task_id: &'static str) {
+    let Some(consecutive_failures) = TASK_FAILURES
+        .lock()
+        .expect("task failures lock poisoned")
+        .remove(task_id)
+    else {
+        return;
+    };
+
+    send(Alert::Recovery {
+        message: format!(
+            "Task `{task_id}` succeeded again after {consecutive_failures} consecutive failures"
+        ),
+    });
+}
+
+fn send(alert: Alert) {
+    ALERT_POSTER.send(alert);
+}
+
+async fn post_alerts(channel_id: ChannelId, mut alerts: mpsc::UnboundedReceiver<Alert>) {
+    let mut throttle = AlertThrottle::new(
+        Duration::from_secs(u64::from(*ALERT_DEDUP_WINDOW_MINUTES) * 60),
+        *ALERT_RATE_LIMIT_PER_HOUR as usize,
+    );
+    let mut flush_interval = tokio::time::interval(FLUSH_INTERVAL);
+
+    loop {
+        tokio::select! {
+            alert = alerts.recv() => match alert {
+                Some(Alert::Error { key, message }) => {
+                    if let Some(message) = throttle.on_error(key, message, Instant::now()) {
+                        post(channel_id, "Error", message, ERROR_EMBED_COLOUR, false).await;
+                    }
+                }
+                Some(Alert::Escalation { message }) => {
+                    throttle.record_post(Instant::now());
+                    post(channel_id, "Escalation", message, ESCALATION_EMBED_COLOUR, true).await;
+                }
+                Some(Alert::Recovery { message }) => {
+                    throttle.record_post(Instant::now());
+                    post(channel_id, "Recovered", message, RECOVERY_EMBED_COLOUR, false).await;
+                }
+                None => return,
+            },
+            _ = flush_interval.tick() => {
+                for message in throttle.flush(Instant::now()) {
+                    post(channel_id, "Error summary", message, ERROR_EMBED_COLOUR, false).await;
+                }
+            }
+        }
+    }
+}
+
+async fn post(channel_id: ChannelId, title: &str, description: String, colour: u32, ping: bool) {
+    let embed = CreateEmbed::new()
+        .title(title)
+        .description(description)
+        .colour(colour)
+        .footer(CreateEmbedFooter::new(format!("Instance {}", *INSTANCE_ID)));
+    let mut message = CreateMessage::new().embed(embed);
+    if let Some(role_id) = OPS_ALERT_ROLE_ID.filter(|_| ping) {
+        message = message
+            .content(format!("<@&{role_id}>"))
+            .allowed_mentions(CreateAllowedMentions::new().roles([RoleId::new(role_id)]));
+    }
+
+    // failures are only logged, reporting them would alert about alerts
+    if let Err(e) = channel_id.send_message(&app_context().http, message).await {
+        tracing::error!("Failed to post alert to ops channel: {e}");
+    }
+}
+
+/// Deduplicates error alerts by key and limits the number of posted alerts per hour.
+struct AlertThrottle {
+    dedup_window: Duration,
+    max_posts_per_hour: usize,
+    errors: HashMap<String, ErrorState>,
+    recent_posts: VecDeque<Instant>,
+}
+
+struct ErrorState {
+    /// When the error has last been posted, or first been suppressed by the rate limit.
+    reported_at: Instant,
+    /// Occurrences that have not been posted since.
+    suppressed: u32,
+    latest_message: String,
+}
+
+impl AlertThrottle {
+    fn new(dedup_window: Duration, max_posts_per_hour: usize) -> Self {
+        Self {
+            dedup_window,
+            max_posts_per_hour,
+            errors: HashMap::new(),
+            recent_posts: VecDeque::new(),
+        }
+    }
+
+    /// Returns the message to post for the error, if any.
+    fn on_error(&mut self, key: String, message: String, now: Instant) -> Option<String> {
+        if let Some(state) = self.errors.get_mut(&key) {
+            if now.duration_since(state.reported_at) < self.dedup_window {
+                state.suppressed += 1;
+                state.latest_message = message;
+                return None;
+            }
+        }
+
+        if !self.try_post(now) {
+            let state = self.errors.entry(key).or_insert_with(|| ErrorState {
+                reported_at: now,
+                suppressed: 0,
+                latest_message: String::new(),
+            });
+            state.suppressed += 1;
+            state.latest_message = message;
+            return None;
+        }
+
+        let previously_suppressed = self
+            .errors
+            .insert(
+                key,
+                ErrorState {
+                    reported_at: now,
+                    suppressed: 0,
+                    latest_message: String::new(),
+                },
+            )
+            .map_or(0, |state| state.suppressed);
+        let mut message = truncate(message);
+        if previously_suppressed > 0 {
+            message.push_str(&format!(
+                "\n\n{previously_suppressed} similar errors have been suppressed before"
+            ));
+        }
+        Some(message)
+    }
+
+    /// Returns summaries of errors that recurred after being posted once their dedup window has
+    /// elapsed, and forgets errors that did not recur.
+    fn flush(&mut self, now: Instant) -> Vec<String> {
+        let mut summaries = Vec::new();
+        let mut expired_keys = Vec::new();
+        for (key, state) in &self.errors {
+            if now.duration_since(state.reported_at) >= self.dedup_window {
+                expired_keys.push(key.clone());
+            }
+        }
+
+        for key in expired_keys {
+            let suppressed = self.errors[&key].suppressed;
+            if suppressed == 0 {
+                self.errors.remove(&key);
+                continue;
+            }
+            if !self.try_post(now) {
+                break;
+            }
+
+            let state = self.errors.get_mut(&key).expect("expired key exists");
+            summaries.push(format!(
+                "`{key}` occurred again ({suppressed}x), latest: {}",
+                truncate(std::mem::take(&mut state.latest_message))
+            ));
+            state.reported_at = now;
+            state.suppressed = 0;
+        }
+        summaries
+    }
+
+    /// Counts a post that is not subject to the rate limit.
+    fn record_post(&mut self, now: Instant) {
+        self.expire_posts(now);
+        self.recent_posts.push_back(now);
+    }
+
+    fn try_post(&mut self, now: Instant) -> bool {
+        self.expire_posts(now);
+        if self.recent_posts.len() >= self.max_posts_per_hour {
+            return false;
+        }
+        self.recent_posts.push_back(now);
+        true
+    }
+
+    fn expire_posts(&mut self, now: Instant) {
+        while self
+            .recent_posts
+            .front()
+            .is_some_and(|posted_at| now.duration_since(*posted_at) >= RATE_LIMIT_WINDOW)
+        {
+            self.recent_posts.pop_front();
+        }
+    }
+}
+
+fn truncate(mut message: String) -> String {
+    if message.len() > MAX_DETAIL_LENGTH {
+        let mut end = MAX_DETAIL_LENGTH;
+        while !message.is_char_boundary(end) {
+            end -= 1;
+        }
+        message.truncate(end);
+        message.push('…');
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: Duration = Duration::from_secs(60);
+
+    fn error(throttle: &mut AlertThrottle, key: &str, now: Instant) -> Option<String> {
+        throttle.on_error(key.to_string(), format!("{key} failed"), now)
+    }
+
+    #[test]
+    fn deduplicates_errors_within_window() {
+        let mut throttle = AlertThrottle::new(10 * MINUTE, 10);
+        let start = Instant::now();
+
+        assert!(error(&mut throttle, "a", start).is_some());
+        assert!(error(&mut throttle, "a", start + MINUTE).is_none());
+        assert!(error(&mut throttle, "b", start + MINUTE).is_some());
+    }
+
+    #[test]
+    fn summarises_suppressed_errors_after_window() {
+        let mut throttle = AlertThrottle::new(10 * MINUTE, 10);
+        let start = Instant::now();
+        error(&mut throttle, "a", start);
+        error(&mut throttle, "a", start + MINUTE);
+        error(&mut throttle, "a", start + 2 * MINUTE);
+
+        assert!(throttle.flush(start + 5 * MINUTE).is_empty());
+        let summaries = throttle.flush(start + 10 * MINUTE);
+        assert_eq!(summaries, vec!["`a` occurred again (2x), latest: a failed"]);
+        // the summary starts a new window
+        assert!(error(&mut throttle, "a", start + 11 * MINUTE).is_none());
+    }
+
+    #[test]
+    fn forgets_errors_that_did_not_recur() {
+        let mut throttle = AlertThrottle::new(10 * MINUTE, 10);
+        let start = Instant::now();
+        error(&mut throttle, "a", start);
+
+        assert!(throttle.flush(start + 10 * MINUTE).is_empty());
+        assert!(throttle.errors.is_empty());
+        assert!(error(&mut throttle, "a", start + 11 * MINUTE).is_some());
+    }
+
+    #[test]
+    fn rate_limits_posts() {
+        let mut throttle = AlertThrottle::new(MINUTE, 2);
+        let start = Instant::now();
+
+        assert!(error(&mut throttle, "a", start).is_some());
+        assert!(error(&mut throttle, "b", start).is_some());
+        assert!(error(&mut throttle, "c", start).is_none());
+        assert!(throttle.flush(start + 2 * MINUTE).is_empty());
+
+        // the suppressed error is summarised once the rate limit allows it
+        let summaries = throttle.flush(start + RATE_LIMIT_WINDOW);
+        assert_eq!(summaries, vec!["`c` occurred again (1x), latest: c failed"]);
+    }
+
+    #[test]
+    fn escalations_count_towards_rate_limit() {
+        let mut throttle = AlertThrottle::new(MINUTE, 1);
+        let start = Instant::now();
+        throttle.record_post(start);
+
+        assert!(error(&mut throttle, "a", start).is_none());
+    }
+
+    #[test]
+    fn truncates_long_messages_on_char_boundary() {
+        let message = truncate("é".repeat(MAX_DETAIL_LENGTH));
+
+        assert!(message.len() <= MAX_DETAIL_LENGTH + '…'.len_utf8());
+        assert!(message.ends_with('…'));
+    }
+}
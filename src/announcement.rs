@@ -0,0 +1,206 @@
+use chrono::{DateTime, Months, Utc};
+use diesel::{ExpressionMethods, QueryDsl};
+use diesel_async::RunQueryDsl;
+use serenity::{
+    all::{ChannelId, CreateEmbed, CreateMessage, UserId},
+    http::Http,
+};
+
+use crate::{
+    acquire_db_connection,
+    context::app_context,
+    error::Error,
+    model::NewSupporterAnnouncement,
+    schema::{aiode_supporter, supporter_announcement},
+    snowflake::Snowflake,
+    DbConnection, ANNIVERSARY_DM_USERS, ANNIVERSARY_MONTHS, ANNIVERSARY_TEMPLATE,
+    ANNOUNCEMENT_CHANNEL_ID, MILESTONE_TEMPLATE, SUPPORTER_COUNT_MILESTONES,
+};
+
+/// Anniversaries that have been reached longer ago than this are not announced anymore, so that
+/// enabling announcements does not announce the anniversaries of all existing supporters at once.
+const ANNIVERSARY_ANNOUNCEMENT_WINDOW_DAYS: i64 = 7;
+const ANNOUNCEMENT_EMBED_COLOUR: u32 = 0xEB459E;
+
+/// Announces supporter anniversaries and supporter count milestones that have not been announced yet.
+/// Each announcement is recorded in the supporter_announcement table before it is sent, so that it
+/// is never sent twice.
+pub async fn announce_supporter_milestones() -> Result<(), Error> {
+    if ANNOUNCEMENT_CHANNEL_ID.is_none() && !*ANNIVERSARY_DM_USERS {
+        tracing::warn!("Cannot announce supporter milestones because neither ANNOUNCEMENT_CHANNEL_ID nor ANNIVERSARY_DM_USERS is set");
+        return Ok(());
+    }
+
+    let serenity_http = &app_context().http;
+    let mut connection = acquire_db_connection().await?;
+    let supporters = aiode_supporter::table
+        .filter(aiode_supporter::revocation_deadline.is_null())
+        .select((
+            aiode_supporter::user_id,
+            aiode_supporter::creation_timestamp,
+        ))
+        .load::<(Snowflake, DateTime<Utc>)>(&mut connection)
+        .await?;
+    drop(connection);
+
+    let now = Utc::now();
+    for (user_id, creation_timestamp) in supporters.iter() {
+        let Some(months) = ANNIVERSARY_MONTHS
+            .iter()
+            .copied()
+            .filter(|months| {
+                creation_timestamp
+                    .checked_add_months(Months::new(*months))
+                    .is_some_and(|anniversary| {
+                        anniversary <= now
+                            && now - anniversary
+                                < chrono::Duration::days(ANNIVERSARY_ANNOUNCEMENT_WINDOW_DAYS)
+                    })
+            })
+            .max()
+        else {
+            continue;
+        };
+
+        let discord_user_id = user_id.get();
+
+        let description = ANNIVERSARY_TEMPLATE
+            .replace("{user}", &format!("<@{discord_user_id}>"))
+            .replace("{months}", &months.to_string());
+        if let Err(e) = announce(
+            serenity_http,
+            format!("anniversary:{user_id}:{months}"),
+            Some(discord_user_id),
+            "Supporter anniversary",
+            description,
+        )
+        .await
+        {
+            // e.g. the user does not accept DMs, keep announcing the other anniversaries
+            tracing::warn!(
+                "Failed to announce {months} month anniversary of supporter {user_id}: {e}"
+            );
+        }
+    }
+
+    if ANNOUNCEMENT_CHANNEL_ID.is_some() {
+        let supporter_count = supporters.len() as u64;
+        if let Some(milestone) = SUPPORTER_COUNT_MILESTONES
+            .iter()
+            .copied()
+            .filter(|milestone| *milestone <= supporter_count)
+            .max()
+        {
+            // lower milestones that have been skipped, e.g. when enabling announcements, are not
+            // announced anymore
+            let mut connection = acquire_db_connection().await?;
+            for skipped_milestone in SUPPORTER_COUNT_MILESTONES
+                .iter()
+                .filter(|skipped_milestone| **skipped_milestone < milestone)
+            {
+                claim_announcement(
+                    &mut connection,
+                    format!("milestone:{skipped_milestone}"),
+                    None,
+                )
+                .await?;
+            }
+            drop(connection);
+
+            let description = MILESTONE_TEMPLATE.replace("{count}", &milestone.to_string());
+            announce(
+                serenity_http,
+                format!("milestone:{milestone}"),
+                None,
+                "Supporter milestone",
+                description,
+            )
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Sends the announcement to the user via DM if it concerns a single user and `ANNIVERSARY_DM_USERS`
+/// is enabled, else to the announcement channel.
+async fn announce(
+    serenity_http: &Http,
+    announcement_key: String,
+    user_id: Option<u64>,
+    title: &str,
+    description: String,
+) -> Result<(), Error> {
+    let dm_user_id = user_id.filter(|_| *ANNIVERSARY_DM_USERS);
+    if dm_user_id.is_none() && ANNOUNCEMENT_CHANNEL_ID.is_none() {
+        return Ok(());
+    }
+
+    let mut connection = acquire_db_connection().await?;
+    if !claim_announcement(&mut connection, announcement_key.clone(), user_id).await? {
+        return Ok(());
+    }
+
+    let message = CreateMessage::new().embed(create_embed(title, description));
+    let result = match (dm_user_id, *ANNOUNCEMENT_CHANNEL_ID) {
+        (Some(dm_user_id), _) => match UserId::new(dm_user_id)
+            .create_dm_channel(serenity_http)
+            .await
+        {
+            Ok(channel) => channel.id.send_message(serenity_http, message).await,
+            Err(e) => Err(e),
+        },
+        (None, Some(channel_id)) => {
+            ChannelId::new(channel_id)
+                .send_message(serenity_http, message)
+                .await
+        }
+        (None, None) => unreachable!(),
+    };
+
+    if let Err(e) = result {
+        // release the announcement so that it is retried on the next run
+        release_announcement(&mut connection, announcement_key).await?;
+        return Err(e.into());
+    }
+
+    tracing::info!("Sent supporter announcement {announcement_key}");
+    Ok(())
+}
+
+fn create_embed(title: &str, description: String) -> CreateEmbed {
+    CreateEmbed::new()
+        .title(title)
+        .description(description)
+        .colour(ANNOUNCEMENT_EMBED_COLOUR)
+}
+
+/// Records the announcement as sent, returns false if it has already been recorded.
+async fn claim_announcement(
+    connection: &mut DbConnection,
+    announcement_key: String,
+    user_id: Option<u64>,
+) -> Result<bool, Error> {
+    let res = diesel::insert_into(supporter_announcement::table)
+        .values(NewSupporterAnnouncement {
+            announcement_key,
+            user_id: user_id.map(Snowflake::from),
+        })
+        .on_conflict_do_nothing()
+        .execute(connection)
+        .await?;
+
+    Ok(res > 0)
+}
+
+async fn release_announcement(
+    connection: &mut DbConnection,
+    announcement_key: String,
+) -> Result<(), Error> {
+    diesel::delete(supporter_announcement::table)
+        .filter(supporter_announcement::announcement_key.eq(announcement_key))
+        .execute(connection)
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,75 @@
+use warp::{reject::Rejection, Filter};
+
+use crate::{error::Error, util::constant_time_eq, ADMIN_API_TOKEN};
+
+/// Requires the request to carry the admin API token as bearer token. Admin routes are not available
+/// if no token is configured.
+pub fn admin_auth() -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::header::optional::<String>("authorization")
+        .and_then(|authorization: Option<String>| async move {
+            if ADMIN_API_TOKEN.is_none() {
+                return Err(warp::reject::not_found());
+            }
+
+            if is_admin_authorization(authorization.as_deref()) {
+                Ok(())
+            } else {
+                Err(Error::UnauthorizedError.into())
+            }
+        })
+        .untuple_one()
+}
+
+fn is_admin_authorization(authorization: Option<&str>) -> bool {
+    let Some(ref admin_api_token) = *ADMIN_API_TOKEN else {
+        return false;
+    };
+
+    authorization
+        .and_then(|authorization| authorization.strip_prefix("Bearer "))
+        .is_some_and(|token| constant_time_eq(token.as_bytes(), admin_api_token.as_bytes()))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use warp::{http::StatusCode, test::request};
+
+    use super::*;
+    use crate::{
+        error::recover_with_request_id,
+        module::{route, Route},
+    };
+
+    pub const ADMIN_TOKEN: &str = "admin-token";
+
+    /// Configures the admin token of route tests. It is read once, so all tests use the same token.
+    pub fn configure_admin_token() {
+        std::env::set_var("GLYPH_ADMIN_API_TOKEN", ADMIN_TOKEN);
+    }
+
+    fn admin_route() -> Route {
+        route(warp::path!("admin").and(admin_auth()).map(|| "ok"))
+    }
+
+    #[tokio::test]
+    async fn admin_token_is_required() {
+        configure_admin_token();
+
+        for authorization in [None, Some("Bearer other"), Some(ADMIN_TOKEN)] {
+            let mut builder = request().path("/admin");
+            if let Some(authorization) = authorization {
+                builder = builder.header("authorization", authorization);
+            }
+            let response = builder.reply(&recover_with_request_id(admin_route())).await;
+
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        }
+
+        let response = request()
+            .path("/admin")
+            .header("authorization", format!("Bearer {ADMIN_TOKEN}"))
+            .reply(&admin_route())
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
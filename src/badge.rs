@@ -0,0 +1,717 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Utc};
+use diesel::{ExpressionMethods, QueryDsl};
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use serde::Serialize;
+use serenity::all::{GuildId, RoleId, UserId};
+use utoipa::ToSchema;
+use warp::{reject::Rejection, reply::Reply};
+
+use crate::{
+    acquire_db_connection,
+    aiode::find_aiode_supporter,
+    context::app_context,
+    discord::DiscordApi,
+    error::{Error, ErrorResponse},
+    model::{NewUserBadge, UserBadge},
+    schema::user_badge,
+    snowflake::Snowflake,
+    BADGE_ROLES, SUPPORTER_REVOCATION_GRACE_PERIOD_HOURS,
+};
+
+/// Badge of users with an aiode_supporter entry, derived from the supporter status rather than a
+/// tracked role.
+pub const SUPPORTER_BADGE: &str = "supporter";
+
+/// A role that grants a badge to the members holding it, see `GLYPH_BADGE_ROLES`.
+#[derive(Clone, Debug)]
+pub struct BadgeRole {
+    pub badge: String,
+    pub guild_id: u64,
+    pub role_id: u64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct UserBadgeResponse {
+    pub badge: String,
+    pub since: DateTime<Utc>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct UserBadgesResponse {
+    pub user_id: String,
+    pub badges: Vec<UserBadgeResponse>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/users/{user_id}/badges",
+    tag = "badges",
+    params(("user_id" = u64, Path, description = "Discord user id")),
+    responses(
+        (status = 200, description = "Badges of the user, oldest first", body = UserBadgesResponse),
+        (status = 500, description = "Badges could not be loaded", body = ErrorResponse),
+    ),
+)]
+pub async fn get_user_badges_handler(user_id: u64) -> Result<impl Reply, Rejection> {
+    let mut connection = acquire_db_connection().await?;
+    let user_badges = user_badge::table
+        .filter(user_badge::user_id.eq(Snowflake::from(user_id)))
+        .order(user_badge::creation_timestamp)
+        .load::<UserBadge>(&mut connection)
+        .await
+        .map_err(Error::from)?;
+    drop(connection);
+
+    let mut badges = Vec::with_capacity(user_badges.len() + 1);
+    if let Some(supporter) = find_aiode_supporter(user_id).await? {
+        badges.push(UserBadgeResponse {
+            badge: String::from(SUPPORTER_BADGE),
+            since: supporter.creation_timestamp,
+        });
+    }
+    badges.extend(user_badges.into_iter().map(|user_badge| UserBadgeResponse {
+        badge: user_badge.badge,
+        since: user_badge.creation_timestamp,
+    }));
+
+    Ok(warp::reply::json(&UserBadgesResponse {
+        user_id: user_id.to_string(),
+        badges,
+    }))
+}
+
+/// How badges are tracked, see `GLYPH_BADGE_ROLES`. Badges are revoked after the same grace period as
+/// supporter status, see `GLYPH_SUPPORTER_REVOCATION_GRACE_PERIOD_HOURS`.
+#[derive(Clone, Debug)]
+pub struct BadgeConfig {
+    pub badge_roles: Vec<BadgeRole>,
+    pub revocation_grace_period: chrono::Duration,
+}
+
+impl BadgeConfig {
+    pub fn from_env() -> Self {
+        Self {
+            badge_roles: BADGE_ROLES.clone(),
+            revocation_grace_period: chrono::Duration::hours(
+                (*SUPPORTER_REVOCATION_GRACE_PERIOD_HOURS).into(),
+            ),
+        }
+    }
+
+    /// Returns true if any badge is tracked via a role on the given guild.
+    pub fn tracks_guild(&self, guild_id: GuildId) -> bool {
+        self.badge_roles
+            .iter()
+            .any(|badge_role| badge_role.guild_id == guild_id.get())
+    }
+
+    /// Returns the guilds with roles granting badges.
+    pub fn guild_ids(&self) -> HashSet<GuildId> {
+        self.badge_roles
+            .iter()
+            .map(|badge_role| GuildId::new(badge_role.guild_id))
+            .collect()
+    }
+
+    /// Returns the badges granted by the given roles on the given guild.
+    pub fn badges_for_roles(&self, guild_id: GuildId, roles: &[RoleId]) -> HashSet<&str> {
+        self.badge_roles
+            .iter()
+            .filter(|badge_role| {
+                badge_role.guild_id == guild_id.get()
+                    && roles.iter().any(|role| *role == badge_role.role_id)
+            })
+            .map(|badge_role| badge_role.badge.as_str())
+            .collect()
+    }
+
+    /// Badges tracked on the given guild.
+    fn badges_of_guild(&self, guild_id: GuildId) -> HashSet<&str> {
+        self.badge_roles
+            .iter()
+            .filter(|badge_role| badge_role.guild_id == guild_id.get())
+            .map(|badge_role| badge_role.badge.as_str())
+            .collect()
+    }
+
+    /// Guilds with roles granting the badge, a badge may be granted by roles on several guilds.
+    fn guilds_granting(&self, badge: &str) -> HashSet<GuildId> {
+        self.badge_roles
+            .iter()
+            .filter(|badge_role| badge_role.badge == badge)
+            .map(|badge_role| GuildId::new(badge_role.guild_id))
+            .collect()
+    }
+}
+
+enum BadgeChange<'a> {
+    Grant(&'a str),
+    Revoke(&'a str),
+}
+
+/// Updates the user's badges tracked on the guild after their roles changed. `old_roles` is `None`
+/// if the previous state is unknown, in which case the current state is persisted.
+pub async fn handle_member_update(
+    guild_id: GuildId,
+    user_id: UserId,
+    old_roles: Option<&[RoleId]>,
+    new_roles: &[RoleId],
+) -> Result<(), Error> {
+    let config = BadgeConfig::from_env();
+    let changes = badge_changes(
+        &*app_context().http,
+        &config,
+        guild_id,
+        user_id,
+        old_roles,
+        new_roles,
+    )
+    .await?;
+    if changes.is_empty() {
+        return Ok(());
+    }
+
+    let mut connection = acquire_db_connection().await?;
+    for change in changes {
+        match change {
+            BadgeChange::Grant(badge) => add_badge(&mut connection, user_id, badge).await?,
+            BadgeChange::Revoke(badge) => {
+                revoke_badge(&mut connection, &config, user_id, badge, Utc::now()).await?
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the badges the user gained or lost. Losing the roles granting a badge on one guild only
+/// revokes the badge if no role on another guild grants it.
+async fn badge_changes<'a>(
+    discord: &dyn DiscordApi,
+    config: &'a BadgeConfig,
+    guild_id: GuildId,
+    user_id: UserId,
+    old_roles: Option<&[RoleId]>,
+    new_roles: &[RoleId],
+) -> Result<Vec<BadgeChange<'a>>, Error> {
+    let new_badges = config.badges_for_roles(guild_id, new_roles);
+    let old_badges = old_roles.map(|old_roles| config.badges_for_roles(guild_id, old_roles));
+
+    let mut changes = Vec::new();
+    for badge in config.badges_of_guild(guild_id) {
+        let has_badge = new_badges.contains(badge);
+        let had_badge = old_badges.as_ref().map(|old| old.contains(badge));
+
+        match (had_badge, has_badge) {
+            (Some(false) | None, true) => changes.push(BadgeChange::Grant(badge)),
+            (Some(true) | None, false)
+                if !holds_badge(discord, config, user_id, badge, Some(guild_id)).await? =>
+            {
+                changes.push(BadgeChange::Revoke(badge))
+            }
+            _ => {}
+        }
+    }
+
+    Ok(changes)
+}
+
+/// Returns whether the user holds a role granting the badge on any guild except `except_guild_id`.
+async fn holds_badge(
+    discord: &dyn DiscordApi,
+    config: &BadgeConfig,
+    user_id: UserId,
+    badge: &str,
+    except_guild_id: Option<GuildId>,
+) -> Result<bool, Error> {
+    for guild_id in config.guilds_granting(badge) {
+        if Some(guild_id) == except_guild_id {
+            continue;
+        }
+
+        if let Some(member) = discord.get_member(guild_id, user_id).await? {
+            if config
+                .badges_for_roles(guild_id, &member.roles)
+                .contains(badge)
+            {
+                return Ok(true);
+            }
+        }
+    }
+
+    Ok(false)
+}
+
+/// Adds the badge or cancels its pending revocation.
+async fn add_badge(
+    connection: &mut AsyncPgConnection,
+    user_id: UserId,
+    badge: &str,
+) -> Result<(), Error> {
+    let res = diesel::insert_into(user_badge::table)
+        .values(NewUserBadge {
+            user_id: user_id.get().into(),
+            badge: badge.to_string(),
+        })
+        .on_conflict_do_nothing()
+        .execute(connection)
+        .await?;
+
+    if res > 0 {
+        tracing::info!("User {} has been granted the {badge} badge", user_id);
+    } else if cancel_badge_revocations(connection, &[user_id.into()], badge).await? > 0 {
+        tracing::info!(
+            "Cancelled pending revocation of the {badge} badge for user {}",
+            user_id
+        );
+    }
+
+    Ok(())
+}
+
+/// Removes the badge or, if a grace period is configured, marks it as pending revocation, see
+/// [`finalise_badge_revocations`].
+async fn revoke_badge(
+    connection: &mut AsyncPgConnection,
+    config: &BadgeConfig,
+    user_id: UserId,
+    badge: &str,
+    now: DateTime<Utc>,
+) -> Result<(), Error> {
+    if config.revocation_grace_period > chrono::Duration::zero() {
+        let revocation_deadline = now + config.revocation_grace_period;
+        let res = diesel::update(user_badge::table)
+            .filter(user_badge::user_id.eq(Snowflake::from(user_id)))
+            .filter(user_badge::badge.eq(badge))
+            .filter(user_badge::revocation_deadline.is_null())
+            .set(user_badge::revocation_deadline.eq(revocation_deadline))
+            .execute(connection)
+            .await?;
+        if res > 0 {
+            tracing::info!(
+                "The {badge} badge of user {} will be revoked at {revocation_deadline}",
+                user_id
+            );
+        }
+
+        return Ok(());
+    }
+
+    let res = diesel::delete(user_badge::table)
+        .filter(user_badge::user_id.eq(Snowflake::from(user_id)))
+        .filter(user_badge::badge.eq(badge))
+        .execute(connection)
+        .await?;
+
+    if res > 0 {
+        tracing::info!("User {} has lost the {badge} badge", user_id);
+    }
+
+    Ok(())
+}
+
+async fn cancel_badge_revocations(
+    connection: &mut AsyncPgConnection,
+    user_ids: &[Snowflake],
+    badge: &str,
+) -> Result<usize, Error> {
+    let res = diesel::update(user_badge::table)
+        .filter(user_badge::revocation_deadline.is_not_null())
+        .filter(user_badge::user_id.eq_any(user_ids))
+        .filter(user_badge::badge.eq(badge))
+        .set(user_badge::revocation_deadline.eq(None::<DateTime<Utc>>))
+        .execute(connection)
+        .await?;
+    Ok(res)
+}
+
+/// Removes badges whose revocation grace period has passed, unless the user has regained a role
+/// granting the badge on any guild in the meantime.
+pub async fn finalise_badge_revocations() -> Result<(), Error> {
+    let config = BadgeConfig::from_env();
+    let now = Utc::now();
+    let mut connection = acquire_db_connection().await?;
+    let due_revocations = user_badge::table
+        .filter(user_badge::revocation_deadline.le(now))
+        .load::<UserBadge>(&mut connection)
+        .await?;
+    drop(connection);
+
+    for user_badge in due_revocations {
+        let user_id = match UserId::try_from(user_badge.user_id) {
+            Ok(user_id) => user_id,
+            Err(e) => {
+                tracing::warn!("Skipping pending badge revocation: {e}");
+                continue;
+            }
+        };
+        // roles are checked without holding a connection
+        let holds_badge = holds_badge(
+            &*app_context().http,
+            &config,
+            user_id,
+            &user_badge.badge,
+            None,
+        )
+        .await?;
+
+        let mut connection = acquire_db_connection().await?;
+        finalise_badge_revocation(&mut connection, &user_badge, holds_badge, now).await?;
+    }
+
+    Ok(())
+}
+
+async fn finalise_badge_revocation(
+    connection: &mut AsyncPgConnection,
+    user_badge: &UserBadge,
+    holds_badge: bool,
+    now: DateTime<Utc>,
+) -> Result<(), Error> {
+    let badge = &user_badge.badge;
+    if holds_badge {
+        if cancel_badge_revocations(connection, &[user_badge.user_id], badge).await? > 0 {
+            tracing::info!(
+                "Cancelled pending revocation of the {badge} badge for user {}",
+                user_badge.user_id
+            );
+        }
+        return Ok(());
+    }
+
+    let res = diesel::delete(user_badge::table)
+        .filter(user_badge::user_id.eq(user_badge.user_id))
+        .filter(user_badge::badge.eq(badge))
+        .filter(user_badge::revocation_deadline.le(now))
+        .execute(connection)
+        .await?;
+    if res > 0 {
+        tracing::info!(
+            "User {} has lost the {badge} badge after the revocation grace period",
+            user_badge.user_id
+        );
+    }
+
+    Ok(())
+}
+
+/// Reconciles the user_badge table with the current holders of each badge, as collected from the
+/// member lists of all guilds with tracked roles. Badges of users that are no longer holders are
+/// revoked after the grace period.
+pub async fn sync_badge_holders(
+    config: &BadgeConfig,
+    holders: HashMap<&str, HashSet<UserId>>,
+) -> Result<(), Error> {
+    let mut connection = acquire_db_connection().await?;
+    sync_badges(&mut connection, config, holders, Utc::now()).await
+}
+
+async fn sync_badges(
+    connection: &mut AsyncPgConnection,
+    config: &BadgeConfig,
+    holders: HashMap<&str, HashSet<UserId>>,
+    now: DateTime<Utc>,
+) -> Result<(), Error> {
+    for (badge, holders) in holders {
+        let user_ids = holders
+            .iter()
+            .map(|user_id| Snowflake::from(*user_id))
+            .collect::<Vec<_>>();
+
+        let new_badges = user_ids
+            .iter()
+            .map(|user_id| NewUserBadge {
+                user_id: *user_id,
+                badge: badge.to_string(),
+            })
+            .collect::<Vec<_>>();
+        // split items into chunks to avoid hitting the parameter limit
+        let mut res = 0;
+        for badge_chunk in new_badges.chunks(4096) {
+            res += diesel::insert_into(user_badge::table)
+                .values(badge_chunk)
+                .on_conflict_do_nothing()
+                .execute(connection)
+                .await?;
+        }
+        if res > 0 {
+            tracing::info!("Granted the {badge} badge to {res} users");
+        }
+
+        let res = cancel_badge_revocations(connection, &user_ids, badge).await?;
+        if res > 0 {
+            tracing::info!("Cancelled {res} pending revocations of the {badge} badge");
+        }
+
+        if config.revocation_grace_period > chrono::Duration::zero() {
+            let res = diesel::update(user_badge::table)
+                .filter(user_badge::badge.eq(badge))
+                .filter(user_badge::user_id.ne_all(&user_ids))
+                .filter(user_badge::revocation_deadline.is_null())
+                .set(user_badge::revocation_deadline.eq(now + config.revocation_grace_period))
+                .execute(connection)
+                .await?;
+            if res > 0 {
+                tracing::info!("Scheduled revocation of the {badge} badge of {res} users");
+            }
+        } else {
+            let res = diesel::delete(user_badge::table)
+                .filter(user_badge::badge.eq(badge))
+                .filter(user_badge::user_id.ne_all(&user_ids))
+                .execute(connection)
+                .await?;
+            if res > 0 {
+                tracing::info!("Removed the {badge} badge from {res} users");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use diesel::OptionalExtension;
+
+    use super::*;
+    use crate::{discord::fake::*, test_db::test_connection};
+
+    const CONTRIBUTOR_ROLE: u64 = 10;
+    const TRANSLATOR_ROLE: u64 = 11;
+    const OTHER_GUILD_CONTRIBUTOR_ROLE: u64 = 20;
+
+    fn config(revocation_grace_period_hours: i64) -> BadgeConfig {
+        let badge_role = |badge: &str, guild_id, role_id| BadgeRole {
+            badge: badge.to_string(),
+            guild_id,
+            role_id,
+        };
+        BadgeConfig {
+            badge_roles: vec![
+                badge_role("contributor", 1, CONTRIBUTOR_ROLE),
+                badge_role("translator", 1, TRANSLATOR_ROLE),
+                badge_role("contributor", 2, OTHER_GUILD_CONTRIBUTOR_ROLE),
+            ],
+            revocation_grace_period: chrono::Duration::hours(revocation_grace_period_hours),
+        }
+    }
+
+    fn roles(role_ids: &[u64]) -> Vec<RoleId> {
+        role_ids.iter().copied().map(RoleId::new).collect()
+    }
+
+    async fn changes(
+        discord: &FakeDiscord,
+        config: &BadgeConfig,
+        old_roles: Option<&[u64]>,
+        new_roles: &[u64],
+    ) -> (Vec<String>, Vec<String>) {
+        let old_roles = old_roles.map(roles);
+        let changes = badge_changes(
+            discord,
+            config,
+            GuildId::new(1),
+            UserId::new(2),
+            old_roles.as_deref(),
+            &roles(new_roles),
+        )
+        .await
+        .unwrap();
+
+        let (mut granted, mut revoked) = (Vec::new(), Vec::new());
+        for change in changes {
+            match change {
+                BadgeChange::Grant(badge) => granted.push(badge.to_string()),
+                BadgeChange::Revoke(badge) => revoked.push(badge.to_string()),
+            }
+        }
+        granted.sort();
+        revoked.sort();
+        (granted, revoked)
+    }
+
+    async fn badge(
+        connection: &mut AsyncPgConnection,
+        user_id: u64,
+        badge: &str,
+    ) -> Option<UserBadge> {
+        user_badge::table
+            .filter(user_badge::user_id.eq(Snowflake::from(user_id)))
+            .filter(user_badge::badge.eq(badge))
+            .get_result(connection)
+            .await
+            .optional()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn gaining_and_losing_badge_roles_changes_badges() {
+        let discord = FakeDiscord::default();
+
+        assert_eq!(
+            changes(&discord, &config(0), Some(&[]), &[CONTRIBUTOR_ROLE]).await,
+            (vec![String::from("contributor")], vec![])
+        );
+        assert_eq!(
+            changes(&discord, &config(0), Some(&[TRANSLATOR_ROLE]), &[]).await,
+            (vec![], vec![String::from("translator")])
+        );
+        assert_eq!(
+            changes(
+                &discord,
+                &config(0),
+                Some(&[TRANSLATOR_ROLE]),
+                &[TRANSLATOR_ROLE]
+            )
+            .await,
+            (vec![], vec![])
+        );
+    }
+
+    #[tokio::test]
+    async fn losing_badge_role_keeps_badge_granted_on_other_guild() {
+        // the fake returns the member for any guild, holding the role of the other guild
+        let discord = FakeDiscord::with_members([member(2, &[OTHER_GUILD_CONTRIBUTOR_ROLE])]);
+
+        assert_eq!(
+            changes(&discord, &config(0), Some(&[CONTRIBUTOR_ROLE]), &[]).await,
+            (vec![], vec![])
+        );
+        assert_eq!(
+            changes(&discord, &config(0), None, &[]).await,
+            (vec![], vec![String::from("translator")])
+        );
+    }
+
+    #[tokio::test]
+    async fn losing_badge_role_on_all_guilds_revokes_badge() {
+        let discord = FakeDiscord::with_members([member(2, &[])]);
+
+        assert_eq!(
+            changes(&discord, &config(0), Some(&[CONTRIBUTOR_ROLE]), &[]).await,
+            (vec![], vec![String::from("contributor")])
+        );
+    }
+
+    #[tokio::test]
+    async fn revoked_badge_is_kept_during_grace_period() {
+        let Some(mut connection) = test_connection().await else {
+            return;
+        };
+        let now = Utc::now();
+        add_badge(&mut connection, UserId::new(2), "contributor")
+            .await
+            .unwrap();
+
+        revoke_badge(
+            &mut connection,
+            &config(24),
+            UserId::new(2),
+            "contributor",
+            now,
+        )
+        .await
+        .unwrap();
+        let user_badge = badge(&mut connection, 2, "contributor").await.unwrap();
+        assert!(user_badge.revocation_deadline.unwrap() > now + chrono::Duration::hours(23));
+
+        add_badge(&mut connection, UserId::new(2), "contributor")
+            .await
+            .unwrap();
+        let user_badge = badge(&mut connection, 2, "contributor").await.unwrap();
+        assert!(user_badge.revocation_deadline.is_none());
+
+        revoke_badge(
+            &mut connection,
+            &config(0),
+            UserId::new(2),
+            "contributor",
+            now,
+        )
+        .await
+        .unwrap();
+        assert!(badge(&mut connection, 2, "contributor").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn finalising_revocation_removes_badge_unless_held_again() {
+        let Some(mut connection) = test_connection().await else {
+            return;
+        };
+        let now = Utc::now();
+        for user_id in [2, 3] {
+            add_badge(&mut connection, UserId::new(user_id), "contributor")
+                .await
+                .unwrap();
+            revoke_badge(
+                &mut connection,
+                &config(24),
+                UserId::new(user_id),
+                "contributor",
+                now - chrono::Duration::hours(25),
+            )
+            .await
+            .unwrap();
+        }
+
+        let user_badge = badge(&mut connection, 2, "contributor").await.unwrap();
+        finalise_badge_revocation(&mut connection, &user_badge, false, now)
+            .await
+            .unwrap();
+        let user_badge = badge(&mut connection, 3, "contributor").await.unwrap();
+        finalise_badge_revocation(&mut connection, &user_badge, true, now)
+            .await
+            .unwrap();
+
+        assert!(badge(&mut connection, 2, "contributor").await.is_none());
+        let user_badge = badge(&mut connection, 3, "contributor").await.unwrap();
+        assert!(user_badge.revocation_deadline.is_none());
+    }
+
+    #[tokio::test]
+    async fn sync_schedules_revocation_of_former_holders() {
+        let Some(mut connection) = test_connection().await else {
+            return;
+        };
+        let now = Utc::now();
+        add_badge(&mut connection, UserId::new(2), "contributor")
+            .await
+            .unwrap();
+        add_badge(&mut connection, UserId::new(3), "contributor")
+            .await
+            .unwrap();
+        revoke_badge(
+            &mut connection,
+            &config(24),
+            UserId::new(3),
+            "contributor",
+            now,
+        )
+        .await
+        .unwrap();
+
+        let holders = HashMap::from([(
+            "contributor",
+            HashSet::from([UserId::new(3), UserId::new(4)]),
+        )]);
+        sync_badges(&mut connection, &config(24), holders, now)
+            .await
+            .unwrap();
+
+        let user_badge = badge(&mut connection, 2, "contributor").await.unwrap();
+        assert!(user_badge.revocation_deadline.is_some());
+        let user_badge = badge(&mut connection, 3, "contributor").await.unwrap();
+        assert!(user_badge.revocation_deadline.is_none());
+        assert!(badge(&mut connection, 4, "contributor").await.is_some());
+
+        let holders = HashMap::from([("contributor", HashSet::from([UserId::new(4)]))]);
+        sync_badges(&mut connection, &config(0), holders, now)
+            .await
+            .unwrap();
+
+        assert!(badge(&mut connection, 2, "contributor").await.is_none());
+        assert!(badge(&mut connection, 3, "contributor").await.is_none());
+    }
+}
@@ -0,0 +1,246 @@
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use futures::StreamExt;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Deserializer};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    sync::{broadcast, mpsc},
+};
+use tokio_postgres::{AsyncMessage, Client, Connection, NoTls, Notification};
+
+use crate::{make_rustls_connect, snowflake::Snowflake, DATABASE_URL, PG_ENABLE_SSL};
+
+/// Channel notified by the `notify_supporter_change` trigger.
+const SUPPORTER_CHANGES_CHANNEL: &str = "supporter_changes";
+
+/// Delay before reconnecting after the listener connection has been lost.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+static LISTENING: AtomicBool = AtomicBool::new(false);
+
+lazy_static! {
+    static ref CHANGE_EVENTS: broadcast::Sender<ChangeEvent> = broadcast::channel(1024).0;
+}
+
+/// A change to a row of a supporter table, made by any instance or manually.
+#[derive(Clone, Debug, Deserialize)]
+pub struct SupporterChange {
+    pub table: String,
+    /// `INSERT`, `UPDATE`, `DELETE` or `TRUNCATE`.
+    pub operation: String,
+    /// The user of the changed row, `None` if the table has been truncated.
+    #[serde(deserialize_with = "deserialize_user_id")]
+    pub user_id: Option<Snowflake>,
+}
+
+/// The trigger sends the `BIGINT` user_id column as text, which is negative for ids of 2^63 and
+/// above, see [`Snowflake`].
+fn deserialize_user_id<'de, D>(deserializer: D) -> Result<Option<Snowflake>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(|user_id| {
+            user_id
+                .parse::<i64>()
+                .map(Snowflake::from_bigint)
+                .map_err(serde::de::Error::custom)
+        })
+        .transpose()
+}
+
+#[derive(Clone, Debug)]
+pub enum ChangeEvent {
+    Changed(SupporterChange),
+    /// Changes may have been missed, e.g. while the listener was reconnecting, so everything derived
+    /// from the supporter tables should be reloaded.
+    Reset,
+}
+
+/// Subscribes to changes of the supporter tables. A subscriber that falls behind misses events and
+/// should handle [`broadcast::error::RecvError::Lagged`] like [`ChangeEvent::Reset`].
+pub fn subscribe() -> broadcast::Receiver<ChangeEvent> {
+    CHANGE_EVENTS.subscribe()
+}
+
+/// Whether the listener is currently connected, changes are not received while it is not.
+pub fn is_listening() -> bool {
+    LISTENING.load(Ordering::Relaxed)
+}
+
+/// Listens for notifications of the supporter tables on a dedicated connection and forwards them to
+/// subscribers, reconnecting if the connection is lost.
+pub async fn run_listener() {
+    loop {
+        match listen().await {
+            Ok((_client, mut notifications)) => {
+                tracing::info!("Listening for changes on channel {SUPPORTER_CHANGES_CHANNEL}");
+                LISTENING.store(true, Ordering::Relaxed);
+                // changes made before listening or while reconnecting have not been received
+                publish(ChangeEvent::Reset);
+
+                while let Some(notification) = notifications.recv().await {
+                    publish(change_event(notification.payload()));
+                }
+                LISTENING.store(false, Ordering::Relaxed);
+                tracing::warn!("Lost change feed connection, reconnecting in {RECONNECT_DELAY:?}");
+            }
+            Err(e) => tracing::error!(
+                "Failed to listen for supporter changes, retrying in {RECONNECT_DELAY:?}: {e}"
+            ),
+        }
+
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+/// Parses the payload of a notification. A payload that cannot be parsed is published as
+/// [`ChangeEvent::Reset`], as the change it describes would be missed otherwise.
+fn change_event(payload: &str) -> ChangeEvent {
+    match serde_json::from_str::<SupporterChange>(payload) {
+        Ok(change) => {
+            tracing::debug!("Received supporter change {change:?}");
+            ChangeEvent::Changed(change)
+        }
+        Err(e) => {
+            tracing::error!("Failed to parse supporter change notification '{payload}': {e}");
+            ChangeEvent::Reset
+        }
+    }
+}
+
+fn publish(event: ChangeEvent) {
+    // without subscribers there is no cache the event could apply to
+    let _ = CHANGE_EVENTS.send(event);
+}
+
+/// Connects to the database outside of the connection pool, using TLS if `GLYPH_PG_ENABLE_SSL` is set,
+/// and starts listening. The connection is closed once the returned client is dropped.
+async fn listen() -> Result<(Client, mpsc::UnboundedReceiver<Notification>), tokio_postgres::Error>
+{
+    let (sender, receiver) = mpsc::unbounded_channel();
+    let client = if *PG_ENABLE_SSL {
+        let (client, connection) =
+            tokio_postgres::connect(&DATABASE_URL, make_rustls_connect()).await?;
+        tokio::spawn(forward_notifications(connection, sender));
+        client
+    } else {
+        let (client, connection) = tokio_postgres::connect(&DATABASE_URL, NoTls).await?;
+        tokio::spawn(forward_notifications(connection, sender));
+        client
+    };
+
+    client
+        .batch_execute(&format!("LISTEN {SUPPORTER_CHANGES_CHANNEL}"))
+        .await?;
+    Ok((client, receiver))
+}
+
+/// Drives the connection, forwarding its notifications until it is closed.
+async fn forward_notifications<S, T>(
+    mut connection: Connection<S, T>,
+    sender: mpsc::UnboundedSender<Notification>,
+) where
+    S: AsyncRead + AsyncWrite + Unpin,
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut messages = futures::stream::poll_fn(move |cx| connection.poll_message(cx));
+    while let Some(message) = messages.next().await {
+        match message {
+            Ok(AsyncMessage::Notification(notification)) => {
+                if sender.send(notification).is_err() {
+                    return;
+                }
+            }
+            Ok(AsyncMessage::Notice(notice)) => {
+                tracing::info!("Change feed connection notice: {notice}")
+            }
+            Ok(_) => {}
+            Err(e) => {
+                tracing::error!("Change feed connection failed: {e}");
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_db::test_connection;
+
+    #[test]
+    fn user_ids_above_i64_max_are_parsed() {
+        let payload = r#"{"table": "aiode_supporter", "operation": "INSERT", "user_id": "-2"}"#;
+
+        let ChangeEvent::Changed(change) = change_event(payload) else {
+            panic!("payload has not been parsed");
+        };
+        assert_eq!(change.user_id, Some(Snowflake(u64::MAX - 1)));
+    }
+
+    #[test]
+    fn unparseable_payload_is_a_reset() {
+        assert!(matches!(
+            change_event(
+                r#"{"table": "aiode_supporter", "operation": "INSERT", "user_id": "abc"}"#
+            ),
+            ChangeEvent::Reset
+        ));
+        assert!(matches!(change_event("{"), ChangeEvent::Reset));
+    }
+
+    #[test]
+    fn truncation_has_no_user_id() {
+        let payload = r#"{"table": "aiode_supporter", "operation": "TRUNCATE", "user_id": null}"#;
+
+        let ChangeEvent::Changed(change) = change_event(payload) else {
+            panic!("payload has not been parsed");
+        };
+        assert!(change.user_id.is_none());
+    }
+
+    #[tokio::test]
+    async fn aiode_supporter_insert_is_notified() {
+        // runs the migrations, the notification is only sent once the insert is committed though
+        if test_connection().await.is_none() {
+            return;
+        }
+        let database_url = std::env::var("GLYPH_TEST_DATABASE_URL").unwrap();
+        let (client, connection) = tokio_postgres::connect(&database_url, NoTls).await.unwrap();
+        let (sender, mut notifications) = mpsc::unbounded_channel();
+        tokio::spawn(forward_notifications(connection, sender));
+        client
+            .batch_execute(&format!("LISTEN {SUPPORTER_CHANGES_CHANNEL}"))
+            .await
+            .unwrap();
+
+        // the row is deleted within the same transaction so that other tests never see it
+        let user_id = Snowflake(u64::MAX - 1);
+        client
+            .batch_execute(&format!(
+                "BEGIN;
+                INSERT INTO aiode_supporter (user_id, source) VALUES ({0}, 'voucher');
+                DELETE FROM aiode_supporter WHERE user_id = {0};
+                COMMIT;",
+                user_id.to_bigint()
+            ))
+            .await
+            .unwrap();
+
+        let notification = tokio::time::timeout(Duration::from_secs(5), notifications.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        let ChangeEvent::Changed(change) = change_event(notification.payload()) else {
+            panic!("notification has not been parsed");
+        };
+        assert_eq!(change.table, "aiode_supporter");
+        assert_eq!(change.operation, "INSERT");
+        assert_eq!(change.user_id, Some(user_id));
+    }
+}
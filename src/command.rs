@@ -0,0 +1,323 @@
+use serenity::all::{
+    CommandInteraction, CommandOptionType, Context, CreateCommand, CreateCommandOption,
+    CreateInteractionResponse, CreateInteractionResponseMessage, GuildId, RoleId, UserId,
+};
+
+use crate::{
+    account::{self, LINKABLE_PROVIDERS},
+    credits,
+    error::Error,
+    gift::{self, Gifter},
+    voucher::{self, RedeemVoucherResult},
+    AIODE_SUPPORT_GUILD_ID, BOT_ADMIN_ROLE_IDS, BOT_ADMIN_USER_IDS, MODULES,
+};
+
+/// Users allowed to use the admin options of slash commands, see `GLYPH_BOT_ADMIN_USER_IDS` and
+/// `GLYPH_BOT_ADMIN_ROLE_IDS`. Guild permissions are not considered as the bot is used on guilds
+/// whose administrators are not bot admins.
+pub struct BotAdmins {
+    pub user_ids: Vec<UserId>,
+    pub role_ids: Vec<RoleId>,
+    pub support_guild_id: Option<GuildId>,
+}
+
+impl BotAdmins {
+    pub fn from_env() -> Self {
+        Self {
+            user_ids: BOT_ADMIN_USER_IDS
+                .iter()
+                .copied()
+                .map(UserId::new)
+                .collect(),
+            role_ids: BOT_ADMIN_ROLE_IDS
+                .iter()
+                .copied()
+                .map(RoleId::new)
+                .collect(),
+            support_guild_id: AIODE_SUPPORT_GUILD_ID.map(GuildId::new),
+        }
+    }
+
+    /// Returns whether the user invoking the command is a bot admin. Admin roles only count if the
+    /// command has been invoked on the support guild.
+    pub fn is_admin(&self, user_id: UserId, guild_id: Option<GuildId>, roles: &[RoleId]) -> bool {
+        self.user_ids.contains(&user_id)
+            || (guild_id.is_some() && guild_id == self.support_guild_id)
+                && roles.iter().any(|role_id| self.role_ids.contains(role_id))
+    }
+}
+
+pub fn create_link_command() -> CreateCommand {
+    let provider_option = LINKABLE_PROVIDERS.iter().fold(
+        CreateCommandOption::new(
+            CommandOptionType::String,
+            "provider",
+            "The service to link your discord account with",
+        )
+        .required(true),
+        |option, provider| option.add_string_choice(*provider, *provider),
+    );
+
+    CreateCommand::new("link")
+        .description("Link your discord account with an external account")
+        .add_option(provider_option)
+}
+
+pub fn create_redeem_command() -> CreateCommand {
+    CreateCommand::new("redeem")
+        .description("Redeem a supporter voucher code")
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::String, "code", "The voucher code")
+                .required(true),
+        )
+}
+
+pub fn create_gift_command() -> CreateCommand {
+    CreateCommand::new("gift")
+        .description("Gift supporter status to another user")
+        .add_option(
+            CreateCommandOption::new(
+                CommandOptionType::User,
+                "user",
+                "The user to gift supporter status to",
+            )
+            .required(true),
+        )
+        .add_option(
+            CreateCommandOption::new(
+                CommandOptionType::Integer,
+                "days",
+                "Duration of the gift in days, bot admins only",
+            )
+            .min_int_value(1),
+        )
+}
+
+pub fn create_credits_command() -> CreateCommand {
+    CreateCommand::new("credits")
+        .description("Choose whether you are listed on the public aiode supporter credits")
+        .add_option(
+            CreateCommandOption::new(
+                CommandOptionType::Boolean,
+                "show",
+                "Whether to show your name and avatar in the supporter credits",
+            )
+            .required(true),
+        )
+}
+
+/// Creates the slash commands of all enabled modules, registered globally when the client is ready.
+pub fn create_commands() -> Vec<CreateCommand> {
+    MODULES
+        .iter()
+        .flat_map(|module| module.commands())
+        .collect()
+}
+
+pub async fn handle_command(ctx: &Context, command: &CommandInteraction) {
+    let mut result = None;
+    for module in MODULES.iter() {
+        result = module.run_command(ctx, command).await;
+        if result.is_some() {
+            break;
+        }
+    }
+    let Some(result) = result else {
+        tracing::warn!("Received unknown command {}", command.data.name);
+        return;
+    };
+
+    let content = match result {
+        Ok(content) => content,
+        Err(e) if e.status_code().is_client_error() => e.to_string(),
+        Err(e) => {
+            tracing::error!(
+                "An error occurred while handling command {} for user {}: {e}",
+                command.data.name,
+                command.user.id
+            );
+            String::from("Something went wrong, please try again later.")
+        }
+    };
+
+    if let Err(e) = command
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .content(content)
+                    .ephemeral(true),
+            ),
+        )
+        .await
+    {
+        tracing::error!(
+            "Failed to respond to command {} for user {}: {e}",
+            command.data.name,
+            command.user.id
+        );
+    }
+}
+
+pub async fn handle_link_command(command: &CommandInteraction) -> Result<String, Error> {
+    let provider = get_string_option(command, "provider")
+        .ok_or_else(|| Error::InvalidPayloadError(String::from("Missing provider")))?;
+
+    let code = account::create_link_code(command.user.id.get(), provider).await?;
+
+    Ok(format!(
+        "Your one-time code to link your {provider} account is `{code}`. Enter it on the {provider} side within {} minutes to complete the link.",
+        account::LINK_CODE_VALIDITY_MINUTES
+    ))
+}
+
+pub async fn handle_redeem_command(command: &CommandInteraction) -> Result<String, Error> {
+    let code = get_string_option(command, "code")
+        .ok_or_else(|| Error::InvalidPayloadError(String::from("Missing code")))?;
+
+    Ok(
+        match voucher::redeem_voucher(command.user.id.get(), code).await? {
+            RedeemVoucherResult::Redeemed {
+                tier,
+                supporter_until: Some(supporter_until),
+            } => format!(
+                "Voucher redeemed, you are a {tier} supporter until <t:{}:f>. Thank you!",
+                supporter_until.timestamp()
+            ),
+            RedeemVoucherResult::Redeemed {
+                supporter_until: None,
+                ..
+            } => {
+                String::from("Voucher redeemed, you already are a permanent supporter. Thank you!")
+            }
+            RedeemVoucherResult::AlreadyRedeemed => {
+                String::from("You have already redeemed this voucher.")
+            }
+            RedeemVoucherResult::Invalid => {
+                String::from("This voucher code is invalid, expired or has been used up.")
+            }
+        },
+    )
+}
+
+pub async fn handle_gift_command(command: &CommandInteraction) -> Result<String, Error> {
+    let recipient = command
+        .data
+        .options
+        .iter()
+        .find(|option| option.name == "user")
+        .and_then(|option| option.value.as_user_id())
+        .ok_or_else(|| Error::InvalidPayloadError(String::from("Missing user")))?;
+    let duration_days = command
+        .data
+        .options
+        .iter()
+        .find(|option| option.name == "days")
+        .and_then(|option| option.value.as_i64())
+        .map(u32::try_from)
+        .transpose()
+        .map_err(|e| Error::InvalidPayloadError(e.to_string()))?;
+
+    let roles = command
+        .member
+        .as_ref()
+        .map(|member| member.roles.as_slice())
+        .unwrap_or_default();
+    let gifter = if BotAdmins::from_env().is_admin(command.user.id, command.guild_id, roles) {
+        Gifter::Admin
+    } else if duration_days.is_some() {
+        return Err(Error::ForbiddenError(String::from(
+            "Only bot admins may choose the duration of a gift",
+        )));
+    } else {
+        Gifter::Supporter(command.user.id.get())
+    };
+
+    let result = gift::gift_supporter(gifter, recipient.get(), None, duration_days).await?;
+
+    let mut content = format!(
+        "You gifted {} supporter status to <@{recipient}> until <t:{}:f>.",
+        result.tier,
+        result.expiration_timestamp.timestamp()
+    );
+    if let Some(remaining_quota) = result.remaining_quota {
+        content.push_str(&format!(
+            " You have {remaining_quota} gifts remaining for the next {} days.",
+            gift::GIFT_QUOTA_PERIOD_DAYS
+        ));
+    }
+    Ok(content)
+}
+
+pub async fn handle_credits_command(command: &CommandInteraction) -> Result<String, Error> {
+    let show = command
+        .data
+        .options
+        .iter()
+        .find(|option| option.name == "show")
+        .and_then(|option| option.value.as_bool())
+        .ok_or_else(|| Error::InvalidPayloadError(String::from("Missing show")))?;
+
+    credits::set_credits_opt_in(command.user.id.get(), show).await?;
+
+    Ok(if show {
+        String::from(
+            "You will be listed in the supporter credits while you are a supporter. Thank you!",
+        )
+    } else {
+        String::from("You are no longer listed in the supporter credits.")
+    })
+}
+
+pub fn get_string_option<'a>(command: &'a CommandInteraction, name: &str) -> Option<&'a str> {
+    command
+        .data
+        .options
+        .iter()
+        .find(|option| option.name == name)
+        .and_then(|option| option.value.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bot_admins() -> BotAdmins {
+        BotAdmins {
+            user_ids: vec![UserId::new(1)],
+            role_ids: vec![RoleId::new(10)],
+            support_guild_id: Some(GuildId::new(100)),
+        }
+    }
+
+    #[test]
+    fn configured_users_are_admins_everywhere() {
+        assert!(bot_admins().is_admin(UserId::new(1), None, &[]));
+        assert!(bot_admins().is_admin(UserId::new(1), Some(GuildId::new(200)), &[]));
+        assert!(!bot_admins().is_admin(UserId::new(2), None, &[]));
+    }
+
+    #[test]
+    fn admin_roles_only_count_on_the_support_guild() {
+        let roles = [RoleId::new(11), RoleId::new(10)];
+
+        assert!(bot_admins().is_admin(UserId::new(2), Some(GuildId::new(100)), &roles));
+        assert!(!bot_admins().is_admin(UserId::new(2), Some(GuildId::new(200)), &roles));
+        assert!(!bot_admins().is_admin(UserId::new(2), None, &roles));
+        assert!(!bot_admins().is_admin(
+            UserId::new(2),
+            Some(GuildId::new(100)),
+            &[RoleId::new(11)]
+        ));
+    }
+
+    #[test]
+    fn admin_roles_are_ignored_without_support_guild() {
+        let bot_admins = BotAdmins {
+            support_guild_id: None,
+            ..bot_admins()
+        };
+
+        assert!(!bot_admins.is_admin(UserId::new(2), None, &[RoleId::new(10)]));
+    }
+}
@@ -0,0 +1,39 @@
+use std::sync::{Arc, OnceLock};
+
+use serenity::{cache::Cache, gateway::ShardManager, http::Http};
+
+static APP_CONTEXT: OnceLock<AppContext> = OnceLock::new();
+
+/// State shared by the discord client, the API and scheduled tasks. Using the client's `Http` shares
+/// its ratelimiter, and the cache gives access to live discord data kept up to date by the gateway.
+#[derive(Clone)]
+pub struct AppContext {
+    pub http: Arc<Http>,
+    pub cache: Arc<Cache>,
+    pub shard_manager: Arc<ShardManager>,
+}
+
+impl AppContext {
+    /// Shares the state of the client, which does not need to be connected to the gateway.
+    pub fn from_client(client: &serenity::Client) -> Self {
+        Self {
+            http: client.http.clone(),
+            cache: client.cache.clone(),
+            shard_manager: client.shard_manager.clone(),
+        }
+    }
+}
+
+/// Sets the application context once the discord client has been created.
+pub fn init_app_context(app_context: AppContext) {
+    if APP_CONTEXT.set(app_context).is_err() {
+        panic!("Application context has already been initialised");
+    }
+}
+
+/// Returns the application context, which is initialised before the API and tasks are started.
+pub fn app_context() -> &'static AppContext {
+    APP_CONTEXT
+        .get()
+        .expect("Application context has not been initialised")
+}
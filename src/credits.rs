@@ -0,0 +1,310 @@
+use std::{
+    collections::HashSet,
+    sync::{Arc, RwLock},
+};
+
+use chrono::{DateTime, Utc};
+use diesel::{BoolExpressionMethods, ExpressionMethods, JoinOnDsl, QueryDsl};
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use lazy_static::lazy_static;
+use serde::Serialize;
+use serenity::all::UserId;
+use tokio::sync::broadcast;
+use utoipa::ToSchema;
+use warp::{reject::Rejection, reply::Reply};
+
+use crate::{
+    acquire_db_connection,
+    change_feed::{ChangeEvent, SupporterChange},
+    context::app_context,
+    error::{Error, ErrorResponse},
+    model::NewSupporterCreditsOptIn,
+    schema::{aiode_supporter, supporter_credits_opt_in},
+    snowflake::Snowflake,
+};
+
+/// Lifetime of the credits response in public caches, the credits are refreshed every 15 minutes.
+const CREDITS_CACHE_MAX_AGE_SECONDS: u32 = 300;
+
+lazy_static! {
+    static ref SUPPORTER_CREDITS: RwLock<Option<Arc<SupporterCreditsResponse>>> =
+        RwLock::new(None);
+    /// Serialises opting out with publishing refreshed credits, so that a refresh that started
+    /// before a user opted out cannot publish them again.
+    static ref CREDITS_PUBLISH_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::new(());
+    /// Held while requests load the credits on demand, so that concurrent requests hitting unloaded
+    /// credits wait for a single refresh instead of each resolving all supporters.
+    static ref CREDITS_LOAD_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::new(());
+}
+
+#[derive(Clone, Serialize, ToSchema)]
+pub struct SupporterCredit {
+    pub user_id: String,
+    pub display_name: String,
+    pub avatar_url: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SupporterCreditsResponse {
+    pub supporters: Vec<SupporterCredit>,
+    pub refreshed_at: DateTime<Utc>,
+}
+
+/// Public endpoint listing the supporters that opted in to being credited. Serves the credits
+/// resolved by the last refresh, only loading them on demand if they have not been loaded or have
+/// been discarded, concurrent requests share a single load.
+#[utoipa::path(
+    get,
+    path = "/supporters/credits",
+    tag = "credits",
+    responses(
+        (status = 200, description = "Supporters that opted in to being credited", body = SupporterCreditsResponse),
+        (status = 500, description = "Credits could not be loaded", body = ErrorResponse),
+    ),
+)]
+pub async fn get_supporter_credits_handler() -> Result<impl Reply, Rejection> {
+    let supporter_credits = match loaded_supporter_credits() {
+        Some(supporter_credits) => supporter_credits,
+        None => load_supporter_credits().await?,
+    };
+
+    Ok(warp::reply::with_header(
+        warp::reply::json(&*supporter_credits),
+        "cache-control",
+        format!("public, max-age={CREDITS_CACHE_MAX_AGE_SECONDS}"),
+    ))
+}
+
+fn loaded_supporter_credits() -> Option<Arc<SupporterCreditsResponse>> {
+    SUPPORTER_CREDITS
+        .read()
+        .expect("supporter credits lock poisoned")
+        .clone()
+}
+
+async fn load_supporter_credits() -> Result<Arc<SupporterCreditsResponse>, Error> {
+    let _load_guard = CREDITS_LOAD_LOCK.lock().await;
+    // loaded by a concurrent request while waiting for the lock
+    if let Some(supporter_credits) = loaded_supporter_credits() {
+        return Ok(supporter_credits);
+    }
+    refresh_supporter_credits().await
+}
+
+/// Resolves display names and avatars of current supporters that opted in to being credited.
+pub async fn refresh_supporter_credits() -> Result<Arc<SupporterCreditsResponse>, Error> {
+    let mut connection = acquire_db_connection().await?;
+    let user_ids = credited_user_ids(&mut connection).await?;
+    drop(connection);
+
+    let serenity_http = &app_context().http;
+    let mut supporters = Vec::with_capacity(user_ids.len());
+    for user_id in user_ids {
+        let user = match UserId::try_from(user_id) {
+            Ok(user_id) => serenity_http.get_user(user_id).await,
+            Err(e) => {
+                tracing::warn!("Skipping supporter credit: {e}");
+                continue;
+            }
+        };
+        match user {
+            Ok(user) => supporters.push(SupporterCredit {
+                user_id: user.id.to_string(),
+                display_name: user
+                    .global_name
+                    .clone()
+                    .unwrap_or_else(|| user.name.clone()),
+                avatar_url: user.face(),
+            }),
+            Err(e) => tracing::warn!("Failed to resolve user {user_id} for supporter credits: {e}"),
+        }
+    }
+
+    // users may have opted out while their profiles were being resolved
+    let _publish_guard = CREDITS_PUBLISH_LOCK.lock().await;
+    let mut connection = acquire_db_connection().await?;
+    let opted_in_user_ids = supporter_credits_opt_in::table
+        .select(supporter_credits_opt_in::user_id)
+        .load::<Snowflake>(&mut connection)
+        .await?
+        .into_iter()
+        .map(|user_id| user_id.to_string())
+        .collect::<HashSet<_>>();
+    supporters.retain(|supporter| opted_in_user_ids.contains(&supporter.user_id));
+
+    let supporter_credits = Arc::new(SupporterCreditsResponse {
+        supporters,
+        refreshed_at: Utc::now(),
+    });
+    *SUPPORTER_CREDITS
+        .write()
+        .expect("supporter credits lock poisoned") = Some(supporter_credits.clone());
+
+    Ok(supporter_credits)
+}
+
+/// Returns the supporters that opted in to being credited, oldest supporters first. Supporters pending
+/// revocation or whose time limited status has expired are not credited.
+async fn credited_user_ids(connection: &mut AsyncPgConnection) -> Result<Vec<Snowflake>, Error> {
+    let now = Utc::now();
+    let user_ids = supporter_credits_opt_in::table
+        .inner_join(
+            aiode_supporter::table
+                .on(aiode_supporter::user_id.eq(supporter_credits_opt_in::user_id)),
+        )
+        .filter(aiode_supporter::revocation_deadline.is_null())
+        .filter(
+            aiode_supporter::expiration_timestamp
+                .is_null()
+                .or(aiode_supporter::expiration_timestamp.gt(now)),
+        )
+        .order(aiode_supporter::creation_timestamp)
+        .select(supporter_credits_opt_in::user_id)
+        .load::<Snowflake>(connection)
+        .await?;
+    Ok(user_ids)
+}
+
+/// Sets whether the user is listed in the public supporter credits. Opting out takes effect
+/// immediately, opting in with the next refresh.
+pub async fn set_credits_opt_in(user_id: u64, opt_in: bool) -> Result<(), Error> {
+    let mut connection = acquire_db_connection().await?;
+
+    if opt_in {
+        diesel::insert_into(supporter_credits_opt_in::table)
+            .values(NewSupporterCreditsOptIn {
+                user_id: user_id.into(),
+            })
+            .on_conflict_do_nothing()
+            .execute(&mut connection)
+            .await?;
+    } else {
+        let _publish_guard = CREDITS_PUBLISH_LOCK.lock().await;
+        diesel::delete(supporter_credits_opt_in::table)
+            .filter(supporter_credits_opt_in::user_id.eq(Snowflake::from(user_id)))
+            .execute(&mut connection)
+            .await?;
+
+        remove_supporter_credit(user_id);
+    }
+
+    tracing::info!("User {user_id} set supporter credits opt in to {opt_in}");
+    Ok(())
+}
+
+/// Keeps the loaded credits up to date with changes made by other instances or manually. Users that
+/// opted out or lost supporter status are removed right away, the loaded credits are discarded if
+/// changes may have been missed.
+pub async fn apply_supporter_changes(mut changes: broadcast::Receiver<ChangeEvent>) {
+    loop {
+        match changes.recv().await {
+            Ok(ChangeEvent::Changed(SupporterChange {
+                table,
+                operation,
+                user_id,
+            })) => {
+                if table != "aiode_supporter" && table != "supporter_credits_opt_in" {
+                    continue;
+                }
+                match (operation.as_str(), user_id) {
+                    ("DELETE", Some(user_id)) => remove_supporter_credit(user_id.get()),
+                    ("TRUNCATE", _) => discard_supporter_credits(),
+                    _ => {}
+                }
+            }
+            Ok(ChangeEvent::Reset) | Err(broadcast::error::RecvError::Lagged(_)) => {
+                discard_supporter_credits()
+            }
+            Err(broadcast::error::RecvError::Closed) => return,
+        }
+    }
+}
+
+fn remove_supporter_credit(user_id: u64) {
+    let mut supporter_credits = SUPPORTER_CREDITS
+        .write()
+        .expect("supporter credits lock poisoned");
+    if let Some(ref mut current) = *supporter_credits {
+        let user_id = user_id.to_string();
+        if current.supporters.iter().any(|s| s.user_id == user_id) {
+            *current = Arc::new(SupporterCreditsResponse {
+                supporters: current
+                    .supporters
+                    .iter()
+                    .filter(|s| s.user_id != user_id)
+                    .cloned()
+                    .collect(),
+                refreshed_at: current.refreshed_at,
+            });
+        }
+    }
+}
+
+/// Discards the loaded credits so that they are loaded again when requested next.
+fn discard_supporter_credits() {
+    *SUPPORTER_CREDITS
+        .write()
+        .expect("supporter credits lock poisoned") = None;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        aiode::SUPPORTER_SOURCE_ROLE,
+        model::{NewAiodeSupporter, NewTimeLimitedAiodeSupporter},
+        test_db::test_connection,
+    };
+
+    async fn opt_in(connection: &mut AsyncPgConnection, user_id: u64) {
+        diesel::insert_into(supporter_credits_opt_in::table)
+            .values(NewSupporterCreditsOptIn {
+                user_id: user_id.into(),
+            })
+            .execute(connection)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn only_current_supporters_that_opted_in_are_credited() {
+        let Some(mut connection) = test_connection().await else {
+            return;
+        };
+        diesel::insert_into(aiode_supporter::table)
+            .values(
+                [1, 2, 3]
+                    .map(|user_id: u64| NewAiodeSupporter {
+                        user_id: user_id.into(),
+                        source: SUPPORTER_SOURCE_ROLE.to_string(),
+                    })
+                    .as_slice(),
+            )
+            .execute(&mut connection)
+            .await
+            .unwrap();
+        diesel::insert_into(aiode_supporter::table)
+            .values(NewTimeLimitedAiodeSupporter {
+                user_id: 4.into(),
+                tier: None,
+                expiration_timestamp: Utc::now() - chrono::Duration::hours(1),
+                source: SUPPORTER_SOURCE_ROLE.to_string(),
+            })
+            .execute(&mut connection)
+            .await
+            .unwrap();
+        diesel::update(aiode_supporter::table)
+            .filter(aiode_supporter::user_id.eq(Snowflake::from(2)))
+            .set(aiode_supporter::revocation_deadline.eq(Utc::now() + chrono::Duration::hours(1)))
+            .execute(&mut connection)
+            .await
+            .unwrap();
+        for user_id in [1, 2, 4, 5] {
+            opt_in(&mut connection, user_id).await;
+        }
+
+        let user_ids = credited_user_ids(&mut connection).await.unwrap();
+
+        assert_eq!(user_ids, vec![Snowflake::from(1)]);
+    }
+}
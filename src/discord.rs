@@ -0,0 +1,196 @@
+use serenity::{
+    all::{GuildId, Member, RoleId, UserId},
+    async_trait,
+    http::Http,
+};
+
+use crate::error::Error;
+
+/// The discord operations used by the supporter logic, implemented by serenity's [`Http`] client and
+/// by an in-memory fake in tests.
+#[async_trait]
+pub trait DiscordApi: Send + Sync {
+    /// Fetches a page of at most `limit` guild members with an id greater than `after`, ordered by id.
+    async fn get_guild_members(
+        &self,
+        guild_id: GuildId,
+        limit: u64,
+        after: Option<UserId>,
+    ) -> Result<Vec<Member>, Error>;
+
+    /// Fetches the guild member, returns `None` if the user is not a member of the guild.
+    async fn get_member(&self, guild_id: GuildId, user_id: UserId)
+        -> Result<Option<Member>, Error>;
+
+    async fn add_member_role(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        role_id: RoleId,
+        reason: &str,
+    ) -> Result<(), Error>;
+
+    async fn remove_member_role(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        role_id: RoleId,
+        reason: &str,
+    ) -> Result<(), Error>;
+}
+
+#[async_trait]
+impl DiscordApi for Http {
+    async fn get_guild_members(
+        &self,
+        guild_id: GuildId,
+        limit: u64,
+        after: Option<UserId>,
+    ) -> Result<Vec<Member>, Error> {
+        Ok(Http::get_guild_members(self, guild_id, Some(limit), after.map(UserId::get)).await?)
+    }
+
+    async fn get_member(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+    ) -> Result<Option<Member>, Error> {
+        match Http::get_member(self, guild_id, user_id).await {
+            Ok(member) => Ok(Some(member)),
+            Err(serenity::Error::Http(e))
+                if e.status_code().map(|status| status.as_u16()) == Some(404) =>
+            {
+                Ok(None)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn add_member_role(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        role_id: RoleId,
+        reason: &str,
+    ) -> Result<(), Error> {
+        Ok(Http::add_member_role(self, guild_id, user_id, role_id, Some(reason)).await?)
+    }
+
+    async fn remove_member_role(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        role_id: RoleId,
+        reason: &str,
+    ) -> Result<(), Error> {
+        Ok(Http::remove_member_role(self, guild_id, user_id, role_id, Some(reason)).await?)
+    }
+}
+
+#[cfg(test)]
+pub mod fake {
+    use std::{collections::BTreeMap, sync::Mutex};
+
+    use super::*;
+
+    /// In-memory [`DiscordApi`] holding the members of a single guild.
+    #[derive(Default)]
+    pub struct FakeDiscord {
+        pub members: Mutex<BTreeMap<UserId, Member>>,
+        /// The `after` argument of each requested member page.
+        pub member_pages: Mutex<Vec<Option<UserId>>>,
+        /// Fail member page requests after this many pages.
+        pub fail_after_pages: Option<usize>,
+    }
+
+    /// Returns a member of the fake guild with the given roles.
+    pub fn member(user_id: u64, roles: &[u64]) -> Member {
+        let mut member = Member::default();
+        member.user.id = UserId::new(user_id);
+        member.roles = roles.iter().copied().map(RoleId::new).collect();
+        member
+    }
+
+    impl FakeDiscord {
+        pub fn with_members(members: impl IntoIterator<Item = Member>) -> Self {
+            Self {
+                members: Mutex::new(
+                    members
+                        .into_iter()
+                        .map(|member| (member.user.id, member))
+                        .collect(),
+                ),
+                ..Default::default()
+            }
+        }
+
+        pub fn roles(&self, user_id: UserId) -> Vec<RoleId> {
+            self.members.lock().unwrap()[&user_id].roles.clone()
+        }
+    }
+
+    #[async_trait]
+    impl DiscordApi for FakeDiscord {
+        async fn get_guild_members(
+            &self,
+            _guild_id: GuildId,
+            limit: u64,
+            after: Option<UserId>,
+        ) -> Result<Vec<Member>, Error> {
+            let mut member_pages = self.member_pages.lock().unwrap();
+            if self
+                .fail_after_pages
+                .is_some_and(|pages| member_pages.len() >= pages)
+            {
+                return Err(Error::SerenityError(serenity::Error::Other("fake failure")));
+            }
+            member_pages.push(after);
+
+            Ok(self
+                .members
+                .lock()
+                .unwrap()
+                .values()
+                .filter(|member| after.is_none_or(|after| member.user.id > after))
+                .take(limit as usize)
+                .cloned()
+                .collect())
+        }
+
+        async fn get_member(
+            &self,
+            _guild_id: GuildId,
+            user_id: UserId,
+        ) -> Result<Option<Member>, Error> {
+            Ok(self.members.lock().unwrap().get(&user_id).cloned())
+        }
+
+        async fn add_member_role(
+            &self,
+            _guild_id: GuildId,
+            user_id: UserId,
+            role_id: RoleId,
+            _reason: &str,
+        ) -> Result<(), Error> {
+            if let Some(member) = self.members.lock().unwrap().get_mut(&user_id) {
+                if !member.roles.contains(&role_id) {
+                    member.roles.push(role_id);
+                }
+            }
+            Ok(())
+        }
+
+        async fn remove_member_role(
+            &self,
+            _guild_id: GuildId,
+            user_id: UserId,
+            role_id: RoleId,
+            _reason: &str,
+        ) -> Result<(), Error> {
+            if let Some(member) = self.members.lock().unwrap().get_mut(&user_id) {
+                member.roles.retain(|role| *role != role_id);
+            }
+            Ok(())
+        }
+    }
+}
@@ -1,10 +1,24 @@
 use serde::Serialize;
 use thiserror::Error;
+use tracing::Span;
+use utoipa::ToSchema;
 use warp::{
-    http::{header, Response},
-    hyper::StatusCode,
-    reject::{Reject, Rejection},
+    body::BodyDeserializeError,
+    filters::BoxedFilter,
+    http::{header, HeaderValue, Response},
+    hyper::{Body, StatusCode},
+    reject::{
+        InvalidHeader, InvalidQuery, LengthRequired, MethodNotAllowed, MissingHeader,
+        PayloadTooLarge, Reject, Rejection, UnsupportedMediaType,
+    },
     reply::Reply,
+    Filter,
+};
+
+use crate::{
+    alert,
+    module::Route,
+    request_id::{request_id, RequestId, REQUEST_ID_HEADER},
 };
 
 #[allow(clippy::enum_variant_names)]
@@ -18,6 +32,30 @@ pub enum Error {
     SerenityError(serenity::Error),
     #[error("Failed to serialise data: {0}")]
     SerialisationError(String),
+    #[error("Invalid request payload: {0}")]
+    InvalidPayloadError(String),
+    #[error("Webhook signature could not be verified")]
+    WebhookSignatureError,
+    #[error("Missing or invalid authorization")]
+    UnauthorizedError,
+    #[error("Forbidden: {0}")]
+    ForbiddenError(String),
+    #[error("Bad request: {0}")]
+    BadRequestError(String),
+    #[error("Conflict: {0}")]
+    ConflictError(String),
+    #[error("Not found")]
+    NotFoundError,
+    #[error("Method not allowed")]
+    MethodNotAllowedError,
+    #[error("Request payload too large")]
+    PayloadTooLargeError,
+    #[error("Too many requests, try again later")]
+    TooManyRequestsError,
+    #[error("Not available on a standby instance, try again later")]
+    StandbyError,
+    #[error("Unhandled rejection: {0}")]
+    UnhandledRejectionError(String),
 }
 
 impl Error {
@@ -26,7 +64,17 @@ impl Error {
             Self::DatabaseConnectionError(_)
             | Self::QueryError(_)
             | Self::SerenityError(_)
-            | Self::SerialisationError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            | Self::SerialisationError(_)
+            | Self::UnhandledRejectionError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::InvalidPayloadError(_) | Self::BadRequestError(_) => StatusCode::BAD_REQUEST,
+            Self::WebhookSignatureError | Self::UnauthorizedError => StatusCode::UNAUTHORIZED,
+            Self::ForbiddenError(_) => StatusCode::FORBIDDEN,
+            Self::NotFoundError => StatusCode::NOT_FOUND,
+            Self::ConflictError(_) => StatusCode::CONFLICT,
+            Self::MethodNotAllowedError => StatusCode::METHOD_NOT_ALLOWED,
+            Self::PayloadTooLargeError => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::TooManyRequestsError => StatusCode::TOO_MANY_REQUESTS,
+            Self::StandbyError => StatusCode::SERVICE_UNAVAILABLE,
         }
     }
 
@@ -36,6 +84,18 @@ impl Error {
             Self::QueryError(_) => 500_002,
             Self::SerenityError(_) => 500_003,
             Self::SerialisationError(_) => 500_004,
+            Self::UnhandledRejectionError(_) => 500_005,
+            Self::InvalidPayloadError(_) => 400_001,
+            Self::BadRequestError(_) => 400_002,
+            Self::WebhookSignatureError => 401_001,
+            Self::UnauthorizedError => 401_002,
+            Self::ForbiddenError(_) => 403_001,
+            Self::NotFoundError => 404_001,
+            Self::MethodNotAllowedError => 405_001,
+            Self::ConflictError(_) => 409_001,
+            Self::PayloadTooLargeError => 413_001,
+            Self::TooManyRequestsError => 429_001,
+            Self::StandbyError => 503_001,
         }
     }
 }
@@ -54,42 +114,240 @@ impl From<serenity::Error> for Error {
 
 impl Reject for Error {}
 
-#[derive(Serialize, Debug)]
-struct ErrorResponse {
-    message: String,
-    status: String,
-    error_code: u32,
+/// Completes the responses of the routes, converting all rejections to an [`ErrorResponse`] and
+/// adding the request id header.
+pub fn recover_with_request_id(routes: Route) -> BoxedFilter<(Response<Body>,)> {
+    request_id()
+        .and(
+            routes
+                .map(reply_to_response)
+                .or_else(|rejection| async move { Ok::<_, Rejection>((Err(rejection),)) }),
+        )
+        .map(
+            |request_id: RequestId, result: Result<Response<Body>, Rejection>| {
+                let mut response = match result {
+                    Ok(response) => response,
+                    Err(rejection) => handle_rejection(&request_id, rejection),
+                };
+                Span::current().record("status", response.status().as_u16());
+                if let Ok(header_value) = HeaderValue::from_str(request_id.as_str()) {
+                    response
+                        .headers_mut()
+                        .insert(REQUEST_ID_HEADER, header_value);
+                }
+                response
+            },
+        )
+        .boxed()
+}
+
+/// The elided lifetime keeps the function general enough for the server future to be `Send`.
+fn reply_to_response(reply: Box<dyn Reply + '_>) -> Result<Response<Body>, Rejection> {
+    Ok(reply.into_response())
 }
 
-/// Creates a Rejection response for the given error and logs internal server errors.
-pub async fn handle_rejection(err: Rejection) -> Result<impl Reply, Rejection> {
-    if let Some(e) = err.find::<Error>() {
-        let status_code = e.status_code();
-        let message = e.to_string();
-        let error_code = e.error_code();
+/// Body of all error responses of the API.
+#[derive(Serialize, Debug, ToSchema)]
+pub struct ErrorResponse {
+    pub message: String,
+    /// The HTTP status, e.g. `404 Not Found`.
+    #[schema(example = "404 Not Found")]
+    pub status: String,
+    /// Stable code identifying the error, its first three digits are the HTTP status.
+    #[schema(example = 404001)]
+    pub error_code: u32,
+    /// Id of the request, also returned in the `x-request-id` header.
+    pub request_id: String,
+}
 
-        if let StatusCode::INTERNAL_SERVER_ERROR = status_code {
-            log::error!("Encountered internal server error: {}", e);
+/// Converts the rejection to an [`ErrorResponse`], including warp's own rejections, e.g. for unknown
+/// paths or unsupported methods, and logs internal server errors.
+fn handle_rejection(request_id: &RequestId, err: Rejection) -> Response<Body> {
+    let warp_error;
+    let error = match err.find::<Error>() {
+        Some(e) => e,
+        None => {
+            warp_error = warp_rejection_to_error(&err);
+            &warp_error
         }
+    };
+    let status_code = error.status_code();
+
+    if let StatusCode::INTERNAL_SERVER_ERROR = status_code {
+        tracing::error!("Encountered internal server error handling request {request_id}: {error}");
+        alert::report_error(
+            format!("api:{}", error.error_code()),
+            format!("Request {request_id} failed: {error}"),
+        );
+    }
+
+    let err_response = ErrorResponse {
+        message: error.to_string(),
+        status: status_code.to_string(),
+        error_code: error.error_code(),
+        request_id: request_id.to_string(),
+    };
+
+    let body = match serde_json::to_vec(&err_response) {
+        Ok(body) => body,
+        Err(e) => {
+            tracing::error!("Failed to serialise error response: {e}");
+            Vec::new()
+        }
+    };
+
+    let mut response = Response::new(Body::from(body));
+    *response.status_mut() = status_code;
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    response
+}
 
-        let err_response = ErrorResponse {
-            message,
-            status: status_code.to_string(),
-            error_code,
-        };
-
-        let response_builder = Response::builder()
-            .status(status_code)
-            .header(header::CONTENT_TYPE, "application/json");
-        let response = response_builder
-            .body(
-                serde_json::to_vec(&err_response)
-                    .map_err(|e| Error::SerialisationError(e.to_string()))?,
-            )
-            .map_err(|e| Error::SerialisationError(e.to_string()))?;
-
-        Ok(response)
+/// Maps warp's built-in rejections to the corresponding client errors. Rejections combined from
+/// several routes are mapped to the most specific error, e.g. an invalid payload takes precedence
+/// over a method that is only supported by another route.
+fn warp_rejection_to_error(err: &Rejection) -> Error {
+    if err.find::<PayloadTooLarge>().is_some() {
+        Error::PayloadTooLargeError
+    } else if let Some(e) = err.find::<BodyDeserializeError>() {
+        Error::InvalidPayloadError(e.to_string())
+    } else if let Some(e) = err.find::<MissingHeader>() {
+        Error::BadRequestError(e.to_string())
+    } else if let Some(e) = err.find::<InvalidHeader>() {
+        Error::BadRequestError(e.to_string())
+    } else if let Some(e) = err.find::<InvalidQuery>() {
+        Error::BadRequestError(e.to_string())
+    } else if let Some(e) = err.find::<LengthRequired>() {
+        Error::BadRequestError(e.to_string())
+    } else if let Some(e) = err.find::<UnsupportedMediaType>() {
+        Error::BadRequestError(e.to_string())
+    } else if err.find::<MethodNotAllowed>().is_some() {
+        Error::MethodNotAllowedError
+    } else if err.is_not_found() {
+        // also covers path parameters that could not be parsed, e.g. a user id that is not a number
+        Error::NotFoundError
     } else {
-        Err(err)
+        Error::UnhandledRejectionError(format!("{err:?}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::Value;
+    use warp::test::request;
+
+    use super::*;
+    use crate::module::route;
+
+    fn routes() -> Route {
+        route(
+            warp::path!("users" / u64)
+                .and(warp::get())
+                .map(|user_id: u64| user_id.to_string()),
+        )
+        .or(route(
+            warp::path!("users")
+                .and(warp::post())
+                .and(warp::body::content_length_limit(64))
+                .and(warp::body::json())
+                .map(|body: HashMap<String, u64>| warp::reply::json(&body)),
+        ))
+        .unify()
+        .or(route(warp::path!("admin").and_then(|| async {
+            Err::<String, Rejection>(Error::UnauthorizedError.into())
+        })))
+        .unify()
+        .boxed()
+    }
+
+    async fn error_response(request: warp::test::RequestBuilder) -> (StatusCode, Value) {
+        let response = request.reply(&recover_with_request_id(routes())).await;
+        let request_id = response.headers()[REQUEST_ID_HEADER].to_str().unwrap();
+        let body = serde_json::from_slice::<Value>(response.body()).unwrap();
+        assert_eq!(body["request_id"], request_id);
+        (response.status(), body)
+    }
+
+    #[tokio::test]
+    async fn successful_response_carries_request_id() {
+        let response = request()
+            .path("/users/1")
+            .header(REQUEST_ID_HEADER, "abc-123")
+            .reply(&recover_with_request_id(routes()))
+            .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[REQUEST_ID_HEADER], "abc-123");
+    }
+
+    #[tokio::test]
+    async fn invalid_request_id_is_replaced() {
+        let response = request()
+            .path("/users/1")
+            .header(REQUEST_ID_HEADER, "not a valid id")
+            .reply(&recover_with_request_id(routes()))
+            .await;
+
+        assert_ne!(response.headers()[REQUEST_ID_HEADER], "not a valid id");
+    }
+
+    #[tokio::test]
+    async fn unknown_path_is_not_found() {
+        let (status, body) = error_response(request().path("/unknown")).await;
+
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["error_code"], 404_001);
+    }
+
+    #[tokio::test]
+    async fn invalid_path_parameter_is_not_found() {
+        let (status, body) = error_response(request().path("/users/abc")).await;
+
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["error_code"], 404_001);
+    }
+
+    #[tokio::test]
+    async fn unsupported_method_is_not_allowed() {
+        let (status, body) = error_response(request().method("DELETE").path("/users")).await;
+
+        assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(body["error_code"], 405_001);
+    }
+
+    #[tokio::test]
+    async fn invalid_body_is_bad_request() {
+        let (status, body) =
+            error_response(request().method("POST").path("/users").body("{")).await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error_code"], 400_001);
+    }
+
+    #[tokio::test]
+    async fn oversized_body_is_rejected() {
+        let (status, body) = error_response(
+            request()
+                .method("POST")
+                .path("/users")
+                .body(format!("{{\"a\": {}}}", "1".repeat(100))),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(body["error_code"], 413_001);
+    }
+
+    #[tokio::test]
+    async fn custom_error_takes_precedence() {
+        let (status, body) = error_response(request().path("/admin")).await;
+
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body["error_code"], 401_002);
+        assert_eq!(body["status"], "401 Unauthorized");
     }
 }
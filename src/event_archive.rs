@@ -0,0 +1,370 @@
+use std::{
+    future::Future,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use serenity::all::{GuildId, GuildMemberUpdateEvent, Member, User};
+use tokio::{
+    fs::{File, OpenOptions},
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    sync::mpsc,
+};
+
+use crate::{
+    event_handler, util::Worker, EVENT_ARCHIVE_DIR, EVENT_ARCHIVE_EVENTS,
+    EVENT_ARCHIVE_RETENTION_DAYS,
+};
+
+const ARCHIVE_FILE_PREFIX: &str = "gateway-events-";
+const ARCHIVE_FILE_EXTENSION: &str = ".jsonl";
+
+static ARCHIVE_WRITER: Worker<ArchivedEvent> = Worker::new("Event archive writer");
+
+/// A gateway event handled by the modules, including the cached state serenity passes to the event
+/// handler, so that it can be replayed through [`event_handler::dispatch_event`].
+#[allow(clippy::large_enum_variant)] // events are short lived, boxing would only add noise
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum GatewayEvent {
+    GuildMemberAddition {
+        member: Member,
+    },
+    GuildMemberRemoval {
+        guild_id: GuildId,
+        user: User,
+    },
+    GuildMemberUpdate {
+        old: Option<Member>,
+        new: Option<Member>,
+        event: GuildMemberUpdateEvent,
+    },
+}
+
+impl GatewayEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::GuildMemberAddition { .. } => "guild_member_addition",
+            Self::GuildMemberRemoval { .. } => "guild_member_removal",
+            Self::GuildMemberUpdate { .. } => "guild_member_update",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ArchivedEvent {
+    pub received_at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub event: GatewayEvent,
+}
+
+/// Starts writing archived events to `GLYPH_EVENT_ARCHIVE_DIR`, if set.
+pub fn start_event_archive() {
+    let Some(ref archive_dir) = *EVENT_ARCHIVE_DIR else {
+        return;
+    };
+
+    tracing::info!(
+        "Archiving gateway events {:?} to {archive_dir:?}",
+        *EVENT_ARCHIVE_EVENTS
+    );
+    ARCHIVE_WRITER.start(|events| write_events(archive_dir.clone(), events));
+}
+
+/// Archives the event if the archive is enabled and the event is selected by
+/// `GLYPH_EVENT_ARCHIVE_EVENTS`.
+pub fn archive_event(event: &GatewayEvent) {
+    // checked first so that events are not cloned while the archive is disabled
+    if !ARCHIVE_WRITER.is_started() || !EVENT_ARCHIVE_EVENTS.iter().any(|name| name == event.name())
+    {
+        return;
+    }
+
+    ARCHIVE_WRITER.send(ArchivedEvent {
+        received_at: Utc::now(),
+        event: event.clone(),
+    });
+}
+
+/// Appends the events to one file per day, deleting files older than the retention period whenever a
+/// new file is started.
+async fn write_events(archive_dir: PathBuf, mut events: mpsc::UnboundedReceiver<ArchivedEvent>) {
+    if let Err(e) = tokio::fs::create_dir_all(&archive_dir).await {
+        tracing::error!("Failed to create event archive directory {archive_dir:?}: {e}");
+    }
+
+    let mut current_file: Option<(NaiveDate, File)> = None;
+    while let Some(archived_event) = events.recv().await {
+        let date = archived_event.received_at.date_naive();
+        if current_file.as_ref().map(|(file_date, _)| *file_date) != Some(date) {
+            current_file = None;
+            delete_expired_files(&archive_dir, date, *EVENT_ARCHIVE_RETENTION_DAYS).await;
+            let path = archive_dir.join(format!(
+                "{ARCHIVE_FILE_PREFIX}{date}{ARCHIVE_FILE_EXTENSION}"
+            ));
+            match OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .await
+            {
+                Ok(file) => current_file = Some((date, file)),
+                Err(e) => tracing::error!("Failed to open event archive file {path:?}: {e}"),
+            }
+        }
+        let Some((_, ref mut file)) = current_file else {
+            continue;
+        };
+
+        let mut line = match serde_json::to_vec(&archived_event) {
+            Ok(line) => line,
+            Err(e) => {
+                tracing::error!("Failed to serialise archived event: {e}");
+                continue;
+            }
+        };
+        line.push(b'\n');
+        if let Err(e) = file.write_all(&line).await {
+            tracing::error!("Failed to write archived event: {e}");
+        } else if let Err(e) = file.flush().await {
+            tracing::error!("Failed to flush event archive file: {e}");
+        }
+    }
+}
+
+async fn delete_expired_files(archive_dir: &Path, today: NaiveDate, retention_days: u32) {
+    let oldest_retained_date = today - chrono::Duration::days(retention_days.into());
+
+    let mut entries = match tokio::fs::read_dir(archive_dir).await {
+        Ok(entries) => entries,
+        Err(e) => {
+            tracing::error!("Failed to list event archive directory {archive_dir:?}: {e}");
+            return;
+        }
+    };
+
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let file_name = entry.file_name();
+        let Some(date) = file_name
+            .to_str()
+            .and_then(|name| name.strip_prefix(ARCHIVE_FILE_PREFIX))
+            .and_then(|name| name.strip_suffix(ARCHIVE_FILE_EXTENSION))
+            .and_then(|date| date.parse::<NaiveDate>().ok())
+        else {
+            continue;
+        };
+
+        if date < oldest_retained_date {
+            match tokio::fs::remove_file(entry.path()).await {
+                Ok(()) => tracing::info!("Deleted expired event archive file {:?}", entry.path()),
+                Err(e) => tracing::error!(
+                    "Failed to delete expired event archive file {:?}: {e}",
+                    entry.path()
+                ),
+            }
+        }
+    }
+}
+
+/// Feeds the events archived in the given files back through the modules' event hooks, in order.
+/// Returns the number of replayed events.
+pub async fn replay_events(paths: &[String]) -> Result<usize, std::io::Error> {
+    replay_events_to(paths, |event| async move {
+        event_handler::dispatch_event(&event).await
+    })
+    .await
+}
+
+async fn replay_events_to<F, Fut>(
+    paths: &[String],
+    mut dispatch: F,
+) -> Result<usize, std::io::Error>
+where
+    F: FnMut(GatewayEvent) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    let mut replayed_events = 0;
+    for path in paths {
+        tracing::info!("Replaying events archived in {path}");
+        let mut lines = BufReader::new(File::open(path).await?).lines();
+        let mut line_number = 0;
+        while let Some(line) = lines.next_line().await? {
+            line_number += 1;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            match serde_json::from_str::<ArchivedEvent>(&line) {
+                Ok(archived_event) => {
+                    tracing::debug!(
+                        "Replaying {} received at {}",
+                        archived_event.event.name(),
+                        archived_event.received_at
+                    );
+                    dispatch(archived_event.event).await;
+                    replayed_events += 1;
+                }
+                Err(e) => tracing::error!("Skipping invalid event at {path}:{line_number}: {e}"),
+            }
+        }
+    }
+
+    Ok(replayed_events)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use serenity::all::UserId;
+
+    use super::*;
+    use crate::{discord::fake::member, util};
+
+    /// Creates an empty directory that is removed when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "glyph-event-archive-test-{}",
+                util::generate_code(16)
+            ));
+            std::fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+
+        fn write(&self, name: &str, contents: &str) -> String {
+            let path = self.0.join(name);
+            std::fs::write(&path, contents).unwrap();
+            path.to_string_lossy().into_owned()
+        }
+
+        fn file_names(&self) -> Vec<String> {
+            let mut file_names = std::fs::read_dir(&self.0)
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+                .collect::<Vec<_>>();
+            file_names.sort();
+            file_names
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn archived(event: GatewayEvent) -> String {
+        serde_json::to_string(&ArchivedEvent {
+            received_at: Utc::now(),
+            event,
+        })
+        .unwrap()
+    }
+
+    fn removal(user_id: u64) -> GatewayEvent {
+        GatewayEvent::GuildMemberRemoval {
+            guild_id: GuildId::new(1),
+            user: member(user_id, &[]).user,
+        }
+    }
+
+    #[test]
+    fn archived_events_round_trip() {
+        let mut new = member(2, &[3]);
+        new.guild_id = GuildId::new(1);
+        let event = serde_json::from_value::<GuildMemberUpdateEvent>(serde_json::json!({
+            "guild_id": "1",
+            "user": serde_json::to_value(&new.user).unwrap(),
+            "roles": ["3"],
+            "joined_at": "2024-01-01T00:00:00Z",
+        }))
+        .unwrap();
+        let archived_event = ArchivedEvent {
+            received_at: Utc::now(),
+            event: GatewayEvent::GuildMemberUpdate {
+                old: Some(member(2, &[])),
+                new: Some(new),
+                event,
+            },
+        };
+
+        let json = serde_json::to_value(&archived_event).unwrap();
+        assert_eq!(json["type"], "guild_member_update");
+        let deserialised = serde_json::from_value::<ArchivedEvent>(json.clone()).unwrap();
+
+        assert_eq!(deserialised.received_at, archived_event.received_at);
+        assert_eq!(deserialised.event.name(), "guild_member_update");
+        assert_eq!(serde_json::to_value(&deserialised).unwrap(), json);
+    }
+
+    #[tokio::test]
+    async fn deletes_archive_files_older_than_the_retention_period() {
+        let archive_dir = TempDir::new();
+        for name in [
+            "gateway-events-2024-06-01.jsonl",
+            "gateway-events-2024-06-07.jsonl",
+            "gateway-events-2024-06-08.jsonl",
+            "gateway-events-2024-06-15.jsonl",
+            "gateway-events-invalid.jsonl",
+            "notes.txt",
+        ] {
+            archive_dir.write(name, "");
+        }
+
+        let today = NaiveDate::from_ymd_opt(2024, 6, 15).unwrap();
+        delete_expired_files(&archive_dir.0, today, 7).await;
+
+        assert_eq!(
+            archive_dir.file_names(),
+            vec![
+                "gateway-events-2024-06-08.jsonl",
+                "gateway-events-2024-06-15.jsonl",
+                "gateway-events-invalid.jsonl",
+                "notes.txt",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn replays_valid_events_in_order() {
+        let archive_dir = TempDir::new();
+        let first = archive_dir.write(
+            "first.jsonl",
+            &format!("{}\n\n{{\"type\":\"unknown\"}}\n", archived(removal(1))),
+        );
+        let second = archive_dir.write(
+            "second.jsonl",
+            &format!("{}\n{}", archived(removal(2)), archived(removal(3))),
+        );
+        let replayed = Mutex::new(Vec::new());
+
+        let replayed_events = replay_events_to(&[first, second], |event| {
+            if let GatewayEvent::GuildMemberRemoval { user, .. } = event {
+                replayed.lock().unwrap().push(user.id);
+            }
+            async {}
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(replayed_events, 3);
+        assert_eq!(
+            *replayed.lock().unwrap(),
+            [1, 2, 3].map(UserId::new).to_vec()
+        );
+    }
+
+    #[tokio::test]
+    async fn replay_fails_if_a_file_cannot_be_read() {
+        let archive_dir = TempDir::new();
+        let missing = archive_dir.0.join("missing.jsonl");
+
+        let res = replay_events_to(&[missing.to_string_lossy().into_owned()], |_| async {}).await;
+
+        assert!(res.is_err());
+    }
+}
@@ -1,26 +1,82 @@
-use bigdecimal::BigDecimal;
-use diesel::ExpressionMethods;
-use diesel_async::RunQueryDsl;
 use serenity::{
-    all::{Context, EventHandler, GuildMemberUpdateEvent, Member, Ready},
+    all::{
+        Command, Context, EventHandler, Guild, GuildId, GuildMemberUpdateEvent,
+        GuildMembersChunkEvent, Interaction, Member, Ready, User, UserId,
+    },
     async_trait,
 };
+use tracing::{Instrument, Span};
 
 use crate::{
-    acquire_db_connection, error::Error, model::NewAiodeSupporter, schema::aiode_supporter,
-    AIODE_SUPPORTER_ROLE_ID, AIODE_SUPPORT_GUILD_ID,
+    command,
+    error::Error,
+    event_archive::{self, GatewayEvent},
+    guild,
+    module::Module,
+    MODULES,
 };
 
 pub struct DiscordEventHandler;
 
 #[async_trait]
 impl EventHandler for DiscordEventHandler {
-    async fn ready(&self, _ctx: Context, data_about_bot: Ready) {
-        log::info!("Serenity client connected with data {data_about_bot:?}");
+    async fn ready(&self, ctx: Context, data_about_bot: Ready) {
+        tracing::info!("Serenity client connected with data {data_about_bot:?}");
+
+        if let Err(e) = Command::set_global_commands(&ctx.http, command::create_commands()).await {
+            tracing::error!("Failed to register slash commands: {e}");
+        }
+    }
+
+    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        if let Interaction::Command(command) = interaction {
+            let span = event_span(
+                "interaction_create",
+                command.guild_id,
+                Some(command.user.id),
+            );
+            span.record("command", command.data.name.as_str());
+            command::handle_command(&ctx, &command)
+                .instrument(span)
+                .await;
+        }
     }
 
     async fn shards_ready(&self, _ctx: Context, total_shards: u32) {
-        log::info!("All {total_shards} shards are ready.");
+        tracing::info!("All {total_shards} shards are ready.");
+    }
+
+    async fn guild_create(&self, _ctx: Context, guild: Guild, _is_new: Option<bool>) {
+        async {
+            for module in MODULES.iter() {
+                log_module_error(
+                    module.as_ref(),
+                    "GuildCreate",
+                    module.guild_create(&guild).await,
+                );
+            }
+        }
+        .instrument(event_span("guild_create", Some(guild.id), None))
+        .await;
+    }
+
+    async fn guild_member_addition(&self, _ctx: Context, new_member: Member) {
+        handle_event(GatewayEvent::GuildMemberAddition { member: new_member }).await;
+    }
+
+    async fn guild_member_removal(
+        &self,
+        _ctx: Context,
+        guild_id: GuildId,
+        user: User,
+        _member_data_if_available: Option<Member>,
+    ) {
+        handle_event(GatewayEvent::GuildMemberRemoval { guild_id, user }).await;
+    }
+
+    async fn guild_members_chunk(&self, _ctx: Context, chunk: GuildMembersChunkEvent) {
+        // completes member requests of the refresh tasks, independent of the enabled modules
+        guild::handle_members_chunk(&chunk);
     }
 
     async fn guild_member_update(
@@ -30,104 +86,74 @@ impl EventHandler for DiscordEventHandler {
         new: Option<Member>,
         event: GuildMemberUpdateEvent,
     ) {
-        if AIODE_SUPPORT_GUILD_ID.is_some()
-            && AIODE_SUPPORTER_ROLE_ID.is_some()
-            && event.guild_id == AIODE_SUPPORT_GUILD_ID.unwrap()
-        {
-            let user_id = event.user.id;
-            log::debug!("Received GuildMemberUpdateEvent for user {} on guild {}. Old: {old_if_available:?}, new: {new:?}, event: {event:?}", user_id, event.guild_id);
-            if let Err(e) = handle_member_update(old_if_available, new, event).await {
-                log::error!(
-                    "An error occurred while handling a GuildMemberUpdateEvent for user {}: {e}",
-                    user_id
-                );
-            }
-        }
+        handle_event(GatewayEvent::GuildMemberUpdate {
+            old: old_if_available,
+            new,
+            event,
+        })
+        .await;
     }
 }
 
-async fn handle_member_update(
-    old: Option<Member>,
-    new: Option<Member>,
-    event: GuildMemberUpdateEvent,
-) -> Result<(), Error> {
-    if old.is_none() || new.is_none() {
-        let mut connection = acquire_db_connection().await?;
-        if event
-            .roles
-            .iter()
-            .any(|role| *role == AIODE_SUPPORTER_ROLE_ID.unwrap())
-        {
-            let res = diesel::insert_into(aiode_supporter::table)
-                .values(NewAiodeSupporter {
-                    user_id: event.user.id.get().into(),
-                })
-                .on_conflict_do_nothing()
-                .execute(&mut connection)
-                .await?;
-
-            if res > 0 {
-                log::info!(
-                    "User {} has been added to the aiode_supporter table",
-                    event.user.id
-                );
+async fn handle_event(event: GatewayEvent) {
+    event_archive::archive_event(&event);
+    dispatch_event(&event).await;
+}
+
+/// Passes the event to the hooks of all enabled modules, used for live and replayed events.
+pub async fn dispatch_event(event: &GatewayEvent) {
+    let (guild_id, user_id) = match event {
+        GatewayEvent::GuildMemberAddition { member } => (member.guild_id, member.user.id),
+        GatewayEvent::GuildMemberRemoval { guild_id, user } => (*guild_id, user.id),
+        GatewayEvent::GuildMemberUpdate { event, .. } => (event.guild_id, event.user.id),
+    };
+    dispatch_to_modules(event)
+        .instrument(event_span(event.name(), Some(guild_id), Some(user_id)))
+        .await;
+}
+
+async fn dispatch_to_modules(event: &GatewayEvent) {
+    for module in MODULES.iter() {
+        let result = match event {
+            GatewayEvent::GuildMemberAddition { member } => {
+                module.guild_member_addition(member).await
             }
-        } else {
-            let res = diesel::delete(aiode_supporter::table)
-                .filter(aiode_supporter::user_id.eq::<BigDecimal>(event.user.id.get().into()))
-                .execute(&mut connection)
-                .await?;
-
-            if res > 0 {
-                log::info!(
-                    "User {} has been removed from the aiode_supporter table",
-                    event.user.id
-                );
+            GatewayEvent::GuildMemberRemoval { guild_id, user } => {
+                module.guild_member_removal(*guild_id, user).await
             }
-        }
-    } else {
-        let old_has_role = old
-            .unwrap()
-            .roles
-            .iter()
-            .any(|role| *role == AIODE_SUPPORTER_ROLE_ID.unwrap());
-        let new_has_role = new
-            .unwrap()
-            .roles
-            .iter()
-            .any(|role| *role == AIODE_SUPPORTER_ROLE_ID.unwrap());
-
-        if old_has_role && !new_has_role {
-            let mut connection = acquire_db_connection().await?;
-            let res = diesel::delete(aiode_supporter::table)
-                .filter(aiode_supporter::user_id.eq::<BigDecimal>(event.user.id.get().into()))
-                .execute(&mut connection)
-                .await?;
-
-            if res > 0 {
-                log::info!(
-                    "User {} has been removed from the aiode_supporter table",
-                    event.user.id
-                );
+            GatewayEvent::GuildMemberUpdate { old, new, event } => {
+                module
+                    .guild_member_update(old.as_ref(), new.as_ref(), event)
+                    .await
             }
-        } else if !old_has_role && new_has_role {
-            let mut connection = acquire_db_connection().await?;
-            let res = diesel::insert_into(aiode_supporter::table)
-                .values(NewAiodeSupporter {
-                    user_id: event.user.id.get().into(),
-                })
-                .on_conflict_do_nothing()
-                .execute(&mut connection)
-                .await?;
-
-            if res > 0 {
-                log::info!(
-                    "User {} has been added to the aiode_supporter table",
-                    event.user.id
-                );
-            }
-        }
+        };
+        log_module_error(module.as_ref(), event.name(), result);
     }
+}
 
-    Ok(())
+/// Creates the span of a gateway event, correlating everything logged while handling it.
+fn event_span(event: &str, guild_id: Option<GuildId>, user_id: Option<UserId>) -> Span {
+    let span = tracing::info_span!(
+        "gateway_event",
+        event,
+        guild_id = tracing::field::Empty,
+        user_id = tracing::field::Empty,
+        command = tracing::field::Empty,
+    );
+    if let Some(guild_id) = guild_id {
+        span.record("guild_id", guild_id.get());
+    }
+    if let Some(user_id) = user_id {
+        span.record("user_id", user_id.get());
+    }
+    span
+}
+
+fn log_module_error(module: &dyn Module, event_name: &str, result: Result<(), Error>) {
+    if let Err(e) = result {
+        tracing::error!(
+            "An error occurred while handling {event_name} in module {}: {e}",
+            module.name()
+        );
+    }
 }
@@ -0,0 +1,428 @@
+use chrono::{DateTime, Utc};
+use diesel::{ExpressionMethods, OptionalExtension, QueryDsl};
+use diesel_async::{
+    scoped_futures::ScopedFutureExt, AsyncConnection, AsyncPgConnection, RunQueryDsl,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use warp::{reject::Rejection, reply::Reply};
+
+use crate::{
+    acquire_db_connection,
+    aiode::{
+        assign_aiode_supporter_role, upsert_time_limited_supporter, DEFAULT_SUPPORTER_TIER,
+        SUPPORTER_SOURCE_GIFT,
+    },
+    error::{Error, ErrorResponse},
+    model::{AiodeSupporter, NewSupporterGift, SupporterGift},
+    schema::{aiode_supporter, supporter_gift},
+    snowflake::Snowflake,
+    GIFT_DURATION_DAYS, GIFT_QUOTAS,
+};
+
+/// The gift quota of a tier applies to a rolling window of this many days.
+pub const GIFT_QUOTA_PERIOD_DAYS: i64 = 30;
+
+pub enum Gifter {
+    /// Admins may gift any tier and duration without being limited by a quota.
+    Admin,
+    Supporter(u64),
+}
+
+pub struct GiftResult {
+    pub tier: String,
+    pub expiration_timestamp: DateTime<Utc>,
+    /// Remaining gift quota of supporter gifters, `None` for admins.
+    pub remaining_quota: Option<u32>,
+}
+
+/// Gift granted by an admin, supporters gift via the `/gift` slash command.
+#[derive(Deserialize, ToSchema)]
+pub struct CreateGiftRequest {
+    pub recipient_user_id: u64,
+    /// Defaults to the `default` tier.
+    pub tier: Option<String>,
+    /// Defaults to `GLYPH_GIFT_DURATION_DAYS`.
+    pub duration_days: Option<u32>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CreateGiftResponse {
+    pub recipient_user_id: String,
+    pub tier: String,
+    pub expires_at: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remaining_quota: Option<u32>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct GiftQuotaResponse {
+    pub tier: String,
+    pub quota: u32,
+    pub remaining: u32,
+    pub period_days: i64,
+}
+
+#[utoipa::path(
+    post,
+    path = "/gifts",
+    tag = "gifts",
+    request_body = CreateGiftRequest,
+    security(("admin_token" = [])),
+    responses(
+        (status = 200, description = "The gift has been granted", body = CreateGiftResponse),
+        (status = 400, description = "Malformed request, or the recipient is a permanent supporter", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 413, description = "Request body too large", body = ErrorResponse),
+        (status = 500, description = "Gift could not be granted", body = ErrorResponse),
+    ),
+)]
+pub async fn create_gift_handler(request: CreateGiftRequest) -> Result<impl Reply, Rejection> {
+    let result = gift_supporter(
+        Gifter::Admin,
+        request.recipient_user_id,
+        request.tier,
+        request.duration_days,
+    )
+    .await?;
+
+    Ok(warp::reply::json(&CreateGiftResponse {
+        recipient_user_id: request.recipient_user_id.to_string(),
+        tier: result.tier,
+        expires_at: result.expiration_timestamp,
+        remaining_quota: result.remaining_quota,
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/gifts/quota/{user_id}",
+    tag = "gifts",
+    params(("user_id" = u64, Path, description = "Discord user id of the supporter")),
+    security(("admin_token" = [])),
+    responses(
+        (status = 200, description = "Gift quota of the user, zero if they are not a supporter", body = GiftQuotaResponse),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 500, description = "Quota could not be loaded", body = ErrorResponse),
+    ),
+)]
+pub async fn get_gift_quota_handler(user_id: u64) -> Result<impl Reply, Rejection> {
+    let (tier, quota, remaining) = get_gift_quota(user_id).await?;
+
+    Ok(warp::reply::json(&GiftQuotaResponse {
+        tier,
+        quota,
+        remaining,
+        period_days: GIFT_QUOTA_PERIOD_DAYS,
+    }))
+}
+
+/// Grants time limited supporter status to the recipient on behalf of the gifter. Supporters gift
+/// their own tier and are limited by the quota configured for it, see `GLYPH_GIFT_QUOTAS`.
+///
+/// The quota check, the aiode_supporter entry and the gift are written in one transaction that locks
+/// the gifter's aiode_supporter entry, so concurrent gifts of the same supporter cannot exceed the
+/// quota.
+pub async fn gift_supporter(
+    gifter: Gifter,
+    recipient_user_id: u64,
+    tier: Option<String>,
+    duration_days: Option<u32>,
+) -> Result<GiftResult, Error> {
+    let gifter_user_id = match gifter {
+        Gifter::Admin => None,
+        Gifter::Supporter(gifter_user_id) if gifter_user_id == recipient_user_id => {
+            return Err(Error::InvalidPayloadError(String::from(
+                "Supporters cannot gift themselves",
+            )));
+        }
+        Gifter::Supporter(gifter_user_id) => Some(gifter_user_id),
+    };
+
+    let mut connection = acquire_db_connection().await?;
+    let result = connection
+        .transaction::<_, Error, _>(|connection| {
+            record_gift(
+                connection,
+                gifter_user_id,
+                recipient_user_id,
+                tier,
+                duration_days,
+            )
+            .scope_boxed()
+        })
+        .await?;
+    drop(connection);
+
+    let reason = match gifter_user_id {
+        Some(gifter_user_id) => format!("Gifted by user {gifter_user_id}"),
+        None => String::from("Gifted by an admin"),
+    };
+    tracing::info!(
+        "User {recipient_user_id} has been granted {} aiode supporter status until {}: {reason}",
+        result.tier,
+        result.expiration_timestamp
+    );
+    assign_aiode_supporter_role(recipient_user_id, &reason).await;
+
+    Ok(result)
+}
+
+/// Checks the quota of the gifter, `None` for admins, and writes the recipient's aiode_supporter entry
+/// and the gift, to be run within a transaction.
+async fn record_gift(
+    connection: &mut AsyncPgConnection,
+    gifter_user_id: Option<u64>,
+    recipient_user_id: u64,
+    tier: Option<String>,
+    duration_days: Option<u32>,
+) -> Result<GiftResult, Error> {
+    let (tier, duration_days, remaining_quota) = match gifter_user_id {
+        None => (
+            tier.unwrap_or_else(|| String::from(DEFAULT_SUPPORTER_TIER)),
+            duration_days.unwrap_or(*GIFT_DURATION_DAYS),
+            None,
+        ),
+        Some(gifter_user_id) => {
+            let gifter = find_gifter(connection, gifter_user_id, true).await?;
+            let (tier, _, remaining) = gift_quota(connection, gifter_user_id, gifter).await?;
+            if remaining == 0 {
+                return Err(Error::ForbiddenError(format!(
+                    "No {tier} gifts remaining for the current period"
+                )));
+            }
+            (tier, *GIFT_DURATION_DAYS, Some(remaining - 1))
+        }
+    };
+
+    let expiration_timestamp = upsert_time_limited_supporter(
+        connection,
+        recipient_user_id,
+        &tier,
+        chrono::Duration::days(duration_days.into()),
+        SUPPORTER_SOURCE_GIFT,
+    )
+    .await?
+    .ok_or_else(|| {
+        Error::InvalidPayloadError(String::from(
+            "The recipient already is a permanent supporter",
+        ))
+    })?;
+
+    diesel::insert_into(supporter_gift::table)
+        .values(NewSupporterGift {
+            gifter_user_id: gifter_user_id.map(Snowflake::from),
+            recipient_user_id: recipient_user_id.into(),
+            tier: tier.clone(),
+            duration_days: i32::try_from(duration_days)
+                .map_err(|e| Error::InvalidPayloadError(e.to_string()))?,
+            expiration_timestamp,
+        })
+        .execute(connection)
+        .await?;
+
+    Ok(GiftResult {
+        tier,
+        expiration_timestamp,
+        remaining_quota,
+    })
+}
+
+/// Returns the tier, total quota and remaining quota of gifts the user may give within the current
+/// period. Users that are not supporters themselves, or only received supporter status as a gift,
+/// have no quota.
+pub async fn get_gift_quota(user_id: u64) -> Result<(String, u32, u32), Error> {
+    let mut connection = acquire_db_connection().await?;
+    let gifter = find_gifter(&mut connection, user_id, false).await?;
+    gift_quota(&mut connection, user_id, gifter).await
+}
+
+/// Loads the aiode_supporter entry of the gifter, locking it until the end of the transaction if
+/// `for_update` is set.
+async fn find_gifter(
+    connection: &mut AsyncPgConnection,
+    user_id: u64,
+    for_update: bool,
+) -> Result<Option<AiodeSupporter>, Error> {
+    let query =
+        aiode_supporter::table.filter(aiode_supporter::user_id.eq(Snowflake::from(user_id)));
+    let gifter = if for_update {
+        query
+            .for_update()
+            .get_result::<AiodeSupporter>(connection)
+            .await
+    } else {
+        query.get_result::<AiodeSupporter>(connection).await
+    }
+    .optional()?;
+    Ok(gifter)
+}
+
+async fn gift_quota(
+    connection: &mut AsyncPgConnection,
+    user_id: u64,
+    gifter: Option<AiodeSupporter>,
+) -> Result<(String, u32, u32), Error> {
+    let Some(gifter) = gifter.filter(|gifter| gifter.source != SUPPORTER_SOURCE_GIFT) else {
+        return Ok((String::from(DEFAULT_SUPPORTER_TIER), 0, 0));
+    };
+
+    let tier = gifter
+        .tier
+        .unwrap_or_else(|| String::from(DEFAULT_SUPPORTER_TIER));
+    let quota = GIFT_QUOTAS.get(&tier).copied().unwrap_or_default();
+
+    let used = supporter_gift::table
+        .filter(supporter_gift::gifter_user_id.eq(Snowflake::from(user_id)))
+        .filter(
+            supporter_gift::creation_timestamp
+                .gt(Utc::now() - chrono::Duration::days(GIFT_QUOTA_PERIOD_DAYS)),
+        )
+        .count()
+        .get_result::<i64>(connection)
+        .await?;
+
+    let remaining = u32::try_from(i64::from(quota) - used).unwrap_or_default();
+    Ok((tier, quota, remaining))
+}
+
+/// Returns the most recent gift that is still active for the given user.
+pub async fn find_active_gift(user_id: u64) -> Result<Option<SupporterGift>, Error> {
+    let mut connection = acquire_db_connection().await?;
+
+    let gift = supporter_gift::table
+        .filter(supporter_gift::recipient_user_id.eq(Snowflake::from(user_id)))
+        .filter(supporter_gift::expiration_timestamp.gt(Utc::now()))
+        .order(supporter_gift::expiration_timestamp.desc())
+        .first::<SupporterGift>(&mut connection)
+        .await
+        .optional()?;
+
+    Ok(gift)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        aiode::{SUPPORTER_SOURCE_PAYMENT, SUPPORTER_SOURCE_ROLE},
+        model::{NewAiodeSupporter, NewTimeLimitedAiodeSupporter},
+        test_db::test_connection,
+    };
+
+    async fn insert_supporter(connection: &mut AsyncPgConnection, user_id: u64, source: &str) {
+        diesel::insert_into(aiode_supporter::table)
+            .values(NewAiodeSupporter {
+                user_id: user_id.into(),
+                source: source.to_string(),
+            })
+            .execute(connection)
+            .await
+            .unwrap();
+    }
+
+    async fn gift_count(connection: &mut AsyncPgConnection, recipient_user_id: u64) -> i64 {
+        supporter_gift::table
+            .filter(supporter_gift::recipient_user_id.eq(Snowflake::from(recipient_user_id)))
+            .count()
+            .get_result(connection)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn admin_gift_grants_time_limited_supporter_status() {
+        let Some(mut connection) = test_connection().await else {
+            return;
+        };
+
+        let result = record_gift(
+            &mut connection,
+            None,
+            2,
+            Some(String::from("gold")),
+            Some(7),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(result.tier, "gold");
+        assert!(result.remaining_quota.is_none());
+        let recipient = find_gifter(&mut connection, 2, false)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(recipient.source, SUPPORTER_SOURCE_GIFT);
+        assert!(recipient
+            .expiration_timestamp
+            .is_some_and(|expiration| expiration > Utc::now() + chrono::Duration::days(6)));
+        assert_eq!(gift_count(&mut connection, 2).await, 1);
+    }
+
+    #[tokio::test]
+    async fn gift_extends_time_limited_supporter_status_keeping_tier_and_source() {
+        let Some(mut connection) = test_connection().await else {
+            return;
+        };
+        let expiration_timestamp = Utc::now() + chrono::Duration::days(10);
+        diesel::insert_into(aiode_supporter::table)
+            .values(NewTimeLimitedAiodeSupporter {
+                user_id: 2.into(),
+                tier: Some(String::from("premium")),
+                expiration_timestamp,
+                source: SUPPORTER_SOURCE_PAYMENT.to_string(),
+            })
+            .execute(&mut connection)
+            .await
+            .unwrap();
+
+        let result = record_gift(&mut connection, None, 2, None, Some(7))
+            .await
+            .unwrap();
+
+        let recipient = find_gifter(&mut connection, 2, false)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(recipient.source, SUPPORTER_SOURCE_PAYMENT);
+        assert_eq!(recipient.tier.as_deref(), Some("premium"));
+        assert_eq!(
+            recipient.expiration_timestamp,
+            Some(result.expiration_timestamp)
+        );
+        assert!(result.expiration_timestamp > expiration_timestamp + chrono::Duration::days(6));
+    }
+
+    #[tokio::test]
+    async fn permanent_supporters_cannot_receive_gifts() {
+        let Some(mut connection) = test_connection().await else {
+            return;
+        };
+        insert_supporter(&mut connection, 2, SUPPORTER_SOURCE_ROLE).await;
+
+        let result = record_gift(&mut connection, None, 2, None, None).await;
+
+        assert!(matches!(result, Err(Error::InvalidPayloadError(_))));
+        assert_eq!(gift_count(&mut connection, 2).await, 0);
+    }
+
+    #[tokio::test]
+    async fn gifters_without_remaining_quota_cannot_gift() {
+        let Some(mut connection) = test_connection().await else {
+            return;
+        };
+        // supporters who received a gift have no quota, neither do non-supporters
+        insert_supporter(&mut connection, 1, SUPPORTER_SOURCE_GIFT).await;
+
+        for gifter_user_id in [1, 3] {
+            let result = record_gift(&mut connection, Some(gifter_user_id), 2, None, None).await;
+
+            assert!(matches!(result, Err(Error::ForbiddenError(_))));
+        }
+        assert!(find_gifter(&mut connection, 2, false)
+            .await
+            .unwrap()
+            .is_none());
+        assert_eq!(gift_count(&mut connection, 2).await, 0);
+    }
+}
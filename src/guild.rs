@@ -0,0 +1,726 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use serde::Serialize;
+use serenity::{
+    all::{
+        ChunkGuildFilter, GuildId, GuildMembersChunkEvent, Member, RoleId, ShardId, Timestamp,
+        UserId,
+    },
+    http::HttpError,
+};
+use tokio::sync::oneshot;
+use utoipa::ToSchema;
+use warp::{reject::Rejection, reply::Reply};
+
+use crate::{
+    context::app_context,
+    discord::DiscordApi,
+    error::{Error, ErrorResponse},
+    MEMBER_FETCH_MODE,
+};
+
+/// Discord JSON error code for requests concerning a user that is not a member of the guild.
+const UNKNOWN_MEMBER_ERROR_CODE: isize = 10007;
+
+/// Maximum time to wait for all member chunks of a guild before falling back to REST.
+const MEMBER_CHUNK_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// How long members fetched from discord for the role members route are reused.
+const FETCHED_MEMBERS_TTL: Duration = Duration::from_secs(60);
+
+static MEMBER_CHUNK_NONCE: AtomicU64 = AtomicU64::new(0);
+
+lazy_static! {
+    static ref GUILD_CACHE_STATES: RwLock<HashMap<GuildId, GuildCacheState>> =
+        RwLock::new(HashMap::new());
+    /// Member chunk requests that have not received all chunks yet, by nonce.
+    static ref PENDING_MEMBER_CHUNKS: Mutex<HashMap<String, PendingMemberChunks>> =
+        Mutex::new(HashMap::new());
+    /// Members fetched from discord by the role members route for guilds whose member list is not
+    /// fully cached.
+    static ref FETCHED_GUILD_MEMBERS: FetchedMembersCache = FetchedMembersCache::new(FETCHED_MEMBERS_TTL);
+}
+
+/// How the members of a guild are fetched by the refresh tasks, see `GLYPH_MEMBER_FETCH_MODE`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemberFetchMode {
+    /// Page through the members via the REST API.
+    Rest,
+    /// Request member chunks over the gateway and read the members from the cache.
+    Gateway,
+}
+
+impl FromStr for MemberFetchMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rest" => Ok(Self::Rest),
+            "gateway" => Ok(Self::Gateway),
+            _ => Err(format!("unknown member fetch mode {s}")),
+        }
+    }
+}
+
+struct PendingMemberChunks {
+    received_chunks: u32,
+    sender: oneshot::Sender<u32>,
+}
+
+/// Tracks when the cached data of a guild has been received from the gateway.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GuildCacheState {
+    /// When the member list of the guild has last been received, e.g. via GUILD_CREATE.
+    pub synced_at: Option<DateTime<Utc>>,
+    /// When a member of the guild has last been added, updated or removed.
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct Freshness {
+    /// `cache` if the data has been served from the gateway cache, `discord` if it has been fetched
+    /// from the discord API.
+    pub source: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub synced_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fetched_at: Option<DateTime<Utc>>,
+}
+
+impl Freshness {
+    fn cache(state: GuildCacheState) -> Self {
+        Self {
+            source: "cache",
+            synced_at: state.synced_at,
+            updated_at: state.updated_at,
+            fetched_at: None,
+        }
+    }
+
+    fn discord(fetched_at: DateTime<Utc>) -> Self {
+        Self {
+            source: "discord",
+            synced_at: None,
+            updated_at: None,
+            fetched_at: Some(fetched_at),
+        }
+    }
+}
+
+/// Members of a guild fetched from discord.
+#[derive(Clone)]
+struct FetchedMembers {
+    members: Arc<Vec<Member>>,
+    fetched_at: DateTime<Utc>,
+    fetched: Instant,
+}
+
+/// Reuses the members of a guild fetched from discord for a short time, concurrent requests for the
+/// same guild wait for a single fetch instead of each paging through all members.
+struct FetchedMembersCache {
+    ttl: Duration,
+    guilds: Mutex<HashMap<GuildId, Arc<tokio::sync::Mutex<Option<FetchedMembers>>>>>,
+}
+
+impl FetchedMembersCache {
+    fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            guilds: Mutex::new(HashMap::new()),
+        }
+    }
+
+    async fn get_or_fetch<F, Fut>(
+        &self,
+        guild_id: GuildId,
+        fetch: F,
+    ) -> Result<FetchedMembers, Error>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<Vec<Member>, Error>>,
+    {
+        let guild = self
+            .guilds
+            .lock()
+            .expect("fetched guild members lock poisoned")
+            .entry(guild_id)
+            .or_default()
+            .clone();
+        let mut fetched = guild.lock().await;
+        // fetched by a concurrent request while waiting for the lock or by a recent request
+        if let Some(fetched) = fetched
+            .as_ref()
+            .filter(|fetched| fetched.fetched.elapsed() < self.ttl)
+        {
+            return Ok(fetched.clone());
+        }
+
+        let members = FetchedMembers {
+            members: Arc::new(fetch().await?),
+            fetched_at: Utc::now(),
+            fetched: Instant::now(),
+        };
+        *fetched = Some(members.clone());
+        Ok(members)
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct GuildMemberResponse {
+    pub guild_id: String,
+    pub user_id: String,
+    pub is_member: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nick: Option<String>,
+    pub roles: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<String>, format = DateTime)]
+    pub joined_at: Option<Timestamp>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<String>, format = DateTime)]
+    pub premium_since: Option<Timestamp>,
+    pub freshness: Freshness,
+}
+
+impl GuildMemberResponse {
+    fn new(
+        guild_id: GuildId,
+        user_id: UserId,
+        member: Option<&Member>,
+        freshness: Freshness,
+    ) -> Self {
+        Self {
+            guild_id: guild_id.to_string(),
+            user_id: user_id.to_string(),
+            is_member: member.is_some(),
+            nick: member.and_then(|member| member.nick.clone()),
+            roles: member
+                .map(|member| member.roles.iter().map(RoleId::to_string).collect())
+                .unwrap_or_default(),
+            joined_at: member.and_then(|member| member.joined_at),
+            premium_since: member.and_then(|member| member.premium_since),
+            freshness,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct RoleMembersResponse {
+    pub guild_id: String,
+    pub role_id: String,
+    pub members: Vec<String>,
+    pub freshness: Freshness,
+}
+
+pub fn mark_guild_synced(guild_id: GuildId) {
+    GUILD_CACHE_STATES
+        .write()
+        .expect("guild cache states lock poisoned")
+        .entry(guild_id)
+        .or_default()
+        .synced_at = Some(Utc::now());
+}
+
+pub fn mark_guild_updated(guild_id: GuildId) {
+    GUILD_CACHE_STATES
+        .write()
+        .expect("guild cache states lock poisoned")
+        .entry(guild_id)
+        .or_default()
+        .updated_at = Some(Utc::now());
+}
+
+fn guild_cache_state(guild_id: GuildId) -> Option<GuildCacheState> {
+    GUILD_CACHE_STATES
+        .read()
+        .expect("guild cache states lock poisoned")
+        .get(&guild_id)
+        .copied()
+}
+
+/// Returns whether the user is a member of the guild and their roles. Served from the cache if the
+/// guild is cached, fetched from discord if the member is not cached and the cached member list of
+/// the guild is incomplete.
+#[utoipa::path(
+    get,
+    path = "/guilds/{guild_id}/members/{user_id}",
+    tag = "guilds",
+    params(
+        ("guild_id" = u64, Path, description = "Discord guild id"),
+        ("user_id" = u64, Path, description = "Discord user id"),
+    ),
+    security(("admin_token" = [])),
+    responses(
+        (status = 200, description = "Membership of the user", body = GuildMemberResponse),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 404, description = "The bot is not a member of the guild", body = ErrorResponse),
+        (status = 429, description = "Rate limited by discord", body = ErrorResponse),
+        (status = 500, description = "Member could not be fetched", body = ErrorResponse),
+    ),
+)]
+pub async fn get_guild_member_handler(
+    guild_id: u64,
+    user_id: u64,
+) -> Result<impl Reply, Rejection> {
+    if guild_id == 0 || user_id == 0 {
+        return Err(warp::reject::not_found());
+    }
+    let guild_id = GuildId::new(guild_id);
+    let user_id = UserId::new(user_id);
+
+    if let Some(state) = guild_cache_state(guild_id) {
+        let cached = app_context().cache.guild(guild_id).map(|guild| {
+            (
+                guild.members.get(&user_id).cloned(),
+                guild.members.len() as u64 >= guild.member_count,
+            )
+        });
+
+        match cached {
+            Some((Some(member), _)) => {
+                return Ok(warp::reply::json(&GuildMemberResponse::new(
+                    guild_id,
+                    user_id,
+                    Some(&member),
+                    Freshness::cache(state),
+                )))
+            }
+            // all members are cached, so the user is not a member
+            Some((None, true)) => {
+                return Ok(warp::reply::json(&GuildMemberResponse::new(
+                    guild_id,
+                    user_id,
+                    None,
+                    Freshness::cache(state),
+                )))
+            }
+            _ => {}
+        }
+    }
+
+    let member = match app_context().http.get_member(guild_id, user_id).await {
+        Ok(member) => Some(member),
+        Err(e) if discord_error_code(&e) == Some(UNKNOWN_MEMBER_ERROR_CODE) => None,
+        Err(e) if is_not_found(&e) => return Err(warp::reject::not_found()),
+        Err(e) if is_rate_limited(&e) => return Err(Error::TooManyRequestsError.into()),
+        Err(e) => return Err(Error::from(e).into()),
+    };
+
+    Ok(warp::reply::json(&GuildMemberResponse::new(
+        guild_id,
+        user_id,
+        member.as_ref(),
+        Freshness::discord(Utc::now()),
+    )))
+}
+
+/// Returns the ids of all members holding the role. Served from the cache if all members of the guild
+/// are cached, else all members are fetched from discord, see [`FetchedMembersCache`].
+#[utoipa::path(
+    get,
+    path = "/guilds/{guild_id}/roles/{role_id}/members",
+    tag = "guilds",
+    params(
+        ("guild_id" = u64, Path, description = "Discord guild id"),
+        ("role_id" = u64, Path, description = "Discord role id"),
+    ),
+    security(("admin_token" = [])),
+    responses(
+        (status = 200, description = "Ids of the members holding the role", body = RoleMembersResponse),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 404, description = "The guild or role does not exist", body = ErrorResponse),
+        (status = 429, description = "Rate limited by discord", body = ErrorResponse),
+        (status = 500, description = "Members could not be fetched", body = ErrorResponse),
+    ),
+)]
+pub async fn get_role_members_handler(
+    guild_id: u64,
+    role_id: u64,
+) -> Result<impl Reply, Rejection> {
+    if guild_id == 0 || role_id == 0 {
+        return Err(warp::reject::not_found());
+    }
+    let guild_id = GuildId::new(guild_id);
+    let role_id = RoleId::new(role_id);
+
+    if let Some(state) = guild_cache_state(guild_id) {
+        let cached = app_context().cache.guild(guild_id).and_then(|guild| {
+            if !guild.roles.contains_key(&role_id) {
+                return Some(None);
+            }
+            (guild.members.len() as u64 >= guild.member_count).then(|| {
+                Some(
+                    guild
+                        .members
+                        .values()
+                        .filter(|member| member.roles.contains(&role_id))
+                        .map(|member| member.user.id.to_string())
+                        .collect::<Vec<_>>(),
+                )
+            })
+        });
+
+        match cached {
+            Some(Some(members)) => {
+                return Ok(warp::reply::json(&RoleMembersResponse {
+                    guild_id: guild_id.to_string(),
+                    role_id: role_id.to_string(),
+                    members,
+                    freshness: Freshness::cache(state),
+                }))
+            }
+            // the role does not exist on the guild
+            Some(None) => return Err(warp::reject::not_found()),
+            // the guild is not cached or its member list is incomplete
+            None => {}
+        }
+    }
+
+    let roles = match app_context().http.get_guild_roles(guild_id).await {
+        Ok(roles) => roles,
+        Err(e) if is_not_found(&e) => return Err(warp::reject::not_found()),
+        Err(e) if is_rate_limited(&e) => return Err(Error::TooManyRequestsError.into()),
+        Err(e) => return Err(Error::from(e).into()),
+    };
+    if !roles.iter().any(|role| role.id == role_id) {
+        return Err(warp::reject::not_found());
+    }
+
+    let fetched = FETCHED_GUILD_MEMBERS
+        .get_or_fetch(guild_id, || fetch_guild_members(guild_id))
+        .await?;
+    let members = fetched
+        .members
+        .iter()
+        .filter(|member| member.roles.contains(&role_id))
+        .map(|member| member.user.id.to_string())
+        .collect();
+
+    Ok(warp::reply::json(&RoleMembersResponse {
+        guild_id: guild_id.to_string(),
+        role_id: role_id.to_string(),
+        members,
+        freshness: Freshness::discord(fetched.fetched_at),
+    }))
+}
+
+/// Fetches all members of the guild. If `GLYPH_MEMBER_FETCH_MODE` is `gateway`, the members are
+/// requested in chunks over the gateway and read from the cache once all chunks have been received,
+/// falling back to paging through the REST API if chunking fails or times out.
+pub async fn fetch_guild_members(guild_id: GuildId) -> Result<Vec<Member>, Error> {
+    let started_at = Instant::now();
+
+    if *MEMBER_FETCH_MODE == MemberFetchMode::Gateway {
+        match fetch_guild_members_via_gateway(guild_id).await {
+            Some((members, chunk_count)) => {
+                tracing::info!(
+                    "Fetched {} members of guild {guild_id} in {chunk_count} gateway chunks after {:?}",
+                    members.len(),
+                    started_at.elapsed()
+                );
+                return Ok(members);
+            }
+            None => tracing::warn!(
+                "Failed to fetch members of guild {guild_id} via the gateway after {:?}, falling back to REST",
+                started_at.elapsed()
+            ),
+        }
+    }
+
+    let (members, page_count) =
+        fetch_guild_members_via_rest(&*app_context().http, guild_id).await?;
+    tracing::info!(
+        "Fetched {} members of guild {guild_id} in {page_count} REST pages after {:?}",
+        members.len(),
+        started_at.elapsed()
+    );
+    Ok(members)
+}
+
+/// Requests all members of the guild from the shard the guild is on and waits for the chunks to be
+/// received, see [`handle_members_chunk`]. Returns the members and number of chunks, or `None` if the
+/// shard is unavailable, the request timed out or the guild is not cached.
+async fn fetch_guild_members_via_gateway(guild_id: GuildId) -> Option<(Vec<Member>, u32)> {
+    let app_context = app_context();
+    let shard_count = app_context.cache.shard_count().max(1);
+    let shard_id = ShardId(((guild_id.get() >> 22) % u64::from(shard_count)) as u32);
+    let shard_messenger = app_context
+        .shard_manager
+        .runners
+        .lock()
+        .await
+        .get(&shard_id)
+        .map(|runner| runner.runner_tx.clone())?;
+
+    let nonce = format!(
+        "{guild_id}-{}",
+        MEMBER_CHUNK_NONCE.fetch_add(1, Ordering::Relaxed)
+    );
+    let (sender, receiver) = oneshot::channel();
+    PENDING_MEMBER_CHUNKS
+        .lock()
+        .expect("pending member chunks lock poisoned")
+        .insert(
+            nonce.clone(),
+            PendingMemberChunks {
+                received_chunks: 0,
+                sender,
+            },
+        );
+
+    shard_messenger.chunk_guild(
+        guild_id,
+        None,
+        false,
+        ChunkGuildFilter::None,
+        Some(nonce.clone()),
+    );
+
+    let chunk_count = match tokio::time::timeout(MEMBER_CHUNK_TIMEOUT, receiver).await {
+        Ok(Ok(chunk_count)) => chunk_count,
+        _ => {
+            PENDING_MEMBER_CHUNKS
+                .lock()
+                .expect("pending member chunks lock poisoned")
+                .remove(&nonce);
+            return None;
+        }
+    };
+
+    let members = app_context
+        .cache
+        .guild(guild_id)
+        .map(|guild| guild.members.values().cloned().collect())?;
+    Some((members, chunk_count))
+}
+
+/// Records a chunk of members requested by [`fetch_guild_members_via_gateway`]. The cache has already
+/// been updated with the members of the chunk when this is called.
+pub fn handle_members_chunk(chunk: &GuildMembersChunkEvent) {
+    let Some(ref nonce) = chunk.nonce else {
+        return;
+    };
+
+    let mut pending_member_chunks = PENDING_MEMBER_CHUNKS
+        .lock()
+        .expect("pending member chunks lock poisoned");
+    let Some(pending) = pending_member_chunks.get_mut(nonce) else {
+        return;
+    };
+
+    pending.received_chunks += 1;
+    if pending.received_chunks >= chunk.chunk_count {
+        if let Some(pending) = pending_member_chunks.remove(nonce) {
+            mark_guild_synced(chunk.guild_id);
+            // the receiver is gone if the request timed out in the meantime
+            let _ = pending.sender.send(pending.received_chunks);
+        }
+    }
+}
+
+/// Pages through all members of the guild via the REST API. Returns the members and number of pages.
+async fn fetch_guild_members_via_rest(
+    discord: &dyn DiscordApi,
+    guild_id: GuildId,
+) -> Result<(Vec<Member>, u32), Error> {
+    let mut guild_members = Vec::new();
+    let mut last_member = None;
+    let mut page_count = 0;
+    let limit = 500;
+
+    loop {
+        let members = discord
+            .get_guild_members(guild_id, limit, last_member)
+            .await?;
+        page_count += 1;
+
+        last_member = members.last().map(|m| m.user.id);
+        let page_size = members.len();
+        guild_members.extend(members);
+
+        if page_size < limit as usize {
+            break;
+        }
+    }
+
+    Ok((guild_members, page_count))
+}
+
+fn discord_error_code(e: &serenity::Error) -> Option<isize> {
+    match e {
+        serenity::Error::Http(HttpError::UnsuccessfulRequest(response)) => {
+            Some(response.error.code)
+        }
+        _ => None,
+    }
+}
+
+/// Discord rejected the request due to a rate limit serenity did not wait out, e.g. a shared limit.
+fn is_rate_limited(e: &serenity::Error) -> bool {
+    match e {
+        serenity::Error::Http(e) => e.status_code().is_some_and(|status| status.as_u16() == 429),
+        _ => false,
+    }
+}
+
+/// The guild is unknown or the bot cannot access it.
+fn is_not_found(e: &serenity::Error) -> bool {
+    match e {
+        serenity::Error::Http(e) => e
+            .status_code()
+            .is_some_and(|status| status.as_u16() == 403 || status.as_u16() == 404),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serenity::all::UserId;
+
+    use super::*;
+    use crate::discord::fake::{member, FakeDiscord};
+
+    fn guild_with_members(count: u64) -> FakeDiscord {
+        FakeDiscord::with_members((1..=count).map(|user_id| member(user_id, &[])))
+    }
+
+    async fn fetch(discord: &FakeDiscord) -> (Vec<Member>, u32) {
+        fetch_guild_members_via_rest(discord, GuildId::new(1))
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn fetches_empty_guild_in_one_page() {
+        let discord = guild_with_members(0);
+
+        let (members, page_count) = fetch(&discord).await;
+
+        assert!(members.is_empty());
+        assert_eq!(page_count, 1);
+        assert_eq!(*discord.member_pages.lock().unwrap(), vec![None]);
+    }
+
+    #[tokio::test]
+    async fn fetches_partial_page() {
+        let discord = guild_with_members(499);
+
+        let (members, page_count) = fetch(&discord).await;
+
+        assert_eq!(members.len(), 499);
+        assert_eq!(page_count, 1);
+    }
+
+    #[tokio::test]
+    async fn requests_empty_page_after_exactly_full_page() {
+        let discord = guild_with_members(500);
+
+        let (members, page_count) = fetch(&discord).await;
+
+        assert_eq!(members.len(), 500);
+        assert_eq!(page_count, 2);
+        assert_eq!(
+            *discord.member_pages.lock().unwrap(),
+            vec![None, Some(UserId::new(500))]
+        );
+    }
+
+    #[tokio::test]
+    async fn pages_through_all_members_in_order() {
+        let discord = guild_with_members(1001);
+
+        let (members, page_count) = fetch(&discord).await;
+
+        assert_eq!(page_count, 3);
+        assert_eq!(
+            *discord.member_pages.lock().unwrap(),
+            vec![None, Some(UserId::new(500)), Some(UserId::new(1000))]
+        );
+        assert!(members
+            .iter()
+            .map(|member| member.user.id.get())
+            .eq(1..=1001));
+    }
+
+    #[tokio::test]
+    async fn fails_if_a_page_fails() {
+        let discord = FakeDiscord {
+            fail_after_pages: Some(1),
+            ..guild_with_members(1000)
+        };
+
+        let res = fetch_guild_members_via_rest(&discord, GuildId::new(1)).await;
+
+        assert!(res.is_err());
+        assert_eq!(discord.member_pages.lock().unwrap().len(), 1);
+    }
+
+    async fn fetch_counted(
+        cache: &FetchedMembersCache,
+        fetch_count: &AtomicU64,
+    ) -> Result<FetchedMembers, Error> {
+        cache
+            .get_or_fetch(GuildId::new(1), || async {
+                fetch_count.fetch_add(1, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                Ok(vec![member(1, &[])])
+            })
+            .await
+    }
+
+    #[tokio::test]
+    async fn concurrent_requests_share_a_single_fetch() {
+        let cache = FetchedMembersCache::new(Duration::from_secs(60));
+        let fetch_count = AtomicU64::new(0);
+
+        let (first, second) = tokio::join!(
+            fetch_counted(&cache, &fetch_count),
+            fetch_counted(&cache, &fetch_count)
+        );
+
+        assert_eq!(fetch_count.load(Ordering::SeqCst), 1);
+        assert_eq!(first.unwrap().fetched_at, second.unwrap().fetched_at);
+        fetch_counted(&cache, &fetch_count).await.unwrap();
+        assert_eq!(fetch_count.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn fetches_again_once_expired() {
+        let cache = FetchedMembersCache::new(Duration::ZERO);
+        let fetch_count = AtomicU64::new(0);
+
+        fetch_counted(&cache, &fetch_count).await.unwrap();
+        fetch_counted(&cache, &fetch_count).await.unwrap();
+
+        assert_eq!(fetch_count.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn failed_fetches_are_not_reused() {
+        let cache = FetchedMembersCache::new(Duration::from_secs(60));
+
+        let res = cache
+            .get_or_fetch(GuildId::new(1), || async {
+                Err(Error::TooManyRequestsError)
+            })
+            .await;
+        assert!(res.is_err());
+
+        let fetch_count = AtomicU64::new(0);
+        fetch_counted(&cache, &fetch_count).await.unwrap();
+        assert_eq!(fetch_count.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,291 @@
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use diesel::{
+    data_types::PgInterval, dsl::now, query_dsl::methods::FilterDsl, sql_types::Timestamptz,
+    upsert::excluded, BoolExpressionMethods, ExpressionMethods, IntoSql,
+};
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+
+use crate::{acquire_db_connection, error::Error, schema::lease, INSTANCE_ID, LEADER_ELECTION};
+
+/// Duration a task lease is valid for without being renewed. If the instance holding the lease
+/// crashes, other instances may run the task once the lease has expired.
+pub const TASK_LEASE_DURATION: Duration = Duration::from_secs(60);
+
+/// Name of the lease held by the instance connected to the gateway, see `GLYPH_LEADER_ELECTION`.
+pub const LEADER_LEASE: &str = "leader";
+/// Duration the leader lease is valid for without being renewed, which bounds the time until a
+/// standby instance takes over after the leader has crashed.
+pub const LEADER_LEASE_DURATION: Duration = Duration::from_secs(30);
+
+/// Set while this instance holds the leader lease and is connected to the gateway.
+static LEADER: AtomicBool = AtomicBool::new(false);
+
+/// Whether this instance is the leader, always true without `GLYPH_LEADER_ELECTION`.
+pub fn is_leader() -> bool {
+    !*LEADER_ELECTION || AtomicBool::load(&LEADER, Ordering::SeqCst)
+}
+
+pub fn set_leader(leader: bool) {
+    LEADER.store(leader, Ordering::SeqCst);
+}
+
+/// Acquires the lease for this instance if it is not held by another instance or the lease has
+/// expired. Returns false if another instance holds the lease.
+pub async fn try_acquire_lease(name: &str, duration: Duration) -> Result<bool, Error> {
+    let mut connection = acquire_db_connection().await?;
+    acquire(&mut connection, name, &INSTANCE_ID, duration).await
+}
+
+/// Lease expiries are computed and compared by the database as its `now()` plus the interval, so that
+/// they do not depend on the clocks of the instances agreeing.
+fn interval(duration: Duration) -> PgInterval {
+    PgInterval::from_microseconds(duration.as_micros().try_into().unwrap_or(i64::MAX))
+}
+
+async fn acquire(
+    connection: &mut AsyncPgConnection,
+    name: &str,
+    holder: &str,
+    duration: Duration,
+) -> Result<bool, Error> {
+    let res = diesel::insert_into(lease::table)
+        .values((
+            lease::name.eq(name),
+            lease::holder.eq(holder),
+            lease::expires_at.eq(now.into_sql::<Timestamptz>() + interval(duration)),
+        ))
+        .on_conflict(lease::name)
+        .do_update()
+        .set((
+            lease::holder.eq(excluded(lease::holder)),
+            lease::expires_at.eq(excluded(lease::expires_at)),
+        ))
+        .filter(lease::expires_at.le(now).or(lease::holder.eq(holder)))
+        .execute(connection)
+        .await?;
+
+    Ok(res > 0)
+}
+
+/// Waits until this instance acquires the lease, trying a few times per lease duration.
+pub async fn acquire_lease(name: &str, duration: Duration) {
+    loop {
+        match try_acquire_lease(name, duration).await {
+            Ok(true) => return,
+            Ok(false) => {}
+            Err(e) => tracing::error!("Failed to acquire lease {name}: {e}"),
+        }
+        tokio::time::sleep(duration / 3).await;
+    }
+}
+
+/// Extends the lease held by this instance. Returns false if the lease has been lost to another
+/// instance.
+pub async fn renew_lease(name: &str, duration: Duration) -> Result<bool, Error> {
+    let mut connection = acquire_db_connection().await?;
+    renew(&mut connection, name, &INSTANCE_ID, duration).await
+}
+
+async fn renew(
+    connection: &mut AsyncPgConnection,
+    name: &str,
+    holder: &str,
+    duration: Duration,
+) -> Result<bool, Error> {
+    let res = diesel::update(lease::table)
+        .filter(lease::name.eq(name))
+        .filter(lease::holder.eq(holder))
+        .set(lease::expires_at.eq(now.into_sql::<Timestamptz>() + interval(duration)))
+        .execute(connection)
+        .await?;
+
+    Ok(res > 0)
+}
+
+/// Renews the lease until it is lost, or could not be renewed and is about to expire, at which point
+/// this returns so that the work guarded by the lease can be stopped before another instance takes
+/// over.
+pub async fn hold_lease(name: &str, duration: Duration) {
+    let renewal_interval = duration / 3;
+    // the lease is valid for the duration from the start of the last successful renewal
+    let mut renewed_at = tokio::time::Instant::now();
+    loop {
+        tokio::time::sleep(renewal_interval).await;
+        let renewal_started_at = tokio::time::Instant::now();
+        match renew_lease(name, duration).await {
+            Ok(true) => renewed_at = renewal_started_at,
+            Ok(false) => {
+                tracing::warn!("Lost lease {name} to another instance");
+                return;
+            }
+            Err(e) => tracing::error!("Failed to renew lease {name}: {e}"),
+        }
+
+        if renewed_at.elapsed() + renewal_interval >= duration {
+            tracing::warn!(
+                "Giving up lease {name} because it could not be renewed before expiring"
+            );
+            return;
+        }
+    }
+}
+
+/// Keeps the lease held by this instance for the given duration, after which other instances may
+/// acquire it.
+pub async fn release_lease(name: &str, keep_for: Duration) -> Result<(), Error> {
+    let mut connection = acquire_db_connection().await?;
+    release(&mut connection, name, &INSTANCE_ID, keep_for).await
+}
+
+async fn release(
+    connection: &mut AsyncPgConnection,
+    name: &str,
+    holder: &str,
+    keep_for: Duration,
+) -> Result<(), Error> {
+    diesel::update(lease::table)
+        .filter(lease::name.eq(name))
+        .filter(lease::holder.eq(holder))
+        .set(lease::expires_at.eq(now.into_sql::<Timestamptz>() + interval(keep_for)))
+        .execute(connection)
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Utc};
+    use diesel::{query_dsl::methods::SelectDsl, OptionalExtension};
+
+    use super::*;
+    use crate::test_db::test_connection;
+
+    const NAME: &str = "test";
+
+    // `now()` is the start of the test transaction, so it does not advance between statements and a
+    // lease acquired for zero seconds has expired right away
+
+    async fn lease(connection: &mut AsyncPgConnection) -> Option<(String, bool)> {
+        lease::table
+            .filter(lease::name.eq(NAME))
+            .select((lease::holder, lease::expires_at.gt(now)))
+            .first::<(String, bool)>(connection)
+            .await
+            .optional()
+            .unwrap()
+    }
+
+    async fn expires_in(connection: &mut AsyncPgConnection) -> chrono::Duration {
+        let (expires_at, db_now) = lease::table
+            .filter(lease::name.eq(NAME))
+            .select((lease::expires_at, now.into_sql::<Timestamptz>()))
+            .first::<(DateTime<Utc>, DateTime<Utc>)>(connection)
+            .await
+            .unwrap();
+        expires_at - db_now
+    }
+
+    #[tokio::test]
+    async fn unexpired_lease_is_held_by_one_instance() {
+        let Some(mut connection) = test_connection().await else {
+            return;
+        };
+
+        assert!(acquire(&mut connection, NAME, "a", Duration::from_secs(60))
+            .await
+            .unwrap());
+        assert!(
+            !acquire(&mut connection, NAME, "b", Duration::from_secs(60))
+                .await
+                .unwrap()
+        );
+        // the holder may acquire the lease again
+        assert!(acquire(&mut connection, NAME, "a", Duration::from_secs(60))
+            .await
+            .unwrap());
+
+        assert_eq!(lease(&mut connection).await, Some(("a".to_string(), true)));
+        assert_eq!(
+            expires_in(&mut connection).await,
+            chrono::Duration::seconds(60)
+        );
+    }
+
+    #[tokio::test]
+    async fn only_the_holder_renews_the_lease() {
+        let Some(mut connection) = test_connection().await else {
+            return;
+        };
+        acquire(&mut connection, NAME, "a", Duration::from_secs(30))
+            .await
+            .unwrap();
+
+        assert!(!renew(&mut connection, NAME, "b", Duration::from_secs(90))
+            .await
+            .unwrap());
+        assert_eq!(
+            expires_in(&mut connection).await,
+            chrono::Duration::seconds(30)
+        );
+
+        assert!(renew(&mut connection, NAME, "a", Duration::from_secs(90))
+            .await
+            .unwrap());
+        assert_eq!(
+            expires_in(&mut connection).await,
+            chrono::Duration::seconds(90)
+        );
+    }
+
+    #[tokio::test]
+    async fn expired_lease_is_taken_over() {
+        let Some(mut connection) = test_connection().await else {
+            return;
+        };
+        acquire(&mut connection, NAME, "a", Duration::ZERO)
+            .await
+            .unwrap();
+
+        assert!(acquire(&mut connection, NAME, "b", Duration::from_secs(60))
+            .await
+            .unwrap());
+
+        assert_eq!(lease(&mut connection).await, Some(("b".to_string(), true)));
+        assert!(!renew(&mut connection, NAME, "a", Duration::from_secs(60))
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn released_lease_is_taken_over() {
+        let Some(mut connection) = test_connection().await else {
+            return;
+        };
+        acquire(&mut connection, NAME, "a", Duration::from_secs(60))
+            .await
+            .unwrap();
+
+        // only the holder releases the lease
+        release(&mut connection, NAME, "b", Duration::ZERO)
+            .await
+            .unwrap();
+        assert!(
+            !acquire(&mut connection, NAME, "b", Duration::from_secs(60))
+                .await
+                .unwrap()
+        );
+
+        release(&mut connection, NAME, "a", Duration::ZERO)
+            .await
+            .unwrap();
+        assert!(acquire(&mut connection, NAME, "b", Duration::from_secs(60))
+            .await
+            .unwrap());
+        assert_eq!(lease(&mut connection).await, Some(("b".to_string(), true)));
+    }
+}
@@ -1,11 +1,6 @@
-use std::{
-    fs, io,
-    str::FromStr,
-    thread::{self, JoinHandle},
-};
+use std::{collections::HashMap, fs, io, str::FromStr, time::Duration};
 
-use chrono::Utc;
-use clokwerk::Scheduler;
+use context::AppContext;
 use diesel::{ConnectionError, ConnectionResult};
 use diesel_async::{
     pooled_connection::{
@@ -19,22 +14,46 @@ use error::Error;
 use event_handler::DiscordEventHandler;
 use futures::{future::BoxFuture, FutureExt};
 use lazy_static::lazy_static;
+use lease::{LEADER_LEASE, LEADER_LEASE_DURATION};
+use module::{Module, ScheduledTask};
 use rustls::pki_types::CertificateDer;
 
+pub mod account;
 pub mod aiode;
+pub mod alert;
+pub mod announcement;
+pub mod auth;
+pub mod badge;
+pub mod change_feed;
+pub mod command;
+pub mod context;
+pub mod credits;
+pub mod discord;
 pub mod error;
+pub mod event_archive;
 pub mod event_handler;
+pub mod gift;
+pub mod guild;
+pub mod lease;
 pub mod model;
+pub mod module;
+pub mod openapi;
+pub mod payment;
+pub mod request_id;
 pub mod schema;
+pub mod snowflake;
+pub mod store;
+pub mod supporter_cache;
 pub mod task;
+pub mod telemetry;
+#[cfg(test)]
+mod test_db;
 pub mod util;
+pub mod voucher;
 
 #[cfg(feature = "auto_migration")]
 use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
-use serenity::all::GatewayIntents;
-use warp::Filter;
-
-use crate::util::OptFmt;
+use warp::{http::Method, reject::Rejection, reply::Reply, Filter};
 
 #[cfg(feature = "auto_migration")]
 pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!();
@@ -84,6 +103,238 @@ lazy_static! {
                 .parse::<u64>()
                 .expect("GLYPH_AIODE_SUPPORTER_ROLE_ID is not a valid u64"))
             .ok();
+    pub static ref AIODE_BOOSTERS_ARE_SUPPORTERS: bool =
+        std::env::var("GLYPH_AIODE_BOOSTERS_ARE_SUPPORTERS")
+            .map(|val| val
+                .parse::<bool>()
+                .expect("GLYPH_AIODE_BOOSTERS_ARE_SUPPORTERS is not a valid boolean"))
+            .unwrap_or_default();
+    pub static ref SUPPORTER_REVOCATION_GRACE_PERIOD_HOURS: u32 =
+        std::env::var("GLYPH_SUPPORTER_REVOCATION_GRACE_PERIOD_HOURS")
+            .map(|val| val
+                .parse::<u32>()
+                .expect("GLYPH_SUPPORTER_REVOCATION_GRACE_PERIOD_HOURS is not a valid u32"))
+            .unwrap_or_default();
+    /// Roles that grant badges, formatted as `badge=role_id` for roles on the support guild or
+    /// `badge=guild_id:role_id` for roles on other guilds.
+    pub static ref BADGE_ROLES: Vec<badge::BadgeRole> = std::env::var("GLYPH_BADGE_ROLES")
+        .map(|val| val
+            .split(',')
+            .filter(|entry| !entry.trim().is_empty())
+            .map(|entry| {
+                let (badge, role) = entry
+                    .split_once('=')
+                    .expect("GLYPH_BADGE_ROLES entries must be formatted as badge=role_id or badge=guild_id:role_id");
+                let (guild_id, role_id) = match role.split_once(':') {
+                    Some((guild_id, role_id)) => (
+                        guild_id
+                            .trim()
+                            .parse::<u64>()
+                            .expect("GLYPH_BADGE_ROLES contains an invalid u64 guild id"),
+                        role_id,
+                    ),
+                    None => (
+                        AIODE_SUPPORT_GUILD_ID.expect(
+                            "GLYPH_BADGE_ROLES entries without guild id require GLYPH_AIODE_SUPPORT_GUILD_ID"
+                        ),
+                        role,
+                    ),
+                };
+                badge::BadgeRole {
+                    badge: badge.trim().to_string(),
+                    guild_id,
+                    role_id: role_id
+                        .trim()
+                        .parse::<u64>()
+                        .expect("GLYPH_BADGE_ROLES contains an invalid u64 role id"),
+                }
+            })
+            .collect())
+        .unwrap_or_default();
+    /// Number of gifts supporters of each tier may give per period, formatted as `tier=quota,tier=quota`.
+    pub static ref GIFT_QUOTAS: HashMap<String, u32> = std::env::var("GLYPH_GIFT_QUOTAS")
+        .map(|val| val
+            .split(',')
+            .filter(|entry| !entry.trim().is_empty())
+            .map(|entry| {
+                let (tier, quota) = entry
+                    .split_once('=')
+                    .expect("GLYPH_GIFT_QUOTAS entries must be formatted as tier=quota");
+                let quota = quota
+                    .trim()
+                    .parse::<u32>()
+                    .expect("GLYPH_GIFT_QUOTAS contains an invalid u32 quota");
+                (tier.trim().to_string(), quota)
+            })
+            .collect())
+        .unwrap_or_default();
+    /// Supporter tiers ordered from lowest to highest, decides which tier a supporter keeps when
+    /// granted time limited status of another tier.
+    pub static ref SUPPORTER_TIERS: Vec<String> = std::env::var("GLYPH_SUPPORTER_TIERS")
+        .map(|val| val
+            .split(',')
+            .map(|tier| tier.trim().to_string())
+            .filter(|tier| !tier.is_empty())
+            .collect())
+        .unwrap_or_default();
+    pub static ref GIFT_DURATION_DAYS: u32 = std::env::var("GLYPH_GIFT_DURATION_DAYS")
+        .map(|val| val
+            .parse::<u32>()
+            .expect("GLYPH_GIFT_DURATION_DAYS is not a valid u32"))
+        .unwrap_or(30);
+    /// Users that may use the admin options of slash commands, e.g. gifting without quota.
+    pub static ref BOT_ADMIN_USER_IDS: Vec<u64> = std::env::var("GLYPH_BOT_ADMIN_USER_IDS")
+        .map(|val| val
+            .split(',')
+            .filter(|user_id| !user_id.trim().is_empty())
+            .map(|user_id| user_id
+                .trim()
+                .parse::<u64>()
+                .expect("GLYPH_BOT_ADMIN_USER_IDS contains an invalid u64"))
+            .collect())
+        .unwrap_or_default();
+    /// Roles on the support guild whose members may use the admin options of slash commands.
+    pub static ref BOT_ADMIN_ROLE_IDS: Vec<u64> = std::env::var("GLYPH_BOT_ADMIN_ROLE_IDS")
+        .map(|val| val
+            .split(',')
+            .filter(|role_id| !role_id.trim().is_empty())
+            .map(|role_id| role_id
+                .trim()
+                .parse::<u64>()
+                .expect("GLYPH_BOT_ADMIN_ROLE_IDS contains an invalid u64"))
+            .collect())
+        .unwrap_or_default();
+    pub static ref ANNOUNCEMENT_CHANNEL_ID: Option<u64> =
+        std::env::var("GLYPH_ANNOUNCEMENT_CHANNEL_ID")
+            .map(|val| val
+                .parse::<u64>()
+                .expect("GLYPH_ANNOUNCEMENT_CHANNEL_ID is not a valid u64"))
+            .ok();
+    pub static ref ANNIVERSARY_DM_USERS: bool = std::env::var("GLYPH_ANNIVERSARY_DM_USERS")
+        .map(|val| val
+            .parse::<bool>()
+            .expect("GLYPH_ANNIVERSARY_DM_USERS is not a valid boolean"))
+        .unwrap_or_default();
+    pub static ref ANNIVERSARY_MONTHS: Vec<u32> = std::env::var("GLYPH_ANNIVERSARY_MONTHS")
+        .map(|val| val
+            .split(',')
+            .map(|months| months
+                .trim()
+                .parse::<u32>()
+                .expect("GLYPH_ANNIVERSARY_MONTHS contains an invalid u32"))
+            .collect())
+        .unwrap_or_else(|_| vec![1, 6, 12, 24, 36]);
+    pub static ref SUPPORTER_COUNT_MILESTONES: Vec<u64> =
+        std::env::var("GLYPH_SUPPORTER_COUNT_MILESTONES")
+            .map(|val| val
+                .split(',')
+                .map(|count| count
+                    .trim()
+                    .parse::<u64>()
+                    .expect("GLYPH_SUPPORTER_COUNT_MILESTONES contains an invalid u64"))
+                .collect())
+            .unwrap_or_else(|_| vec![10, 25, 50, 100, 250, 500, 1000]);
+    pub static ref ANNIVERSARY_TEMPLATE: String = std::env::var("GLYPH_ANNIVERSARY_TEMPLATE")
+        .unwrap_or_else(|_| String::from(
+            "{user} has been supporting aiode for {months} months, thank you!"
+        ));
+    pub static ref MILESTONE_TEMPLATE: String = std::env::var("GLYPH_MILESTONE_TEMPLATE")
+        .unwrap_or_else(|_| String::from(
+            "aiode has reached {count} supporters, thank you all!"
+        ));
+    pub static ref ADMIN_API_TOKEN: Option<String> = std::env::var("GLYPH_ADMIN_API_TOKEN").ok();
+    pub static ref KOFI_VERIFICATION_TOKEN: Option<String> =
+        std::env::var("GLYPH_KOFI_VERIFICATION_TOKEN").ok();
+    pub static ref PATREON_WEBHOOK_SECRET: Option<String> =
+        std::env::var("GLYPH_PATREON_WEBHOOK_SECRET").ok();
+    pub static ref STRIPE_WEBHOOK_SECRET: Option<String> =
+        std::env::var("GLYPH_STRIPE_WEBHOOK_SECRET").ok();
+    /// Names of the modules to enable, all modules compiled into the binary are enabled if not set.
+    pub static ref MODULE_NAMES: Option<Vec<String>> = std::env::var("GLYPH_MODULES")
+        .map(|val| val
+            .split(',')
+            .map(|name| name.trim().to_string())
+            .filter(|name| !name.is_empty())
+            .collect())
+        .ok();
+    pub static ref MODULES: Vec<Box<dyn Module>> = module::enabled_modules();
+    /// How the refresh tasks fetch guild members, `rest` (default) or `gateway`.
+    pub static ref MEMBER_FETCH_MODE: guild::MemberFetchMode =
+        std::env::var("GLYPH_MEMBER_FETCH_MODE")
+            .map(|val| val
+                .parse::<guild::MemberFetchMode>()
+                .expect("GLYPH_MEMBER_FETCH_MODE must be rest or gateway"))
+            .unwrap_or(guild::MemberFetchMode::Rest);
+    /// Maximum number of scheduled tasks executing at the same time.
+    pub static ref MAX_CONCURRENT_TASKS: usize = std::env::var("GLYPH_MAX_CONCURRENT_TASKS")
+        .map(|val| val
+            .parse::<usize>()
+            .expect("GLYPH_MAX_CONCURRENT_TASKS is not a valid usize"))
+        .unwrap_or(4);
+    /// Directory the gateway event archive is written to, the archive is disabled if not set.
+    pub static ref EVENT_ARCHIVE_DIR: Option<std::path::PathBuf> =
+        std::env::var("GLYPH_EVENT_ARCHIVE_DIR").ok().map(Into::into);
+    /// Names of the gateway events to archive, see [`event_archive::GatewayEvent::name`].
+    pub static ref EVENT_ARCHIVE_EVENTS: Vec<String> = std::env::var("GLYPH_EVENT_ARCHIVE_EVENTS")
+        .map(|val| val
+            .split(',')
+            .map(|name| name.trim().to_string())
+            .filter(|name| !name.is_empty())
+            .collect())
+        .unwrap_or_else(|_| vec![
+            String::from("guild_member_addition"),
+            String::from("guild_member_removal"),
+            String::from("guild_member_update"),
+        ]);
+    pub static ref EVENT_ARCHIVE_RETENTION_DAYS: u32 =
+        std::env::var("GLYPH_EVENT_ARCHIVE_RETENTION_DAYS")
+            .map(|val| val
+                .parse::<u32>()
+                .expect("GLYPH_EVENT_ARCHIVE_RETENTION_DAYS is not a valid u32"))
+            .unwrap_or(14);
+    /// Whether only the instance holding the leader lease connects to the gateway and runs tasks.
+    pub static ref LEADER_ELECTION: bool = std::env::var("GLYPH_LEADER_ELECTION")
+        .map(|val| val
+            .parse::<bool>()
+            .expect("GLYPH_LEADER_ELECTION is not a valid bool"))
+        .unwrap_or(false);
+    /// Identifies this instance as the holder of leases, random if not set.
+    pub static ref INSTANCE_ID: String = std::env::var("GLYPH_INSTANCE_ID")
+        .unwrap_or_else(|_| util::generate_code(16));
+    /// Channel that errors and task failures are posted to, only logged if not set.
+    pub static ref OPS_CHANNEL_ID: Option<u64> = std::env::var("GLYPH_OPS_CHANNEL_ID")
+        .map(|val| val
+            .parse::<u64>()
+            .expect("GLYPH_OPS_CHANNEL_ID is not a valid u64"))
+        .ok();
+    /// Role pinged when a task keeps failing.
+    pub static ref OPS_ALERT_ROLE_ID: Option<u64> = std::env::var("GLYPH_OPS_ALERT_ROLE_ID")
+        .map(|val| val
+            .parse::<u64>()
+            .expect("GLYPH_OPS_ALERT_ROLE_ID is not a valid u64"))
+        .ok();
+    /// Number of consecutive failures after which a task failure is escalated.
+    pub static ref TASK_FAILURE_ALERT_THRESHOLD: u32 =
+        std::env::var("GLYPH_TASK_FAILURE_ALERT_THRESHOLD")
+            .map(|val| val
+                .parse::<u32>()
+                .expect("GLYPH_TASK_FAILURE_ALERT_THRESHOLD is not a valid u32"))
+            .unwrap_or(3);
+    pub static ref ALERT_DEDUP_WINDOW_MINUTES: u32 =
+        std::env::var("GLYPH_ALERT_DEDUP_WINDOW_MINUTES")
+            .map(|val| val
+                .parse::<u32>()
+                .expect("GLYPH_ALERT_DEDUP_WINDOW_MINUTES is not a valid u32"))
+            .unwrap_or(60);
+    pub static ref ALERT_RATE_LIMIT_PER_HOUR: u32 =
+        std::env::var("GLYPH_ALERT_RATE_LIMIT_PER_HOUR")
+            .map(|val| val
+                .parse::<u32>()
+                .expect("GLYPH_ALERT_RATE_LIMIT_PER_HOUR is not a valid u32"))
+            .unwrap_or(20);
+    /// gRPC endpoint of the OpenTelemetry collector spans are exported to, e.g.
+    /// `http://localhost:4317`. Requires the `otlp` feature.
+    pub static ref OTLP_ENDPOINT: Option<String> = std::env::var("GLYPH_OTLP_ENDPOINT").ok();
     pub static ref API_PORT: u16 = {
         let port_str = std::env::var("GLYPH_API_PORT")
             .expect("Missing environment variable GLYPH_API_PORT must be set.");
@@ -91,8 +342,15 @@ lazy_static! {
     };
 }
 
+/// Maximum accepted body size for JSON API requests.
+pub const JSON_BODY_LIMIT: u64 = 16 * 1024;
+/// Maximum accepted body size for payment provider webhooks.
+pub const WEBHOOK_BODY_LIMIT: u64 = 64 * 1024;
+
 pub type DbConnection = Object<AsyncPgConnection>;
 
+/// Waits for a pooled connection, the span shows how long requests and tasks wait for the pool.
+#[tracing::instrument(name = "db_connection", skip_all)]
 pub async fn acquire_db_connection() -> Result<DbConnection, Error> {
     CONNECTION_POOL
         .get()
@@ -115,95 +373,208 @@ fn main() {
     .ok();
     dotenv().ok();
 
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let replay_paths = match args.split_first() {
+        Some((command, paths)) if command == "replay" => {
+            // never replay events against the production database by accident
+            let replay_database_url = std::env::var("GLYPH_REPLAY_DATABASE_URL")
+                .expect("Missing environment variable GLYPH_REPLAY_DATABASE_URL must be set to replay events");
+            std::env::set_var("GLYPH_DATABASE_URL", replay_database_url);
+            Some(paths.to_vec())
+        }
+        _ => None,
+    };
+
     lazy_static::initialize(&CONNECTION_POOL);
 
-    setup_logger();
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .expect("Failed to build tokio runtime");
+    // the OTLP exporter is spawned on the runtime
+    let tracing_guard = {
+        let _runtime_guard = runtime.enter();
+        telemetry::init_tracing()
+    };
 
     #[cfg(feature = "auto_migration")]
     {
         use diesel::Connection;
-        log::info!("Running diesel migrations");
+        tracing::info!("Running diesel migrations");
         let mut connection = diesel::pg::PgConnection::establish(&DATABASE_URL)
             .expect("Failed to acquire database connection");
         if let Err(e) = connection.run_pending_migrations(MIGRATIONS) {
             panic!("Failed running db migrations: {}", e);
         }
-        log::info!("Done running diesel migrations");
+        tracing::info!("Done running diesel migrations");
     }
 
-    let _task_scheduler = start_task_scheduler_runtime(configure_scheduler());
+    let success = match replay_paths {
+        Some(paths) => runtime.block_on(replay(paths)),
+        None => runtime.block_on(run()),
+    };
 
-    thread::Builder::new()
-        .name(String::from("api_thread"))
-        .spawn(|| {
-            setup_warp_runtime();
-        })
-        .expect("Failed to spawn api thread");
+    drop(tracing_guard);
+    if !success {
+        std::process::exit(1);
+    }
+}
+
+/// Replays the archived events in the given files against `GLYPH_REPLAY_DATABASE_URL` without
+/// connecting to the gateway. Returns false if the events could not be read.
+async fn replay(paths: Vec<String>) -> bool {
+    // the client is never started, the handlers only use its `Http` and empty cache
+    let client = serenity::Client::builder(&*DISCORD_TOKEN, serenity::all::GatewayIntents::empty())
+        .await
+        .expect("Failed to create serenity client");
+    context::init_app_context(AppContext::from_client(&client));
 
-    setup_serenity_runtime();
+    match event_archive::replay_events(&paths).await {
+        Ok(replayed_events) => {
+            tracing::info!("Replayed {replayed_events} events");
+            true
+        }
+        Err(e) => {
+            tracing::error!("Failed to replay events: {e}");
+            false
+        }
+    }
 }
 
-#[tokio::main(flavor = "current_thread")]
-async fn setup_serenity_runtime() {
-    let intents = GatewayIntents::all();
+/// Runs the discord client, the API and the task scheduler on a single runtime, sharing the client's
+/// `Http` and `Cache` through the application context. With `GLYPH_LEADER_ELECTION` the API is served
+/// right away while the client and the scheduler only start once this instance becomes the leader.
+///
+/// The API and the scheduler are expected to run as long as the process, returns false if either of
+/// them stopped, e.g. because it panicked, or if leadership has been lost, so that the process exits
+/// and is restarted.
+async fn run() -> bool {
+    let intents = module::required_intents(&MODULES);
+    tracing::info!(
+        "Enabled modules {:?} requiring gateway intents {intents:?}",
+        MODULES
+            .iter()
+            .map(|module| module.name())
+            .collect::<Vec<_>>()
+    );
 
     let mut client = serenity::Client::builder(&*DISCORD_TOKEN, intents)
         .event_handler(DiscordEventHandler)
         .await
         .expect("Failed to create serenity client");
 
-    if let Err(why) = client.start().await {
-        log::error!("An error occurred while starting the serenity client: {why:?}");
+    context::init_app_context(AppContext::from_client(&client));
+
+    alert::start_alerts();
+    event_archive::start_event_archive();
+    tokio::spawn(credits::apply_supporter_changes(change_feed::subscribe()));
+    tokio::spawn(supporter_cache::apply_supporter_changes(
+        change_feed::subscribe(),
+    ));
+    tokio::spawn(change_feed::run_listener());
+    let mut api = tokio::spawn(serve_api());
+
+    if *LEADER_ELECTION {
+        tracing::info!("Waiting to become the leader before connecting to the gateway");
+        tokio::select! {
+            _ = lease::acquire_lease(LEADER_LEASE, LEADER_LEASE_DURATION) => {}
+            result = &mut api => return report_stopped("API server", result),
+        }
+        lease::set_leader(true);
+        tracing::info!("Became the leader as instance {}", *INSTANCE_ID);
+
+        let shard_manager = client.shard_manager.clone();
+        tokio::spawn(async move {
+            lease::hold_lease(LEADER_LEASE, LEADER_LEASE_DURATION).await;
+            // stop before the lease expires so that the gateway events are never handled twice, the
+            // process then exits and may be restarted as a standby
+            lease::set_leader(false);
+            tracing::error!("Lost leadership, disconnecting from the gateway");
+            task::cancel_all_tasks();
+            shard_manager.shutdown_all().await;
+        });
+    }
+
+    let mut scheduler = tokio::spawn(task::run_scheduler(scheduled_tasks()));
+
+    let success = tokio::select! {
+        result = client.start() => {
+            if let Err(why) = result {
+                tracing::error!("An error occurred while starting the serenity client: {why:?}");
+            }
+            // the client is stopped when leadership is lost, fail so that the process is restarted
+            lease::is_leader()
+        }
+        result = &mut api => report_stopped("API server", result),
+        result = &mut scheduler => report_stopped("Task scheduler", result),
+    };
+
+    if *LEADER_ELECTION {
+        task::cancel_all_tasks();
+        client.shard_manager.shutdown_all().await;
+        // let a standby take over right away
+        if let Err(e) = lease::release_lease(LEADER_LEASE, Duration::ZERO).await {
+            tracing::error!("Failed to release the leader lease: {e}");
+        }
     }
+
+    success
 }
 
-#[tokio::main(flavor = "current_thread")]
-async fn setup_warp_runtime() {
-    let check_is_aiode_supporter = warp::path!("is-aiode-supporter" / u64)
-        .and(warp::get())
-        .and_then(aiode::check_is_aiode_supporter_handler);
-
-    let routes = check_is_aiode_supporter;
-
-    let filter = routes
-        .recover(error::handle_rejection)
-        .with(warp::log::custom(|info| {
-            let log_level = if info.elapsed().as_secs() >= 10 {
-                log::Level::Warn
-            } else if info.elapsed().as_millis() >= 250 || !info.status().is_success() {
-                log::Level::Info
-            } else {
-                log::Level::Debug
-            };
-
-            log::log!(
-                target: "glyph_bot::api",
-                log_level,
-                "{} \"{} {} {:?}\" {} \"{}\" \"{}\" {:?}",
-                OptFmt(info.remote_addr()),
-                info.method(),
-                info.path(),
-                info.version(),
-                info.status().as_u16(),
-                OptFmt(info.referer()),
-                OptFmt(info.user_agent()),
-                info.elapsed(),
-            );
-        }));
+/// Logs that a component that should run as long as the process has stopped, returns false.
+fn report_stopped(component: &str, result: Result<(), tokio::task::JoinError>) -> bool {
+    match result {
+        Err(e) if e.is_panic() => tracing::error!("{component} panicked, exiting"),
+        _ => tracing::error!("{component} stopped unexpectedly, exiting"),
+    }
+    false
+}
+
+async fn serve_api() {
+    let routes = MODULES
+        .iter()
+        .flat_map(|module| module.routes())
+        .reduce(|routes, route| routes.or(route).unify().boxed())
+        .unwrap_or_else(|| {
+            warp::any()
+                .and_then(|| async { Err::<Box<dyn Reply>, Rejection>(warp::reject::not_found()) })
+                .boxed()
+        });
+
+    let routes = standby_reads_only()
+        .and(
+            openapi::openapi_route()
+                .or(module::versioned_routes(routes))
+                .unify(),
+        )
+        .boxed();
+
+    let filter = error::recover_with_request_id(routes).with(warp::trace(telemetry::request_span));
 
     warp::serve(filter).run(([127, 0, 0, 1], *API_PORT)).await;
 }
 
+/// Rejects requests other than reads while this instance is on standby, so that payment webhooks, gifts,
+/// vouchers and the role changes they make are only handled by the instance connected to the gateway.
+/// The caller or load balancer retries them against the leader.
+fn standby_reads_only() -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::method()
+        .and_then(|method: Method| async move {
+            if lease::is_leader() || [Method::GET, Method::HEAD, Method::OPTIONS].contains(&method)
+            {
+                Ok::<_, Rejection>(())
+            } else {
+                Err(Error::StandbyError.into())
+            }
+        })
+        .untuple_one()
+}
+
 // enable TLS for AsyncPgConnection, see https://github.com/weiznich/diesel_async/blob/main/examples/postgres/pooled-with-rustls
 
-fn establish_pg_ssl_connection(config: &str) -> BoxFuture<ConnectionResult<AsyncPgConnection>> {
+fn establish_pg_ssl_connection(config: &str) -> BoxFuture<'_, ConnectionResult<AsyncPgConnection>> {
     let fut = async {
-        // We first set up the way we want rustls to work.
-        let rustls_config = rustls::ClientConfig::builder()
-            .with_root_certificates(root_certs())
-            .with_no_client_auth();
-        let tls = tokio_postgres_rustls::MakeRustlsConnect::new(rustls_config);
-        let (client, conn) = tokio_postgres::connect(config, tls)
+        let (client, conn) = tokio_postgres::connect(config, make_rustls_connect())
             .await
             .map_err(|e| ConnectionError::BadConnection(e.to_string()))?;
         tokio::spawn(async move {
@@ -216,6 +587,14 @@ fn establish_pg_ssl_connection(config: &str) -> BoxFuture<ConnectionResult<Async
     fut.boxed()
 }
 
+/// Creates the rustls connector used for postgres connections if `GLYPH_PG_ENABLE_SSL` is set.
+pub fn make_rustls_connect() -> tokio_postgres_rustls::MakeRustlsConnect {
+    let rustls_config = rustls::ClientConfig::builder()
+        .with_root_certificates(root_certs())
+        .with_no_client_auth();
+    tokio_postgres_rustls::MakeRustlsConnect::new(rustls_config)
+}
+
 fn root_certs() -> rustls::RootCertStore {
     let mut roots = rustls::RootCertStore::empty();
     let certs =
@@ -237,84 +616,9 @@ fn load_certs(cert_path: &str) -> io::Result<Vec<CertificateDer<'static>>> {
     certs.collect()
 }
 
-fn configure_scheduler() -> Scheduler<Utc> {
-    let mut scheduler = Scheduler::with_tz(Utc);
-    scheduler
-        .every(clokwerk::Interval::Minutes(5))
-        .run(|| task::submit_task("refresh_aiode_supporters", task::refresh_aiode_supporters));
-
-    scheduler
-}
-
-fn start_task_scheduler_runtime(scheduler: Scheduler<Utc>) -> JoinHandle<()> {
-    std::thread::Builder::new()
-        .name(String::from("task_scheduler"))
-        .spawn(move || {
-            let mut task_scheduler_sentinel = TaskSchedulerSentinel { scheduler };
-
-            let runtime = match tokio::runtime::Builder::new_multi_thread()
-                .thread_name("task_tokio_worker")
-                .enable_all()
-                .build()
-            {
-                Ok(runtime) => runtime,
-                Err(e) => {
-                    eprintln!("Failed to start task scheduler runtime: {}", e);
-                    std::process::exit(1);
-                }
-            };
-
-            runtime.block_on(async {
-                loop {
-                    task_scheduler_sentinel.scheduler.run_pending();
-                    std::thread::sleep(std::time::Duration::from_millis(10));
-                }
-            });
-        })
-        .expect("Failed to spawn task scheduler thread")
-}
-
-struct TaskSchedulerSentinel {
-    scheduler: Scheduler<Utc>,
-}
-
-impl Drop for TaskSchedulerSentinel {
-    fn drop(&mut self) {
-        if std::thread::panicking() {
-            start_task_scheduler_runtime(configure_scheduler());
-        }
-    }
-}
-
-fn setup_logger() {
-    // create logs dir as fern does not appear to handle that itself
-    if !std::path::Path::new("logs/").exists() {
-        std::fs::create_dir("logs").expect("Failed to create logs/ directory");
-    }
-
-    let logging_level = if cfg!(debug_assertions) {
-        log::LevelFilter::Debug
-    } else {
-        log::LevelFilter::Info
-    };
-
-    fern::Dispatch::new()
-        .format(|out, message, record| {
-            out.finish(format_args!(
-                "[{}]{}[{}] {}",
-                record.level(),
-                chrono::Local::now().format("[%Y-%m-%d %H:%M:%S]"),
-                record.target(),
-                message
-            ))
-        })
-        .level(log::LevelFilter::Info)
-        .level_for("glyph_bot", logging_level)
-        .level_for("tracing::span", log::LevelFilter::Warn)
-        .level_for("serenity::gateway", log::LevelFilter::Warn)
-        .level_for("serenity::http", log::LevelFilter::Warn)
-        .chain(std::io::stdout())
-        .chain(fern::DateBased::new("logs/", "logs_%Y-%m-%d.log"))
-        .apply()
-        .expect("Failed to set up logging");
+fn scheduled_tasks() -> Vec<ScheduledTask> {
+    task::core_tasks()
+        .into_iter()
+        .chain(MODULES.iter().flat_map(|module| module.tasks()))
+        .collect()
 }
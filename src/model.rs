@@ -1,20 +1,169 @@
-use bigdecimal::BigDecimal;
 use chrono::{DateTime, Utc};
 use diesel::{associations::Identifiable, deserialize::Queryable, prelude::Insertable};
 
-use crate::schema::aiode_supporter;
+use crate::{
+    schema::{
+        account_link_code, aiode_supporter, linked_account, payment_event, supporter_announcement,
+        supporter_credits_opt_in, supporter_gift, user_badge, voucher, voucher_redemption,
+    },
+    snowflake::Snowflake,
+};
 
 #[derive(Clone, Debug, Identifiable, Insertable, Queryable)]
 #[diesel(table_name = aiode_supporter)]
 #[diesel(primary_key(user_id))]
 pub struct AiodeSupporter {
-    // postgres does not have an unsigned 64 bit integer type, therefore numeric is used
-    pub user_id: BigDecimal,
+    // postgres does not have an unsigned 64 bit integer type, see Snowflake for how ids are stored
+    pub user_id: Snowflake,
     pub creation_timestamp: DateTime<Utc>,
+    pub tier: Option<String>,
+    pub expiration_timestamp: Option<DateTime<Utc>>,
+    /// How the user obtained supporter status, see the `SUPPORTER_SOURCE_*` constants in [`crate::aiode`].
+    pub source: String,
+    /// Set while revocation is pending after the user lost the supporter role.
+    pub revocation_deadline: Option<DateTime<Utc>>,
 }
 
 #[derive(Clone, Debug, Insertable)]
 #[diesel(table_name = aiode_supporter)]
 pub struct NewAiodeSupporter {
-    pub user_id: BigDecimal,
+    pub user_id: Snowflake,
+    pub source: String,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[diesel(table_name = aiode_supporter)]
+pub struct NewTimeLimitedAiodeSupporter {
+    pub user_id: Snowflake,
+    pub tier: Option<String>,
+    pub expiration_timestamp: DateTime<Utc>,
+    pub source: String,
+}
+
+#[derive(Clone, Debug, Identifiable, Queryable)]
+#[diesel(table_name = linked_account)]
+#[diesel(primary_key(provider, external_id))]
+pub struct LinkedAccount {
+    pub provider: String,
+    pub external_id: String,
+    pub user_id: Snowflake,
+    pub creation_timestamp: DateTime<Utc>,
+    pub verified: bool,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[diesel(table_name = linked_account)]
+pub struct NewLinkedAccount {
+    pub provider: String,
+    pub external_id: String,
+    pub user_id: Snowflake,
+    pub verified: bool,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[diesel(table_name = account_link_code)]
+pub struct NewAccountLinkCode {
+    pub code_hash: String,
+    pub user_id: Snowflake,
+    pub provider: String,
+    pub expiration_timestamp: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[diesel(table_name = payment_event)]
+pub struct NewPaymentEvent {
+    pub provider: String,
+    pub event_id: String,
+    pub external_id: String,
+    pub event_type: String,
+    pub user_id: Option<Snowflake>,
+    pub pending_action: Option<String>,
+    pub pending_grant_days: Option<i32>,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[diesel(table_name = supporter_announcement)]
+pub struct NewSupporterAnnouncement {
+    pub announcement_key: String,
+    pub user_id: Option<Snowflake>,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[diesel(table_name = supporter_credits_opt_in)]
+pub struct NewSupporterCreditsOptIn {
+    pub user_id: Snowflake,
+}
+
+#[derive(Clone, Debug, Identifiable, Queryable)]
+#[diesel(table_name = supporter_gift)]
+#[diesel(primary_key(pk))]
+pub struct SupporterGift {
+    pub pk: i32,
+    pub gifter_user_id: Option<Snowflake>,
+    pub recipient_user_id: Snowflake,
+    pub tier: String,
+    pub duration_days: i32,
+    pub expiration_timestamp: DateTime<Utc>,
+    pub creation_timestamp: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[diesel(table_name = supporter_gift)]
+pub struct NewSupporterGift {
+    pub gifter_user_id: Option<Snowflake>,
+    pub recipient_user_id: Snowflake,
+    pub tier: String,
+    pub duration_days: i32,
+    pub expiration_timestamp: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, Identifiable, Queryable)]
+#[diesel(table_name = user_badge)]
+#[diesel(primary_key(user_id, badge))]
+pub struct UserBadge {
+    pub user_id: Snowflake,
+    pub badge: String,
+    pub creation_timestamp: DateTime<Utc>,
+    pub revocation_deadline: Option<DateTime<Utc>>,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[diesel(table_name = user_badge)]
+pub struct NewUserBadge {
+    pub user_id: Snowflake,
+    pub badge: String,
+}
+
+#[derive(Clone, Debug, Identifiable, Queryable)]
+#[diesel(table_name = voucher)]
+#[diesel(primary_key(pk))]
+pub struct Voucher {
+    pub pk: i32,
+    pub code_hash: String,
+    pub batch: String,
+    pub tier: String,
+    pub duration_days: i32,
+    pub max_redemptions: i32,
+    pub redemption_count: i32,
+    pub revoked: bool,
+    pub expiration_timestamp: Option<DateTime<Utc>>,
+    pub creation_timestamp: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[diesel(table_name = voucher)]
+pub struct NewVoucher {
+    pub code_hash: String,
+    pub batch: String,
+    pub tier: String,
+    pub duration_days: i32,
+    pub max_redemptions: i32,
+    pub expiration_timestamp: Option<DateTime<Utc>>,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[diesel(table_name = voucher_redemption)]
+pub struct NewVoucherRedemption {
+    pub voucher_pk: i32,
+    pub user_id: Snowflake,
 }
@@ -0,0 +1,308 @@
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use serenity::{
+    all::{
+        CommandInteraction, Context, CreateCommand, GatewayIntents, Guild, GuildId,
+        GuildMemberUpdateEvent, Member, User,
+    },
+    async_trait,
+};
+use utoipa::openapi::OpenApi;
+use warp::{filters::BoxedFilter, path::FullPath, reject::Rejection, reply::Reply, Filter};
+
+use crate::{error::Error, MODULE_NAMES};
+
+#[cfg(feature = "accounts")]
+pub mod accounts;
+#[cfg(feature = "announcements")]
+pub mod announcements;
+#[cfg(feature = "badges")]
+pub mod badges;
+#[cfg(feature = "credits")]
+pub mod credits;
+#[cfg(feature = "gifts")]
+pub mod gifts;
+#[cfg(feature = "guilds")]
+pub mod guilds;
+#[cfg(feature = "payments")]
+pub mod payments;
+#[cfg(feature = "supporters")]
+pub mod supporters;
+#[cfg(feature = "vouchers")]
+pub mod vouchers;
+
+/// A type-erased warp route contributed by a module.
+pub type Route = BoxedFilter<(Box<dyn Reply>,)>;
+
+/// Path segment prefixing the routes of the current API version.
+pub const API_VERSION: &str = "v1";
+
+/// Creates the future executing a scheduled task.
+pub type TaskFn = fn() -> BoxFuture<'static, Result<(), Error>>;
+
+/// A task executed at a fixed interval, see [`crate::task::submit_task`].
+pub struct ScheduledTask {
+    pub id: &'static str,
+    pub interval: Duration,
+    /// The task is cancelled if it runs longer than this.
+    pub timeout: Duration,
+    pub run: TaskFn,
+}
+
+/// A feature of the bot. Each module contributes the gateway intents it requires, slash commands,
+/// API routes, scheduled tasks and gateway event hooks. Modules are compiled in via their cargo
+/// feature and enabled via `GLYPH_MODULES`.
+#[async_trait]
+pub trait Module: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn intents(&self) -> GatewayIntents {
+        GatewayIntents::empty()
+    }
+
+    fn commands(&self) -> Vec<CreateCommand> {
+        Vec::new()
+    }
+
+    fn routes(&self) -> Vec<Route> {
+        Vec::new()
+    }
+
+    /// Documents the routes, relative to the `/v1` prefix, see [`crate::openapi`].
+    fn openapi(&self) -> OpenApi {
+        OpenApi::default()
+    }
+
+    fn tasks(&self) -> Vec<ScheduledTask> {
+        Vec::new()
+    }
+
+    /// Handles the slash command and returns the content of the ephemeral response, or `None` if the
+    /// command does not belong to this module.
+    async fn run_command(
+        &self,
+        _ctx: &Context,
+        _command: &CommandInteraction,
+    ) -> Option<Result<String, Error>> {
+        None
+    }
+
+    // the gateway event hooks do not receive the serenity context so that archived events can be
+    // replayed without a gateway connection, see `crate::event_archive`
+
+    async fn guild_create(&self, _guild: &Guild) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn guild_member_addition(&self, _member: &Member) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn guild_member_removal(&self, _guild_id: GuildId, _user: &User) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn guild_member_update(
+        &self,
+        _old: Option<&Member>,
+        _new: Option<&Member>,
+        _event: &GuildMemberUpdateEvent,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Boxes the filter of a route so that routes of all modules can be combined.
+pub fn route<F, R>(filter: F) -> Route
+where
+    F: Filter<Extract = (R,), Error = Rejection> + Clone + Send + Sync + 'static,
+    R: Reply + 'static,
+{
+    filter
+        .map(|reply| Box::new(reply) as Box<dyn Reply>)
+        .boxed()
+}
+
+/// First path segments of the routes that have been served before the API was versioned.
+const UNVERSIONED_ROUTES: [&str; 1] = ["is-aiode-supporter"];
+
+/// Serves the routes under `/v1` and, as deprecated aliases, the routes that predate versioning at
+/// their unversioned paths. Responses of the aliases carry a `Deprecation` header and link to the
+/// versioned path.
+pub fn versioned_routes(routes: Route) -> Route {
+    let deprecated_routes = warp::path::full()
+        .and(unversioned_route())
+        .and(routes.clone())
+        .map(|path: FullPath, reply: Box<dyn Reply>| {
+            let successor = format!(
+                "</{API_VERSION}{}>; rel=\"successor-version\"",
+                path.as_str()
+            );
+            Box::new(warp::reply::with_header(
+                warp::reply::with_header(reply, "deprecation", "true"),
+                "link",
+                successor,
+            )) as Box<dyn Reply>
+        });
+
+    warp::path(API_VERSION)
+        .and(routes)
+        .or(deprecated_routes)
+        .unify()
+        .boxed()
+}
+
+fn unversioned_route() -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::path::peek()
+        .and_then(|path: warp::path::Peek| async move {
+            match path.segments().next() {
+                Some(segment) if UNVERSIONED_ROUTES.contains(&segment) => Ok(()),
+                _ => Err(warp::reject::not_found()),
+            }
+        })
+        .untuple_one()
+}
+
+/// Returns all modules compiled into the binary.
+#[allow(clippy::vec_init_then_push, unused_mut)] // the pushes depend on the enabled features
+fn available_modules() -> Vec<Box<dyn Module>> {
+    let mut modules: Vec<Box<dyn Module>> = Vec::new();
+    #[cfg(feature = "supporters")]
+    modules.push(Box::new(supporters::SupporterModule));
+    #[cfg(feature = "accounts")]
+    modules.push(Box::new(accounts::AccountModule));
+    #[cfg(feature = "payments")]
+    modules.push(Box::new(payments::PaymentModule));
+    #[cfg(feature = "vouchers")]
+    modules.push(Box::new(vouchers::VoucherModule));
+    #[cfg(feature = "gifts")]
+    modules.push(Box::new(gifts::GiftModule));
+    #[cfg(feature = "announcements")]
+    modules.push(Box::new(announcements::AnnouncementModule));
+    #[cfg(feature = "credits")]
+    modules.push(Box::new(credits::CreditsModule));
+    #[cfg(feature = "badges")]
+    modules.push(Box::new(badges::BadgeModule));
+    #[cfg(feature = "guilds")]
+    modules.push(Box::new(guilds::GuildModule));
+    modules
+}
+
+/// Returns the compiled in modules enabled by `GLYPH_MODULES`, or all of them if it is not set.
+pub fn enabled_modules() -> Vec<Box<dyn Module>> {
+    let available_modules = available_modules();
+
+    let Some(ref module_names) = *MODULE_NAMES else {
+        return available_modules;
+    };
+
+    for module_name in module_names.iter() {
+        if !available_modules
+            .iter()
+            .any(|module| module.name() == module_name)
+        {
+            tracing::warn!("Module {module_name} enabled in GLYPH_MODULES is not available, make sure its cargo feature is enabled");
+        }
+    }
+
+    available_modules
+        .into_iter()
+        .filter(|module| module_names.iter().any(|name| name == module.name()))
+        .collect()
+}
+
+/// The gateway intents required by the given modules. `GUILDS` is always requested as the cache
+/// relies on it.
+pub fn required_intents(modules: &[Box<dyn Module>]) -> GatewayIntents {
+    modules
+        .iter()
+        .fold(GatewayIntents::GUILDS, |intents, module| {
+            intents | module.intents()
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use warp::{http::StatusCode, test::request};
+
+    use super::*;
+    use crate::error::{recover_with_request_id, Error};
+
+    fn routes() -> Route {
+        route(
+            warp::path!("users" / u64)
+                .and(warp::get())
+                .map(|user_id: u64| user_id.to_string()),
+        )
+        .or(route(
+            warp::path!("is-aiode-supporter" / u64)
+                .and(warp::get())
+                .map(|user_id: u64| user_id.to_string()),
+        ))
+        .unify()
+        .or(route(warp::path!("admin").and_then(|| async {
+            Err::<String, Rejection>(Error::UnauthorizedError.into())
+        })))
+        .unify()
+        .boxed()
+    }
+
+    #[tokio::test]
+    async fn versioned_route_is_not_deprecated() {
+        let response = request()
+            .path("/v1/users/1")
+            .reply(&versioned_routes(routes()))
+            .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.body(), "1");
+        assert!(!response.headers().contains_key("deprecation"));
+    }
+
+    #[tokio::test]
+    async fn unversioned_route_is_deprecated_alias() {
+        let response = request()
+            .path("/is-aiode-supporter/1")
+            .reply(&versioned_routes(routes()))
+            .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.body(), "1");
+        assert_eq!(response.headers()["deprecation"], "true");
+        assert_eq!(
+            response.headers()["link"],
+            "</v1/is-aiode-supporter/1>; rel=\"successor-version\""
+        );
+    }
+
+    #[tokio::test]
+    async fn routes_added_after_versioning_have_no_unversioned_alias() {
+        let response = request()
+            .path("/users/1")
+            .reply(&recover_with_request_id(versioned_routes(routes())))
+            .await;
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn versioned_route_keeps_rejection() {
+        let response = request()
+            .path("/v1/admin")
+            .reply(&recover_with_request_id(versioned_routes(routes())))
+            .await;
+
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn unknown_versioned_path_is_not_found() {
+        let response = request()
+            .path("/v1/unknown")
+            .reply(&recover_with_request_id(versioned_routes(routes())))
+            .await;
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,107 @@
+use serenity::{
+    all::{CommandInteraction, Context, CreateCommand},
+    async_trait,
+};
+use utoipa::OpenApi;
+use warp::Filter;
+
+use crate::{
+    account, auth, command,
+    error::Error,
+    module::{route, Module, Route},
+    JSON_BODY_LIMIT,
+};
+
+/// Links discord accounts with accounts of external providers.
+pub struct AccountModule;
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(account::verify_linked_account_handler, account::get_linked_accounts_by_user_handler, account::get_linked_account_handler),
+    tags((name = "accounts", description = "Links between discord and external accounts"))
+)]
+struct AccountApi;
+
+#[async_trait]
+impl Module for AccountModule {
+    fn name(&self) -> &'static str {
+        "accounts"
+    }
+
+    fn commands(&self) -> Vec<CreateCommand> {
+        vec![command::create_link_command()]
+    }
+
+    fn routes(&self) -> Vec<Route> {
+        vec![
+            route(
+                warp::path!("linked-accounts" / "verify")
+                    .and(warp::post())
+                    .and(auth::admin_auth())
+                    .and(warp::body::content_length_limit(JSON_BODY_LIMIT))
+                    .and(warp::body::json())
+                    .and_then(account::verify_linked_account_handler),
+            ),
+            route(
+                warp::path!("linked-accounts" / "discord" / u64)
+                    .and(warp::get())
+                    .and(auth::admin_auth())
+                    .and_then(account::get_linked_accounts_by_user_handler),
+            ),
+            route(
+                warp::path!("linked-accounts" / String / String)
+                    .and(warp::get())
+                    .and(auth::admin_auth())
+                    .and_then(account::get_linked_account_handler),
+            ),
+        ]
+    }
+
+    fn openapi(&self) -> utoipa::openapi::OpenApi {
+        AccountApi::openapi()
+    }
+
+    async fn run_command(
+        &self,
+        _ctx: &Context,
+        command: &CommandInteraction,
+    ) -> Option<Result<String, Error>> {
+        match command.data.name.as_str() {
+            "link" => Some(command::handle_link_command(command).await),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use warp::{http::StatusCode, test::request};
+
+    use super::*;
+    use crate::{auth::tests::configure_admin_token, error::recover_with_request_id};
+
+    fn routes() -> Route {
+        AccountModule
+            .routes()
+            .into_iter()
+            .reduce(|routes, route| routes.or(route).unify().boxed())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn linked_account_lookups_require_admin_token() {
+        configure_admin_token();
+
+        for path in [
+            "/linked-accounts/discord/1",
+            "/linked-accounts/kofi/payer@example.com",
+        ] {
+            let response = request()
+                .path(path)
+                .reply(&recover_with_request_id(routes()))
+                .await;
+
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        }
+    }
+}
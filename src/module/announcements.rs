@@ -0,0 +1,28 @@
+use std::time::Duration;
+
+use futures::FutureExt;
+use serenity::async_trait;
+
+use crate::{
+    module::{Module, ScheduledTask},
+    task,
+};
+
+/// Announces supporter anniversaries and supporter count milestones.
+pub struct AnnouncementModule;
+
+#[async_trait]
+impl Module for AnnouncementModule {
+    fn name(&self) -> &'static str {
+        "announcements"
+    }
+
+    fn tasks(&self) -> Vec<ScheduledTask> {
+        vec![ScheduledTask {
+            id: "announce_supporter_milestones",
+            interval: Duration::from_secs(60 * 60),
+            timeout: Duration::from_secs(30 * 60),
+            run: || task::announce_supporter_milestones().boxed(),
+        }]
+    }
+}
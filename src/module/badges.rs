@@ -0,0 +1,87 @@
+use std::time::Duration;
+
+use futures::FutureExt;
+use serenity::{
+    all::{GatewayIntents, GuildMemberUpdateEvent, Member},
+    async_trait,
+};
+use utoipa::OpenApi;
+use warp::Filter;
+
+use crate::{
+    badge,
+    error::Error,
+    module::{route, Module, Route, ScheduledTask},
+    task, BADGE_ROLES,
+};
+
+/// Tracks the badges granted by the roles configured in `GLYPH_BADGE_ROLES`.
+pub struct BadgeModule;
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(badge::get_user_badges_handler),
+    tags((name = "badges", description = "Badges of discord users"))
+)]
+struct BadgeApi;
+
+#[async_trait]
+impl Module for BadgeModule {
+    fn name(&self) -> &'static str {
+        "badges"
+    }
+
+    fn intents(&self) -> GatewayIntents {
+        if BADGE_ROLES.is_empty() {
+            GatewayIntents::empty()
+        } else {
+            GatewayIntents::GUILD_MEMBERS
+        }
+    }
+
+    fn routes(&self) -> Vec<Route> {
+        vec![route(
+            warp::path!("users" / u64 / "badges")
+                .and(warp::get())
+                .and_then(badge::get_user_badges_handler),
+        )]
+    }
+
+    fn openapi(&self) -> utoipa::openapi::OpenApi {
+        BadgeApi::openapi()
+    }
+
+    fn tasks(&self) -> Vec<ScheduledTask> {
+        vec![
+            ScheduledTask {
+                id: "refresh_user_badges",
+                interval: Duration::from_secs(5 * 60),
+                timeout: Duration::from_secs(15 * 60),
+                run: || task::refresh_user_badges().boxed(),
+            },
+            ScheduledTask {
+                id: "finalise_badge_revocations",
+                interval: Duration::from_secs(5 * 60),
+                timeout: Duration::from_secs(10 * 60),
+                run: || task::finalise_badge_revocations().boxed(),
+            },
+        ]
+    }
+
+    async fn guild_member_update(
+        &self,
+        old: Option<&Member>,
+        new: Option<&Member>,
+        event: &GuildMemberUpdateEvent,
+    ) -> Result<(), Error> {
+        if !badge::BadgeConfig::from_env().tracks_guild(event.guild_id) {
+            return Ok(());
+        }
+
+        let old_roles = match (old, new) {
+            (Some(old), Some(_)) => Some(old.roles.as_slice()),
+            _ => None,
+        };
+        badge::handle_member_update(event.guild_id, event.user.id, old_roles, &event.roles).await
+    }
+}
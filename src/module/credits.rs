@@ -0,0 +1,69 @@
+use std::time::Duration;
+
+use futures::FutureExt;
+use serenity::{
+    all::{CommandInteraction, Context, CreateCommand},
+    async_trait,
+};
+use utoipa::OpenApi;
+use warp::Filter;
+
+use crate::{
+    command, credits,
+    error::Error,
+    module::{route, Module, Route, ScheduledTask},
+    task,
+};
+
+/// Public credits of the supporters that opted in to being listed.
+pub struct CreditsModule;
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(credits::get_supporter_credits_handler),
+    tags((name = "credits", description = "Public credits of supporters"))
+)]
+struct CreditsApi;
+
+#[async_trait]
+impl Module for CreditsModule {
+    fn name(&self) -> &'static str {
+        "credits"
+    }
+
+    fn commands(&self) -> Vec<CreateCommand> {
+        vec![command::create_credits_command()]
+    }
+
+    fn routes(&self) -> Vec<Route> {
+        vec![route(
+            warp::path!("supporters" / "credits")
+                .and(warp::get())
+                .and_then(credits::get_supporter_credits_handler),
+        )]
+    }
+
+    fn openapi(&self) -> utoipa::openapi::OpenApi {
+        CreditsApi::openapi()
+    }
+
+    fn tasks(&self) -> Vec<ScheduledTask> {
+        vec![ScheduledTask {
+            id: "refresh_supporter_credits",
+            interval: Duration::from_secs(15 * 60),
+            timeout: Duration::from_secs(10 * 60),
+            run: || task::refresh_supporter_credits().boxed(),
+        }]
+    }
+
+    async fn run_command(
+        &self,
+        _ctx: &Context,
+        command: &CommandInteraction,
+    ) -> Option<Result<String, Error>> {
+        match command.data.name.as_str() {
+            "credits" => Some(command::handle_credits_command(command).await),
+            _ => None,
+        }
+    }
+}
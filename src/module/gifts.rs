@@ -0,0 +1,69 @@
+use serenity::{
+    all::{CommandInteraction, Context, CreateCommand},
+    async_trait,
+};
+use utoipa::OpenApi;
+use warp::Filter;
+
+use crate::{
+    auth, command,
+    error::Error,
+    gift,
+    module::{route, Module, Route},
+    JSON_BODY_LIMIT,
+};
+
+/// Time limited supporter status gifted by supporters and admins.
+pub struct GiftModule;
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(gift::create_gift_handler, gift::get_gift_quota_handler),
+    tags((name = "gifts", description = "Supporter status gifted by supporters and admins"))
+)]
+struct GiftApi;
+
+#[async_trait]
+impl Module for GiftModule {
+    fn name(&self) -> &'static str {
+        "gifts"
+    }
+
+    fn commands(&self) -> Vec<CreateCommand> {
+        vec![command::create_gift_command()]
+    }
+
+    fn routes(&self) -> Vec<Route> {
+        vec![
+            route(
+                warp::path!("gifts")
+                    .and(warp::post())
+                    .and(auth::admin_auth())
+                    .and(warp::body::content_length_limit(JSON_BODY_LIMIT))
+                    .and(warp::body::json())
+                    .and_then(gift::create_gift_handler),
+            ),
+            route(
+                warp::path!("gifts" / "quota" / u64)
+                    .and(warp::get())
+                    .and(auth::admin_auth())
+                    .and_then(gift::get_gift_quota_handler),
+            ),
+        ]
+    }
+
+    fn openapi(&self) -> utoipa::openapi::OpenApi {
+        GiftApi::openapi()
+    }
+
+    async fn run_command(
+        &self,
+        _ctx: &Context,
+        command: &CommandInteraction,
+    ) -> Option<Result<String, Error>> {
+        match command.data.name.as_str() {
+            "gift" => Some(command::handle_gift_command(command).await),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use serenity::{
+    all::{GatewayIntents, Guild, GuildId, GuildMemberUpdateEvent, Member, User},
+    async_trait,
+};
+use utoipa::OpenApi;
+use warp::Filter;
+
+use crate::{
+    auth,
+    error::Error,
+    guild,
+    module::{route, Module, Route},
+};
+
+/// Serves guild memberships and roles from the gateway cache to other services.
+pub struct GuildModule;
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(guild::get_guild_member_handler, guild::get_role_members_handler),
+    tags((name = "guilds", description = "Guild memberships and roles"))
+)]
+struct GuildApi;
+
+#[async_trait]
+impl Module for GuildModule {
+    fn name(&self) -> &'static str {
+        "guilds"
+    }
+
+    fn intents(&self) -> GatewayIntents {
+        GatewayIntents::GUILD_MEMBERS
+    }
+
+    fn routes(&self) -> Vec<Route> {
+        vec![
+            route(
+                warp::path!("guilds" / u64 / "members" / u64)
+                    .and(warp::get())
+                    .and(auth::admin_auth())
+                    .and_then(guild::get_guild_member_handler),
+            ),
+            route(
+                warp::path!("guilds" / u64 / "roles" / u64 / "members")
+                    .and(warp::get())
+                    .and(auth::admin_auth())
+                    .and_then(guild::get_role_members_handler),
+            ),
+        ]
+    }
+
+    fn openapi(&self) -> utoipa::openapi::OpenApi {
+        GuildApi::openapi()
+    }
+
+    async fn guild_create(&self, guild: &Guild) -> Result<(), Error> {
+        guild::mark_guild_synced(guild.id);
+        Ok(())
+    }
+
+    async fn guild_member_addition(&self, member: &Member) -> Result<(), Error> {
+        guild::mark_guild_updated(member.guild_id);
+        Ok(())
+    }
+
+    async fn guild_member_removal(&self, guild_id: GuildId, _user: &User) -> Result<(), Error> {
+        guild::mark_guild_updated(guild_id);
+        Ok(())
+    }
+
+    async fn guild_member_update(
+        &self,
+        _old: Option<&Member>,
+        _new: Option<&Member>,
+        event: &GuildMemberUpdateEvent,
+    ) -> Result<(), Error> {
+        guild::mark_guild_updated(event.guild_id);
+        Ok(())
+    }
+}
@@ -0,0 +1,283 @@
+use serenity::async_trait;
+use utoipa::OpenApi;
+use warp::Filter;
+
+use crate::{
+    module::{route, Module, Route},
+    payment, WEBHOOK_BODY_LIMIT,
+};
+
+/// Grants and revokes supporter status via payment provider webhooks.
+pub struct PaymentModule;
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(payment::kofi_webhook_handler, payment::patreon_webhook_handler, payment::stripe_webhook_handler),
+    tags((name = "payments", description = "Payment provider webhooks"))
+)]
+struct PaymentApi;
+
+#[async_trait]
+impl Module for PaymentModule {
+    fn name(&self) -> &'static str {
+        "payments"
+    }
+
+    fn routes(&self) -> Vec<Route> {
+        vec![
+            route(
+                warp::path!("webhooks" / "kofi")
+                    .and(warp::post())
+                    .and(warp::body::content_length_limit(WEBHOOK_BODY_LIMIT))
+                    .and(warp::body::form())
+                    .and_then(payment::kofi_webhook_handler),
+            ),
+            route(
+                warp::path!("webhooks" / "patreon")
+                    .and(warp::post())
+                    .and(warp::header::<String>("x-patreon-event"))
+                    .and(warp::header::<String>("x-patreon-signature"))
+                    .and(warp::body::content_length_limit(WEBHOOK_BODY_LIMIT))
+                    .and(warp::body::bytes())
+                    .and_then(payment::patreon_webhook_handler),
+            ),
+            route(
+                warp::path!("webhooks" / "stripe")
+                    .and(warp::post())
+                    .and(warp::header::<String>("stripe-signature"))
+                    .and(warp::body::content_length_limit(WEBHOOK_BODY_LIMIT))
+                    .and(warp::body::bytes())
+                    .and_then(payment::stripe_webhook_handler),
+            ),
+        ]
+    }
+
+    fn openapi(&self) -> utoipa::openapi::OpenApi {
+        PaymentApi::openapi()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use diesel::{ExpressionMethods, QueryDsl};
+    use diesel_async::RunQueryDsl;
+    use hmac::{Hmac, Mac};
+    use md5::Md5;
+    use sha2::Sha256;
+    use warp::{http::StatusCode, test::request};
+
+    use super::*;
+    use crate::{
+        acquire_db_connection, error::recover_with_request_id, payment::PROVIDER_STRIPE,
+        schema::payment_event, test_db::test_connection, util::generate_code,
+    };
+
+    const KOFI_TOKEN: &str = "kofi-token";
+    const PATREON_SECRET: &str = "patreon-secret";
+    const STRIPE_SECRET: &str = "stripe-secret";
+
+    /// Configures the webhook secrets. They are read once, so all tests use the same secrets.
+    fn configure_webhook_secrets() {
+        std::env::set_var("GLYPH_KOFI_VERIFICATION_TOKEN", KOFI_TOKEN);
+        std::env::set_var("GLYPH_PATREON_WEBHOOK_SECRET", PATREON_SECRET);
+        std::env::set_var("GLYPH_STRIPE_WEBHOOK_SECRET", STRIPE_SECRET);
+    }
+
+    fn routes() -> Route {
+        PaymentModule
+            .routes()
+            .into_iter()
+            .reduce(|routes, route| routes.or(route).unify().boxed())
+            .unwrap()
+    }
+
+    async fn status(request: warp::test::RequestBuilder) -> StatusCode {
+        request
+            .method("POST")
+            .reply(&recover_with_request_id(routes()))
+            .await
+            .status()
+    }
+
+    fn kofi_request(token: &str, event_type: &str, message_id: &str) -> warp::test::RequestBuilder {
+        let data = serde_json::json!({
+            "verification_token": token,
+            "message_id": message_id,
+            "type": event_type,
+            "email": "payer@example.com",
+        })
+        .to_string();
+        // percent-encodes everything but alphanumerics, which is valid form encoding
+        let data = data
+            .bytes()
+            .map(|byte| {
+                if byte.is_ascii_alphanumeric() {
+                    char::from(byte).to_string()
+                } else {
+                    format!("%{byte:02X}")
+                }
+            })
+            .collect::<String>();
+        request()
+            .path("/webhooks/kofi")
+            .header("content-type", "application/x-www-form-urlencoded")
+            .body(format!("data={data}"))
+    }
+
+    fn patreon_request(secret: &str, patron_status: &str) -> warp::test::RequestBuilder {
+        let body = serde_json::json!({
+            "data": {
+                "attributes": { "patron_status": patron_status },
+                "relationships": { "user": { "data": { "id": "patron-1", "type": "user" } } },
+            }
+        })
+        .to_string();
+        let mut mac = Hmac::<Md5>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(body.as_bytes());
+        request()
+            .path("/webhooks/patreon")
+            .header("x-patreon-event", "members:update")
+            .header(
+                "x-patreon-signature",
+                hex::encode(mac.finalize().into_bytes()),
+            )
+            .body(body)
+    }
+
+    fn stripe_request(
+        secret: &str,
+        event_id: &str,
+        event_type: &str,
+        customer: &str,
+    ) -> warp::test::RequestBuilder {
+        let body = serde_json::json!({
+            "id": event_id,
+            "type": event_type,
+            "data": { "object": { "customer": customer, "mode": "payment" } },
+        })
+        .to_string();
+        let timestamp = chrono::Utc::now().timestamp();
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(format!("{timestamp}.{body}").as_bytes());
+        request()
+            .path("/webhooks/stripe")
+            .header(
+                "stripe-signature",
+                format!(
+                    "t={timestamp},v1={}",
+                    hex::encode(mac.finalize().into_bytes())
+                ),
+            )
+            .body(body)
+    }
+
+    #[tokio::test]
+    async fn kofi_webhook_checks_verification_token() {
+        configure_webhook_secrets();
+
+        assert_eq!(
+            status(kofi_request("other", "Subscription", "message-1")).await,
+            StatusCode::UNAUTHORIZED
+        );
+        // ignored without touching the database
+        assert_eq!(
+            status(kofi_request(KOFI_TOKEN, "Donation", "message-1")).await,
+            StatusCode::OK
+        );
+    }
+
+    #[tokio::test]
+    async fn patreon_webhook_checks_signature() {
+        configure_webhook_secrets();
+
+        assert_eq!(
+            status(patreon_request("other", "active_patron")).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status(
+                patreon_request(PATREON_SECRET, "active_patron")
+                    .header("x-patreon-signature", "not hex")
+            )
+            .await,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            status(patreon_request(PATREON_SECRET, "unknown")).await,
+            StatusCode::OK
+        );
+    }
+
+    #[tokio::test]
+    async fn stripe_webhook_checks_signature() {
+        configure_webhook_secrets();
+
+        assert_eq!(
+            status(stripe_request("other", "evt_1", "invoice.paid", "cus_1")).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status(
+                request()
+                    .path("/webhooks/stripe")
+                    .body(r#"{"id": "evt_1"}"#)
+            )
+            .await,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            status(stripe_request(
+                STRIPE_SECRET,
+                "evt_1",
+                "invoice.created",
+                "cus_1"
+            ))
+            .await,
+            StatusCode::OK
+        );
+    }
+
+    #[tokio::test]
+    async fn duplicate_delivery_is_recorded_once() {
+        // the webhooks commit via the connection pool, which is pointed at the test database
+        let Some(mut connection) = test_connection().await else {
+            return;
+        };
+        std::env::set_var(
+            "GLYPH_DATABASE_URL",
+            std::env::var("GLYPH_TEST_DATABASE_URL").unwrap(),
+        );
+        configure_webhook_secrets();
+        // unlinked, so the event is recorded as pending without granting anything
+        let event_id = format!("evt_{}", generate_code(12));
+        let customer = format!("cus_{}", generate_code(12));
+
+        for _ in 0..2 {
+            assert_eq!(
+                status(stripe_request(
+                    STRIPE_SECRET,
+                    &event_id,
+                    "checkout.session.completed",
+                    &customer
+                ))
+                .await,
+                StatusCode::OK
+            );
+        }
+
+        let pending_actions = payment_event::table
+            .filter(payment_event::provider.eq(PROVIDER_STRIPE))
+            .filter(payment_event::external_id.eq(&customer))
+            .select(payment_event::pending_action)
+            .load::<Option<String>>(&mut connection)
+            .await
+            .unwrap();
+        diesel::delete(payment_event::table)
+            .filter(payment_event::provider.eq(PROVIDER_STRIPE))
+            .filter(payment_event::external_id.eq(&customer))
+            .execute(&mut acquire_db_connection().await.unwrap())
+            .await
+            .unwrap();
+        assert_eq!(pending_actions, vec![Some(String::from("grant_days"))]);
+    }
+}
@@ -0,0 +1,81 @@
+use std::time::Duration;
+
+use futures::FutureExt;
+use serenity::{
+    all::{GatewayIntents, GuildMemberUpdateEvent, Member},
+    async_trait,
+};
+use utoipa::OpenApi;
+use warp::Filter;
+
+use crate::{
+    aiode,
+    error::Error,
+    module::{route, Module, Route, ScheduledTask},
+    task, AIODE_BOOSTERS_ARE_SUPPORTERS, AIODE_SUPPORTER_ROLE_ID, AIODE_SUPPORT_GUILD_ID,
+};
+
+/// Tracks supporters via the supporter role and boosts on the support guild and serves their status.
+pub struct SupporterModule;
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(aiode::check_is_aiode_supporter_handler),
+    tags((name = "supporters", description = "Supporter status of discord users"))
+)]
+struct SupporterApi;
+
+fn tracks_support_guild() -> bool {
+    AIODE_SUPPORT_GUILD_ID.is_some()
+        && (AIODE_SUPPORTER_ROLE_ID.is_some() || *AIODE_BOOSTERS_ARE_SUPPORTERS)
+}
+
+#[async_trait]
+impl Module for SupporterModule {
+    fn name(&self) -> &'static str {
+        "supporters"
+    }
+
+    fn intents(&self) -> GatewayIntents {
+        if tracks_support_guild() {
+            GatewayIntents::GUILD_MEMBERS
+        } else {
+            GatewayIntents::empty()
+        }
+    }
+
+    fn routes(&self) -> Vec<Route> {
+        vec![route(
+            warp::path!("is-aiode-supporter" / u64)
+                .and(warp::get())
+                .and_then(aiode::check_is_aiode_supporter_handler),
+        )]
+    }
+
+    fn openapi(&self) -> utoipa::openapi::OpenApi {
+        SupporterApi::openapi()
+    }
+
+    fn tasks(&self) -> Vec<ScheduledTask> {
+        vec![ScheduledTask {
+            id: "refresh_aiode_supporters",
+            interval: Duration::from_secs(5 * 60),
+            timeout: Duration::from_secs(15 * 60),
+            run: || task::refresh_aiode_supporters().boxed(),
+        }]
+    }
+
+    async fn guild_member_update(
+        &self,
+        old: Option<&Member>,
+        new: Option<&Member>,
+        event: &GuildMemberUpdateEvent,
+    ) -> Result<(), Error> {
+        if !tracks_support_guild() || event.guild_id != AIODE_SUPPORT_GUILD_ID.unwrap() {
+            return Ok(());
+        }
+
+        tracing::debug!("Received GuildMemberUpdateEvent for user {} on guild {}. Old: {old:?}, new: {new:?}, event: {event:?}", event.user.id, event.guild_id);
+        aiode::handle_member_update(old, new, event).await
+    }
+}
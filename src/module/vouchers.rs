@@ -0,0 +1,75 @@
+use serenity::{
+    all::{CommandInteraction, Context, CreateCommand},
+    async_trait,
+};
+use utoipa::OpenApi;
+use warp::Filter;
+
+use crate::{
+    auth, command,
+    error::Error,
+    module::{route, Module, Route},
+    voucher, JSON_BODY_LIMIT,
+};
+
+/// Supporter vouchers created by admins and redeemed by users.
+pub struct VoucherModule;
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(voucher::create_vouchers_handler, voucher::list_vouchers_handler, voucher::revoke_voucher_handler),
+    tags((name = "vouchers", description = "Supporter vouchers, requires the admin token"))
+)]
+struct VoucherApi;
+
+#[async_trait]
+impl Module for VoucherModule {
+    fn name(&self) -> &'static str {
+        "vouchers"
+    }
+
+    fn commands(&self) -> Vec<CreateCommand> {
+        vec![command::create_redeem_command()]
+    }
+
+    fn routes(&self) -> Vec<Route> {
+        vec![
+            route(
+                warp::path!("admin" / "vouchers")
+                    .and(warp::post())
+                    .and(auth::admin_auth())
+                    .and(warp::body::content_length_limit(JSON_BODY_LIMIT))
+                    .and(warp::body::json())
+                    .and_then(voucher::create_vouchers_handler),
+            ),
+            route(
+                warp::path!("admin" / "vouchers")
+                    .and(warp::get())
+                    .and(auth::admin_auth())
+                    .and(warp::query())
+                    .and_then(voucher::list_vouchers_handler),
+            ),
+            route(
+                warp::path!("admin" / "vouchers" / i32 / "revoke")
+                    .and(warp::post())
+                    .and(auth::admin_auth())
+                    .and_then(voucher::revoke_voucher_handler),
+            ),
+        ]
+    }
+
+    fn openapi(&self) -> utoipa::openapi::OpenApi {
+        VoucherApi::openapi()
+    }
+
+    async fn run_command(
+        &self,
+        _ctx: &Context,
+        command: &CommandInteraction,
+    ) -> Option<Result<String, Error>> {
+        match command.data.name.as_str() {
+            "redeem" => Some(command::handle_redeem_command(command).await),
+            _ => None,
+        }
+    }
+}
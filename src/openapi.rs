@@ -0,0 +1,156 @@
+use lazy_static::lazy_static;
+use utoipa::{
+    openapi::{
+        self,
+        security::{HttpAuthScheme, HttpBuilder, SecurityScheme},
+    },
+    Modify, OpenApi,
+};
+use warp::Filter;
+
+use crate::{
+    error::ErrorResponse,
+    module::{route, Module, Route, API_VERSION},
+    MODULES,
+};
+
+lazy_static! {
+    static ref API_DOC: openapi::OpenApi = api_doc(&MODULES);
+}
+
+/// Parts of the document shared by all modules, the paths are contributed by the modules via
+/// [`Module::openapi`].
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "glyph-bot",
+        description = "API of glyph-bot. All errors are answered with an `ErrorResponse` and every \
+            response carries an `x-request-id` header."
+    ),
+    servers((url = "/v1")),
+    components(schemas(ErrorResponse)),
+    modifiers(&AdminTokenSecurity)
+)]
+struct ApiDoc;
+
+/// Adds the `admin_token` bearer scheme referenced by routes requiring `GLYPH_ADMIN_API_TOKEN`.
+struct AdminTokenSecurity;
+
+impl Modify for AdminTokenSecurity {
+    fn modify(&self, openapi: &mut openapi::OpenApi) {
+        openapi
+            .components
+            .get_or_insert_with(Default::default)
+            .add_security_scheme(
+                "admin_token",
+                SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+            );
+    }
+}
+
+/// Generates the OpenAPI document of the routes of the given modules.
+pub fn api_doc(modules: &[Box<dyn Module>]) -> openapi::OpenApi {
+    let mut api_doc = ApiDoc::openapi();
+    // utoipa takes the license from Cargo.toml, which does not declare one
+    api_doc.info.license = None;
+    modules.iter().fold(api_doc, |api_doc, module| {
+        api_doc.merge_from(module.openapi())
+    })
+}
+
+/// Serves the OpenAPI document of the enabled modules at `/v1/openapi.json`.
+pub fn openapi_route() -> Route {
+    route(
+        warp::path(API_VERSION)
+            .and(warp::path!("openapi.json"))
+            .and(warp::get())
+            .map(|| warp::reply::json(&*API_DOC)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+    use crate::module::enabled_modules;
+
+    fn api_doc_json() -> Value {
+        serde_json::to_value(api_doc(&enabled_modules())).unwrap()
+    }
+
+    #[test]
+    fn documents_routes_of_all_modules_relative_to_version_prefix() {
+        let api_doc = api_doc_json();
+
+        assert_eq!(api_doc["servers"][0]["url"], "/v1");
+        let paths = api_doc["paths"].as_object().unwrap();
+        for path in [
+            "/is-aiode-supporter/{user_id}",
+            "/supporters/credits",
+            "/users/{user_id}/badges",
+            "/guilds/{guild_id}/members/{user_id}",
+            "/guilds/{guild_id}/roles/{role_id}/members",
+            "/linked-accounts/verify",
+            "/linked-accounts/discord/{user_id}",
+            "/linked-accounts/{provider}/{external_id}",
+            "/gifts",
+            "/gifts/quota/{user_id}",
+            "/admin/vouchers",
+            "/admin/vouchers/{voucher_id}/revoke",
+            "/webhooks/kofi",
+            "/webhooks/patreon",
+            "/webhooks/stripe",
+        ] {
+            assert!(paths.contains_key(path), "{path} is not documented");
+        }
+        assert!(paths["/admin/vouchers"]["get"].is_object());
+        assert!(paths["/admin/vouchers"]["post"].is_object());
+    }
+
+    #[test]
+    fn references_response_schemas() {
+        let api_doc = api_doc_json();
+
+        let operation = &api_doc["paths"]["/is-aiode-supporter/{user_id}"]["get"];
+        assert_eq!(
+            operation["responses"]["200"]["content"]["application/json"]["schema"]["$ref"],
+            "#/components/schemas/CheckIsAiodeSupporterResponse"
+        );
+        assert_eq!(
+            operation["responses"]["500"]["content"]["application/json"]["schema"]["$ref"],
+            "#/components/schemas/ErrorResponse"
+        );
+
+        let schemas = api_doc["components"]["schemas"].as_object().unwrap();
+        for schema in [
+            "CheckIsAiodeSupporterResponse",
+            "SupporterGiftResponse",
+            "ErrorResponse",
+            "GuildMemberResponse",
+            "Freshness",
+            "VouchersResponse",
+        ] {
+            assert!(schemas.contains_key(schema), "{schema} is missing");
+        }
+        let required = schemas["CheckIsAiodeSupporterResponse"]["required"]
+            .as_array()
+            .unwrap();
+        assert!(required.contains(&Value::from("is_supporter")));
+        assert!(!required.contains(&Value::from("tier")));
+    }
+
+    #[test]
+    fn documents_admin_token_security() {
+        let api_doc = api_doc_json();
+
+        assert_eq!(
+            api_doc["components"]["securitySchemes"]["admin_token"]["scheme"],
+            "bearer"
+        );
+        assert_eq!(
+            api_doc["paths"]["/admin/vouchers"]["post"]["security"][0]["admin_token"],
+            serde_json::json!([])
+        );
+    }
+}
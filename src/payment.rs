@@ -0,0 +1,959 @@
+use diesel::{ExpressionMethods, QueryDsl};
+use diesel_async::{
+    scoped_futures::ScopedFutureExt, AsyncConnection, AsyncPgConnection, RunQueryDsl,
+};
+use hmac::{Hmac, Mac};
+use md5::Md5;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use utoipa::ToSchema;
+use warp::{hyper::body::Bytes, reject::Rejection, reply::Reply};
+
+use crate::{
+    account::{find_linked_user, normalise_external_id},
+    acquire_db_connection,
+    aiode::{
+        grant_aiode_supporter, grant_time_limited_aiode_supporter, revoke_payment_supporter,
+        DEFAULT_SUPPORTER_TIER, SUPPORTER_SOURCE_PAYMENT,
+    },
+    error::{Error, ErrorResponse},
+    model::NewPaymentEvent,
+    schema::payment_event,
+    snowflake::Snowflake,
+    util::constant_time_eq,
+    KOFI_VERIFICATION_TOKEN, PATREON_WEBHOOK_SECRET, STRIPE_WEBHOOK_SECRET,
+};
+
+pub const PROVIDER_KOFI: &str = "kofi";
+pub const PROVIDER_PATREON: &str = "patreon";
+pub const PROVIDER_STRIPE: &str = "stripe";
+
+/// Maximum accepted age of a stripe webhook signature timestamp, protects against replay attacks.
+const STRIPE_SIGNATURE_TOLERANCE_SECONDS: i64 = 300;
+
+/// Type of the Ko-fi events sent for each payment of a membership subscription.
+const KOFI_SUBSCRIPTION_EVENT_TYPE: &str = "Subscription";
+/// Days of supporter status granted per Ko-fi subscription payment, the monthly period rounded up.
+const KOFI_SUBSCRIPTION_PERIOD_DAYS: i64 = 31;
+/// Days of supporter status granted per one-off Stripe checkout, as there is no cancellation to revoke it.
+const STRIPE_ONE_OFF_PERIOD_DAYS: i64 = 31;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PaymentAction {
+    /// Grants permanent supporter status, for providers that send cancellation events.
+    Grant,
+    /// Grants supporter status for the given number of days, extending an existing time limit.
+    GrantDays(i64),
+    Revoke,
+}
+
+/// Provider independent representation of a payment webhook event that affects supporter status.
+#[derive(Clone, Debug)]
+pub struct PaymentEvent {
+    pub provider: &'static str,
+    pub event_id: String,
+    /// Identity of the payer on the provider's side, mapped to a discord user via the linked_account table.
+    pub external_id: String,
+    pub event_type: String,
+    pub action: PaymentAction,
+}
+
+/// Ko-fi posts a form with a single `data` field containing the JSON payload.
+#[derive(Deserialize, ToSchema)]
+pub struct KofiWebhookForm {
+    pub data: String,
+}
+
+#[derive(Deserialize)]
+struct KofiWebhookData {
+    verification_token: String,
+    message_id: String,
+    #[serde(rename = "type")]
+    event_type: String,
+    email: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct PatreonWebhookPayload {
+    data: PatreonMember,
+}
+
+#[derive(Deserialize)]
+struct PatreonMember {
+    attributes: PatreonMemberAttributes,
+    relationships: PatreonMemberRelationships,
+}
+
+#[derive(Deserialize)]
+struct PatreonMemberAttributes {
+    patron_status: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct PatreonMemberRelationships {
+    user: PatreonRelationship,
+}
+
+#[derive(Deserialize)]
+struct PatreonRelationship {
+    data: PatreonResourceIdentifier,
+}
+
+#[derive(Deserialize)]
+struct PatreonResourceIdentifier {
+    id: String,
+}
+
+#[derive(Deserialize)]
+struct StripeEvent {
+    id: String,
+    #[serde(rename = "type")]
+    event_type: String,
+    data: StripeEventData,
+}
+
+#[derive(Deserialize)]
+struct StripeEventData {
+    object: StripeEventObject,
+}
+
+#[derive(Deserialize)]
+struct StripeEventObject {
+    customer: Option<String>,
+    /// Set for checkout sessions, `payment` for one-off payments and `subscription` for subscriptions.
+    mode: Option<String>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/webhooks/kofi",
+    tag = "payments",
+    request_body(content = KofiWebhookForm, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 200, description = "The event has been processed or ignored"),
+        (status = 400, description = "Malformed event", body = ErrorResponse),
+        (status = 401, description = "Invalid verification token", body = ErrorResponse),
+        (status = 404, description = "GLYPH_KOFI_VERIFICATION_TOKEN is not configured", body = ErrorResponse),
+        (status = 500, description = "The event could not be processed", body = ErrorResponse),
+    ),
+)]
+pub async fn kofi_webhook_handler(form: KofiWebhookForm) -> Result<impl Reply, Rejection> {
+    let Some(ref verification_token) = *KOFI_VERIFICATION_TOKEN else {
+        return Err(warp::reject::not_found());
+    };
+
+    let data = serde_json::from_str::<KofiWebhookData>(&form.data)
+        .map_err(|e| Error::InvalidPayloadError(e.to_string()))?;
+    if !constant_time_eq(
+        data.verification_token.as_bytes(),
+        verification_token.as_bytes(),
+    ) {
+        return Err(Error::WebhookSignatureError.into());
+    }
+
+    if let Some(event) = kofi_payment_event(data) {
+        process_payment_event(event).await?;
+    }
+
+    Ok(warp::reply())
+}
+
+/// Ko-fi does not send cancellation events, so only subscription payments grant supporter status,
+/// each for one subscription period. One-off donations and shop orders are ignored.
+fn kofi_payment_event(data: KofiWebhookData) -> Option<PaymentEvent> {
+    if data.event_type != KOFI_SUBSCRIPTION_EVENT_TYPE {
+        tracing::debug!(
+            "Ignoring Ko-fi event {} of type {}",
+            data.message_id,
+            data.event_type
+        );
+        return None;
+    }
+
+    let Some(email) = data.email else {
+        tracing::warn!(
+            "Ignoring Ko-fi event {} without payer email",
+            data.message_id
+        );
+        return None;
+    };
+
+    Some(PaymentEvent {
+        provider: PROVIDER_KOFI,
+        event_id: data.message_id,
+        external_id: normalise_external_id(PROVIDER_KOFI, &email),
+        event_type: data.event_type,
+        action: PaymentAction::GrantDays(KOFI_SUBSCRIPTION_PERIOD_DAYS),
+    })
+}
+
+#[utoipa::path(
+    post,
+    path = "/webhooks/patreon",
+    tag = "payments",
+    params(
+        ("X-Patreon-Event" = String, Header, description = "Type of the event, e.g. `members:pledge:create`"),
+        ("X-Patreon-Signature" = String, Header, description = "Hex encoded HMAC-MD5 of the body"),
+    ),
+    request_body(content = Object, description = "Patreon member event"),
+    responses(
+        (status = 200, description = "The event has been processed or ignored"),
+        (status = 400, description = "Malformed event, signature or missing header", body = ErrorResponse),
+        (status = 401, description = "Invalid signature", body = ErrorResponse),
+        (status = 404, description = "GLYPH_PATREON_WEBHOOK_SECRET is not configured", body = ErrorResponse),
+        (status = 500, description = "The event could not be processed", body = ErrorResponse),
+    ),
+)]
+pub async fn patreon_webhook_handler(
+    event_type: String,
+    signature: String,
+    body: Bytes,
+) -> Result<impl Reply, Rejection> {
+    let Some(ref secret) = *PATREON_WEBHOOK_SECRET else {
+        return Err(warp::reject::not_found());
+    };
+
+    let signature =
+        hex::decode(signature).map_err(|e| Error::InvalidPayloadError(e.to_string()))?;
+    if !verify_patreon_signature(secret, &signature, &body) {
+        return Err(Error::WebhookSignatureError.into());
+    }
+
+    let event = patreon_payment_event(event_type, &body)
+        .map_err(|e| Error::InvalidPayloadError(e.to_string()))?;
+    if let Some(event) = event {
+        process_payment_event(event).await?;
+    }
+
+    Ok(warp::reply())
+}
+
+/// Verifies the `X-Patreon-Signature` header, the HMAC-MD5 of the body.
+fn verify_patreon_signature(secret: &str, signature: &[u8], body: &[u8]) -> bool {
+    let Ok(mut mac) = Hmac::<Md5>::new_from_slice(secret.as_bytes()) else {
+        return false;
+    };
+    mac.update(body);
+    mac.verify_slice(signature).is_ok()
+}
+
+fn patreon_payment_event(
+    event_type: String,
+    body: &[u8],
+) -> Result<Option<PaymentEvent>, serde_json::Error> {
+    let payload = serde_json::from_slice::<PatreonWebhookPayload>(body)?;
+
+    let action = match (
+        event_type.as_str(),
+        payload.data.attributes.patron_status.as_deref(),
+    ) {
+        ("members:delete" | "members:pledge:delete", _) => PaymentAction::Revoke,
+        (_, Some("active_patron")) => PaymentAction::Grant,
+        (_, Some("declined_patron" | "former_patron")) => PaymentAction::Revoke,
+        _ => {
+            tracing::debug!("Ignoring Patreon event {event_type} without relevant patron status");
+            return Ok(None);
+        }
+    };
+
+    // Patreon does not send a delivery id, so the hash of the event type and payload serves as
+    // idempotency key, as events of different types may carry the same payload
+    let event_id = patreon_event_id(&event_type, body);
+
+    Ok(Some(PaymentEvent {
+        provider: PROVIDER_PATREON,
+        event_id,
+        external_id: payload.data.relationships.user.data.id,
+        event_type,
+        action,
+    }))
+}
+
+fn patreon_event_id(event_type: &str, body: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(event_type.as_bytes());
+    hasher.update(b"\n");
+    hasher.update(body);
+    hex::encode(hasher.finalize())
+}
+
+#[utoipa::path(
+    post,
+    path = "/webhooks/stripe",
+    tag = "payments",
+    params(("Stripe-Signature" = String, Header, description = "Timestamp and HMAC-SHA256 signatures of the body")),
+    request_body(content = Object, description = "Stripe event"),
+    responses(
+        (status = 200, description = "The event has been processed or ignored"),
+        (status = 400, description = "Malformed event or missing signature header", body = ErrorResponse),
+        (status = 401, description = "Invalid signature", body = ErrorResponse),
+        (status = 404, description = "GLYPH_STRIPE_WEBHOOK_SECRET is not configured", body = ErrorResponse),
+        (status = 500, description = "The event could not be processed", body = ErrorResponse),
+    ),
+)]
+pub async fn stripe_webhook_handler(
+    signature_header: String,
+    body: Bytes,
+) -> Result<impl Reply, Rejection> {
+    let Some(ref secret) = *STRIPE_WEBHOOK_SECRET else {
+        return Err(warp::reject::not_found());
+    };
+
+    if !verify_stripe_signature(secret, &signature_header, &body) {
+        return Err(Error::WebhookSignatureError.into());
+    }
+
+    let event = serde_json::from_slice::<StripeEvent>(&body)
+        .map_err(|e| Error::InvalidPayloadError(e.to_string()))?;
+    if let Some(event) = stripe_payment_event(event) {
+        process_payment_event(event).await?;
+    }
+
+    Ok(warp::reply())
+}
+
+fn stripe_payment_event(event: StripeEvent) -> Option<PaymentEvent> {
+    // subscriptions grant ongoing status via their invoices until they are deleted, one-off
+    // checkouts grant status for a limited time
+    let action = match (event.event_type.as_str(), event.data.object.mode.as_deref()) {
+        ("checkout.session.completed", Some("payment")) => {
+            PaymentAction::GrantDays(STRIPE_ONE_OFF_PERIOD_DAYS)
+        }
+        ("invoice.paid", _) => PaymentAction::Grant,
+        ("customer.subscription.deleted", _) => PaymentAction::Revoke,
+        _ => {
+            tracing::debug!(
+                "Ignoring Stripe event {} of type {}",
+                event.id,
+                event.event_type
+            );
+            return None;
+        }
+    };
+
+    let Some(customer) = event.data.object.customer else {
+        tracing::warn!("Ignoring Stripe event {} without customer", event.id);
+        return None;
+    };
+
+    Some(PaymentEvent {
+        provider: PROVIDER_STRIPE,
+        event_id: event.id,
+        external_id: customer,
+        event_type: event.event_type,
+        action,
+    })
+}
+
+/// Verifies a `Stripe-Signature` header of the form `t=<timestamp>,v1=<signature>[,v1=<signature>]`
+/// where the signature is the hex encoded HMAC-SHA256 of `<timestamp>.<body>`.
+fn verify_stripe_signature(secret: &str, signature_header: &str, body: &[u8]) -> bool {
+    let mut timestamp = None;
+    let mut signatures = Vec::new();
+    for (key, value) in signature_header
+        .split(',')
+        .filter_map(|part| part.split_once('='))
+    {
+        match key.trim() {
+            "t" => timestamp = value.trim().parse::<i64>().ok(),
+            "v1" => signatures.push(value.trim()),
+            _ => {}
+        }
+    }
+
+    let Some(timestamp) = timestamp else {
+        return false;
+    };
+    if (chrono::Utc::now().timestamp() - timestamp).abs() > STRIPE_SIGNATURE_TOLERANCE_SECONDS {
+        return false;
+    }
+
+    signatures.into_iter().any(|signature| {
+        let Ok(signature) = hex::decode(signature) else {
+            return false;
+        };
+        let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(secret.as_bytes()) else {
+            return false;
+        };
+        mac.update(timestamp.to_string().as_bytes());
+        mac.update(b".");
+        mac.update(body);
+        mac.verify_slice(&signature).is_ok()
+    })
+}
+
+/// Maps the payer to a discord user via the linked_account table and grants or revokes supporter
+/// status accordingly. Events that have already been processed are skipped, as providers may
+/// deliver the same event multiple times.
+///
+/// Events of payers that have not linked their account are recorded as pending and applied once
+/// the payer links their account, see [`apply_pending_payment_events`].
+pub async fn process_payment_event(event: PaymentEvent) -> Result<(), Error> {
+    let user_id = find_linked_user(event.provider, &event.external_id).await?;
+
+    // recording the event first claims it, so that concurrent deliveries are not applied twice
+    let mut connection = acquire_db_connection().await?;
+    let recorded = record_payment_event(&mut connection, &event, user_id).await?;
+    // granting and revoking acquire their own connection
+    drop(connection);
+
+    if !recorded {
+        tracing::info!(
+            "Skipping {} event {} because it has already been processed",
+            event.provider,
+            event.event_id
+        );
+        return Ok(());
+    }
+
+    let Some(user_id) = user_id else {
+        tracing::info!(
+            "Deferring {} event {} until account {} is linked",
+            event.provider,
+            event.event_id,
+            event.external_id
+        );
+        // the account may have been linked after the lookup, after the link applied pending events
+        if let Some(user_id) = find_linked_user(event.provider, &event.external_id).await? {
+            apply_pending_payment_events(event.provider, &event.external_id, user_id).await?;
+        }
+        return Ok(());
+    };
+
+    if let Err(e) =
+        apply_payment_action(user_id, event.provider, &event.event_type, event.action).await
+    {
+        // forget the event so that it is applied if the provider retries the delivery
+        if let Err(delete_error) = delete_payment_event(&event).await {
+            tracing::error!(
+                "Failed to forget {} event {} after it could not be applied: {delete_error}",
+                event.provider,
+                event.event_id
+            );
+        }
+        return Err(e);
+    }
+
+    Ok(())
+}
+
+/// Applies the pending events of an external identity that has just been linked to the given
+/// discord user, in the order they have been received. Events that could not be applied stay
+/// pending.
+pub async fn apply_pending_payment_events(
+    provider: &str,
+    external_id: &str,
+    user_id: u64,
+) -> Result<(), Error> {
+    let mut connection = acquire_db_connection().await?;
+    let pending_events =
+        claim_pending_payment_events(&mut connection, provider, external_id, user_id).await?;
+    drop(connection);
+
+    for (i, pending_event) in pending_events.iter().enumerate() {
+        if let Err(e) = apply_payment_action(
+            user_id,
+            provider,
+            &pending_event.event_type,
+            pending_event.action,
+        )
+        .await
+        {
+            if let Err(restore_error) =
+                restore_pending_payment_events(provider, &pending_events[i..]).await
+            {
+                tracing::error!(
+                    "Failed to restore pending {provider} events of account {external_id}: {restore_error}"
+                );
+            }
+            return Err(e);
+        }
+
+        tracing::info!(
+            "Applied pending {provider} event {} to user {user_id}",
+            pending_event.event_id
+        );
+    }
+
+    Ok(())
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct PendingPaymentEvent {
+    event_id: String,
+    event_type: String,
+    action: PaymentAction,
+}
+
+impl PaymentAction {
+    fn to_pending_columns(self) -> (&'static str, Option<i32>) {
+        match self {
+            PaymentAction::Grant => ("grant", None),
+            PaymentAction::GrantDays(days) => ("grant_days", i32::try_from(days).ok()),
+            PaymentAction::Revoke => ("revoke", None),
+        }
+    }
+
+    fn from_pending_columns(action: &str, grant_days: Option<i32>) -> Option<Self> {
+        match (action, grant_days) {
+            ("grant", _) => Some(PaymentAction::Grant),
+            ("grant_days", Some(days)) => Some(PaymentAction::GrantDays(days.into())),
+            ("revoke", _) => Some(PaymentAction::Revoke),
+            _ => None,
+        }
+    }
+}
+
+/// Records the event, as pending if the payer has not linked their account. Returns false if the
+/// event has already been recorded.
+async fn record_payment_event(
+    connection: &mut AsyncPgConnection,
+    event: &PaymentEvent,
+    user_id: Option<u64>,
+) -> Result<bool, Error> {
+    let (pending_action, pending_grant_days) = match user_id {
+        Some(_) => (None, None),
+        None => {
+            let (action, grant_days) = event.action.to_pending_columns();
+            (Some(action.to_string()), grant_days)
+        }
+    };
+
+    let inserted = diesel::insert_into(payment_event::table)
+        .values(NewPaymentEvent {
+            provider: event.provider.to_string(),
+            event_id: event.event_id.clone(),
+            external_id: event.external_id.clone(),
+            event_type: event.event_type.clone(),
+            user_id: user_id.map(Snowflake::from),
+            pending_action,
+            pending_grant_days,
+        })
+        .on_conflict_do_nothing()
+        .execute(connection)
+        .await?;
+
+    Ok(inserted > 0)
+}
+
+/// Assigns the pending events of the external identity to the user, returning them oldest first.
+/// The events are locked while claiming, so concurrent callers never receive the same event.
+async fn claim_pending_payment_events(
+    connection: &mut AsyncPgConnection,
+    provider: &str,
+    external_id: &str,
+    user_id: u64,
+) -> Result<Vec<PendingPaymentEvent>, Error> {
+    connection
+        .transaction::<_, Error, _>(|connection| {
+            async move {
+                let pending_events = payment_event::table
+                    .filter(payment_event::provider.eq(provider))
+                    .filter(payment_event::external_id.eq(external_id))
+                    .filter(payment_event::pending_action.is_not_null())
+                    .order(payment_event::creation_timestamp)
+                    .select((
+                        payment_event::event_id,
+                        payment_event::event_type,
+                        payment_event::pending_action,
+                        payment_event::pending_grant_days,
+                    ))
+                    .for_update()
+                    .load::<(String, String, Option<String>, Option<i32>)>(connection)
+                    .await?;
+
+                let event_ids = pending_events
+                    .iter()
+                    .map(|(event_id, ..)| event_id)
+                    .collect::<Vec<_>>();
+                diesel::update(payment_event::table)
+                    .filter(payment_event::provider.eq(provider))
+                    .filter(payment_event::event_id.eq_any(event_ids))
+                    .set((
+                        payment_event::user_id.eq(Some(Snowflake::from(user_id))),
+                        payment_event::pending_action.eq(None::<String>),
+                        payment_event::pending_grant_days.eq(None::<i32>),
+                    ))
+                    .execute(connection)
+                    .await?;
+
+                Ok(pending_events
+                    .into_iter()
+                    .filter_map(|(event_id, event_type, action, grant_days)| {
+                        let Some(action) = action.as_deref().and_then(|action| {
+                            PaymentAction::from_pending_columns(action, grant_days)
+                        }) else {
+                            tracing::warn!(
+                                "Dropping pending {provider} event {event_id} with unknown action {action:?}"
+                            );
+                            return None;
+                        };
+                        Some(PendingPaymentEvent {
+                            event_id,
+                            event_type,
+                            action,
+                        })
+                    })
+                    .collect())
+            }
+            .scope_boxed()
+        })
+        .await
+}
+
+async fn restore_pending_payment_events(
+    provider: &str,
+    pending_events: &[PendingPaymentEvent],
+) -> Result<(), Error> {
+    let mut connection = acquire_db_connection().await?;
+    for pending_event in pending_events {
+        let (pending_action, pending_grant_days) = pending_event.action.to_pending_columns();
+        diesel::update(payment_event::table)
+            .filter(payment_event::provider.eq(provider))
+            .filter(payment_event::event_id.eq(&pending_event.event_id))
+            .set((
+                payment_event::user_id.eq(None::<Snowflake>),
+                payment_event::pending_action.eq(pending_action),
+                payment_event::pending_grant_days.eq(pending_grant_days),
+            ))
+            .execute(&mut connection)
+            .await?;
+    }
+    Ok(())
+}
+
+async fn apply_payment_action(
+    user_id: u64,
+    provider: &str,
+    event_type: &str,
+    action: PaymentAction,
+) -> Result<(), Error> {
+    let reason = format!("{provider} event {event_type}");
+    match action {
+        PaymentAction::Grant => grant_aiode_supporter(user_id, &reason).await,
+        PaymentAction::GrantDays(days) => grant_time_limited_aiode_supporter(
+            user_id,
+            DEFAULT_SUPPORTER_TIER,
+            chrono::Duration::days(days),
+            SUPPORTER_SOURCE_PAYMENT,
+            &reason,
+        )
+        .await
+        .map(|_| ()),
+        PaymentAction::Revoke => revoke_payment_supporter(user_id, &reason).await,
+    }
+}
+
+async fn delete_payment_event(event: &PaymentEvent) -> Result<(), Error> {
+    let mut connection = acquire_db_connection().await?;
+    diesel::delete(payment_event::table)
+        .filter(payment_event::provider.eq(event.provider))
+        .filter(payment_event::event_id.eq(&event.event_id))
+        .execute(&mut connection)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_db::test_connection;
+
+    fn hmac_sha256_hex(secret: &str, message: &[u8]) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(message);
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    fn stripe_signature_header(secret: &str, timestamp: i64, body: &[u8]) -> String {
+        let mut message = format!("{timestamp}.").into_bytes();
+        message.extend_from_slice(body);
+        format!("t={timestamp},v1={}", hmac_sha256_hex(secret, &message))
+    }
+
+    fn kofi_data(event_type: &str, email: Option<&str>) -> KofiWebhookData {
+        serde_json::from_value(serde_json::json!({
+            "verification_token": "token",
+            "message_id": "message-1",
+            "type": event_type,
+            "email": email,
+        }))
+        .unwrap()
+    }
+
+    fn patreon_body(patron_status: &str) -> Vec<u8> {
+        serde_json::to_vec(&serde_json::json!({
+            "data": {
+                "attributes": { "patron_status": patron_status },
+                "relationships": { "user": { "data": { "id": "patron-1", "type": "user" } } },
+            }
+        }))
+        .unwrap()
+    }
+
+    fn stripe_event(event_type: &str, customer: Option<&str>, mode: Option<&str>) -> StripeEvent {
+        serde_json::from_value(serde_json::json!({
+            "id": "evt_1",
+            "type": event_type,
+            "data": { "object": { "customer": customer, "mode": mode } },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn stripe_signature_is_verified() {
+        let body = br#"{"id":"evt_1"}"#;
+        let now = chrono::Utc::now().timestamp();
+
+        let header = stripe_signature_header("secret", now, body);
+        assert!(verify_stripe_signature("secret", &header, body));
+        assert!(!verify_stripe_signature("other", &header, body));
+        assert!(!verify_stripe_signature("secret", &header, b"{}"));
+    }
+
+    #[test]
+    fn stripe_signature_accepts_any_valid_v1_signature() {
+        let body = b"{}";
+        let header = format!(
+            "{},v1=deadbeef",
+            stripe_signature_header("secret", chrono::Utc::now().timestamp(), body)
+        );
+
+        assert!(verify_stripe_signature("secret", &header, body));
+    }
+
+    #[test]
+    fn stripe_signature_outside_tolerance_is_rejected() {
+        let body = b"{}";
+        let timestamp = chrono::Utc::now().timestamp() - STRIPE_SIGNATURE_TOLERANCE_SECONDS - 60;
+
+        let header = stripe_signature_header("secret", timestamp, body);
+        assert!(!verify_stripe_signature("secret", &header, body));
+    }
+
+    #[test]
+    fn malformed_stripe_signature_is_rejected() {
+        assert!(!verify_stripe_signature("secret", "", b"{}"));
+        assert!(!verify_stripe_signature("secret", "v1=abc", b"{}"));
+        assert!(!verify_stripe_signature("secret", "t=abc,v1=abc", b"{}"));
+    }
+
+    #[test]
+    fn patreon_signature_is_verified() {
+        let body = patreon_body("active_patron");
+        let mut mac = Hmac::<Md5>::new_from_slice(b"secret").unwrap();
+        mac.update(&body);
+        let signature = mac.finalize().into_bytes();
+
+        assert!(verify_patreon_signature("secret", &signature, &body));
+        assert!(!verify_patreon_signature("other", &signature, &body));
+        assert!(!verify_patreon_signature("secret", &signature, b"{}"));
+    }
+
+    #[test]
+    fn only_kofi_subscriptions_grant_supporter_status() {
+        let event =
+            kofi_payment_event(kofi_data("Subscription", Some("Payer@Example.com"))).unwrap();
+        assert_eq!(
+            event.action,
+            PaymentAction::GrantDays(KOFI_SUBSCRIPTION_PERIOD_DAYS)
+        );
+        assert_eq!(event.external_id, "payer@example.com");
+        assert_eq!(event.event_id, "message-1");
+
+        assert!(kofi_payment_event(kofi_data("Donation", Some("payer@example.com"))).is_none());
+        assert!(kofi_payment_event(kofi_data("Shop Order", Some("payer@example.com"))).is_none());
+        assert!(kofi_payment_event(kofi_data("Subscription", None)).is_none());
+    }
+
+    #[test]
+    fn patreon_events_are_mapped_by_patron_status() {
+        let action = |event_type: &str, patron_status: &str| {
+            patreon_payment_event(event_type.to_string(), &patreon_body(patron_status))
+                .unwrap()
+                .map(|event| event.action)
+        };
+
+        assert_eq!(
+            action("members:pledge:create", "active_patron"),
+            Some(PaymentAction::Grant)
+        );
+        assert_eq!(
+            action("members:update", "declined_patron"),
+            Some(PaymentAction::Revoke)
+        );
+        assert_eq!(
+            action("members:pledge:delete", "active_patron"),
+            Some(PaymentAction::Revoke)
+        );
+        assert_eq!(action("members:update", "unknown"), None);
+
+        let body = patreon_body("active_patron");
+        let event = patreon_payment_event(String::from("members:create"), &body)
+            .unwrap()
+            .unwrap();
+        assert_eq!(event.external_id, "patron-1");
+        assert_eq!(event.event_id, patreon_event_id("members:create", &body));
+    }
+
+    #[test]
+    fn patreon_event_id_depends_on_event_type() {
+        let body = patreon_body("active_patron");
+        let event_id = |event_type: &str| {
+            patreon_payment_event(event_type.to_string(), &body)
+                .unwrap()
+                .unwrap()
+                .event_id
+        };
+
+        assert_eq!(
+            event_id("members:pledge:create"),
+            event_id("members:pledge:create")
+        );
+        assert_ne!(
+            event_id("members:pledge:create"),
+            event_id("members:pledge:delete")
+        );
+    }
+
+    #[test]
+    fn malformed_patreon_payload_is_an_error() {
+        assert!(patreon_payment_event(String::from("members:create"), b"{}").is_err());
+    }
+
+    #[test]
+    fn stripe_events_are_mapped_by_type() {
+        let action = |event_type: &str, mode: Option<&str>| {
+            stripe_payment_event(stripe_event(event_type, Some("cus_1"), mode))
+                .map(|event| event.action)
+        };
+
+        assert_eq!(action("invoice.paid", None), Some(PaymentAction::Grant));
+        assert_eq!(
+            action("checkout.session.completed", Some("payment")),
+            Some(PaymentAction::GrantDays(STRIPE_ONE_OFF_PERIOD_DAYS))
+        );
+        // granted by the subscription's invoices
+        assert_eq!(
+            action("checkout.session.completed", Some("subscription")),
+            None
+        );
+        assert_eq!(
+            action("customer.subscription.deleted", None),
+            Some(PaymentAction::Revoke)
+        );
+        assert_eq!(action("invoice.created", None), None);
+        assert!(stripe_payment_event(stripe_event("invoice.paid", None, None)).is_none());
+
+        let event =
+            stripe_payment_event(stripe_event("invoice.paid", Some("cus_1"), None)).unwrap();
+        assert_eq!(event.external_id, "cus_1");
+        assert_eq!(event.event_id, "evt_1");
+    }
+
+    fn payment_event(event_id: &str, action: PaymentAction) -> PaymentEvent {
+        PaymentEvent {
+            provider: PROVIDER_KOFI,
+            event_id: event_id.to_string(),
+            external_id: String::from("payer@example.com"),
+            event_type: String::from(KOFI_SUBSCRIPTION_EVENT_TYPE),
+            action,
+        }
+    }
+
+    #[test]
+    fn pending_actions_round_trip() {
+        for action in [
+            PaymentAction::Grant,
+            PaymentAction::GrantDays(KOFI_SUBSCRIPTION_PERIOD_DAYS),
+            PaymentAction::Revoke,
+        ] {
+            let (pending_action, grant_days) = action.to_pending_columns();
+            assert_eq!(
+                PaymentAction::from_pending_columns(pending_action, grant_days),
+                Some(action)
+            );
+        }
+        assert_eq!(
+            PaymentAction::from_pending_columns("grant_days", None),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn unlinked_events_are_recorded_as_pending_and_claimed_once() {
+        let Some(mut connection) = test_connection().await else {
+            return;
+        };
+        let first = payment_event("message-1", PaymentAction::GrantDays(31));
+        let second = payment_event("message-2", PaymentAction::Revoke);
+
+        assert!(record_payment_event(&mut connection, &first, None)
+            .await
+            .unwrap());
+        assert!(record_payment_event(&mut connection, &second, None)
+            .await
+            .unwrap());
+        // redelivery
+        assert!(!record_payment_event(&mut connection, &first, None)
+            .await
+            .unwrap());
+
+        let claimed =
+            claim_pending_payment_events(&mut connection, PROVIDER_KOFI, "payer@example.com", 1)
+                .await
+                .unwrap();
+        assert_eq!(
+            claimed
+                .iter()
+                .map(|event| (event.event_id.as_str(), event.action))
+                .collect::<Vec<_>>(),
+            vec![
+                ("message-1", PaymentAction::GrantDays(31)),
+                ("message-2", PaymentAction::Revoke)
+            ]
+        );
+        let user_id = payment_event::table
+            .filter(payment_event::event_id.eq("message-1"))
+            .select(payment_event::user_id)
+            .get_result::<Option<Snowflake>>(&mut connection)
+            .await
+            .unwrap();
+        assert_eq!(user_id, Some(Snowflake::from(1)));
+
+        assert!(claim_pending_payment_events(
+            &mut connection,
+            PROVIDER_KOFI,
+            "payer@example.com",
+            1
+        )
+        .await
+        .unwrap()
+        .is_empty());
+    }
+
+    #[tokio::test]
+    async fn events_of_linked_payers_are_not_pending() {
+        let Some(mut connection) = test_connection().await else {
+            return;
+        };
+
+        assert!(record_payment_event(
+            &mut connection,
+            &payment_event("message-1", PaymentAction::Grant),
+            Some(1)
+        )
+        .await
+        .unwrap());
+
+        assert!(claim_pending_payment_events(
+            &mut connection,
+            PROVIDER_KOFI,
+            "payer@example.com",
+            2
+        )
+        .await
+        .unwrap()
+        .is_empty());
+    }
+}
@@ -0,0 +1,51 @@
+use std::{convert::Infallible, fmt};
+
+use tracing::Span;
+use warp::{http::HeaderMap, Filter};
+
+use crate::util::generate_code;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+const MAX_REQUEST_ID_LENGTH: usize = 64;
+
+/// Identifies an API request in logs and error responses.
+#[derive(Clone, Debug)]
+pub struct RequestId(String);
+
+impl RequestId {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Extracts the id of the request from the `x-request-id` header, e.g. as set by a reverse proxy, or
+/// generates a new one if the header is missing or not a plausible id.
+pub fn request_id() -> impl Filter<Extract = (RequestId,), Error = Infallible> + Clone {
+    // the headers are inspected directly as a malformed header must not reject the request
+    warp::header::headers_cloned().map(|headers: HeaderMap| {
+        let request_id = headers
+            .get(REQUEST_ID_HEADER)
+            .and_then(|request_id| request_id.to_str().ok())
+            .filter(|request_id| is_valid_request_id(request_id))
+            .map(String::from)
+            .unwrap_or_else(|| generate_code(16));
+        // correlates everything logged while handling the request, see telemetry::request_span
+        Span::current().record("request_id", request_id.as_str());
+        RequestId(request_id)
+    })
+}
+
+fn is_valid_request_id(request_id: &str) -> bool {
+    !request_id.is_empty()
+        && request_id.len() <= MAX_REQUEST_ID_LENGTH
+        && request_id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.'))
+}
@@ -1,8 +1,147 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    account_link_code (code_hash) {
+        #[max_length = 64]
+        code_hash -> Varchar,
+        user_id -> Int8,
+        #[max_length = 32]
+        provider -> Varchar,
+        expiration_timestamp -> Timestamptz,
+        creation_timestamp -> Timestamptz,
+    }
+}
+
 diesel::table! {
     aiode_supporter (user_id) {
-        user_id -> Numeric,
+        user_id -> Int8,
+        creation_timestamp -> Timestamptz,
+        #[max_length = 32]
+        tier -> Nullable<Varchar>,
+        expiration_timestamp -> Nullable<Timestamptz>,
+        #[max_length = 32]
+        source -> Varchar,
+        revocation_deadline -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    lease (name) {
+        #[max_length = 64]
+        name -> Varchar,
+        #[max_length = 64]
+        holder -> Varchar,
+        expires_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    linked_account (provider, external_id) {
+        #[max_length = 32]
+        provider -> Varchar,
+        #[max_length = 255]
+        external_id -> Varchar,
+        user_id -> Int8,
         creation_timestamp -> Timestamptz,
+        verified -> Bool,
     }
 }
+
+diesel::table! {
+    payment_event (provider, event_id) {
+        #[max_length = 32]
+        provider -> Varchar,
+        #[max_length = 255]
+        event_id -> Varchar,
+        #[max_length = 255]
+        external_id -> Varchar,
+        #[max_length = 255]
+        event_type -> Varchar,
+        user_id -> Nullable<Int8>,
+        creation_timestamp -> Timestamptz,
+        #[max_length = 16]
+        pending_action -> Nullable<Varchar>,
+        pending_grant_days -> Nullable<Int4>,
+    }
+}
+
+diesel::table! {
+    supporter_announcement (announcement_key) {
+        #[max_length = 255]
+        announcement_key -> Varchar,
+        user_id -> Nullable<Int8>,
+        creation_timestamp -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    supporter_credits_opt_in (user_id) {
+        user_id -> Int8,
+        creation_timestamp -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    supporter_gift (pk) {
+        pk -> Int4,
+        gifter_user_id -> Nullable<Int8>,
+        recipient_user_id -> Int8,
+        #[max_length = 32]
+        tier -> Varchar,
+        duration_days -> Int4,
+        expiration_timestamp -> Timestamptz,
+        creation_timestamp -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    user_badge (user_id, badge) {
+        user_id -> Int8,
+        #[max_length = 32]
+        badge -> Varchar,
+        creation_timestamp -> Timestamptz,
+        revocation_deadline -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    voucher (pk) {
+        pk -> Int4,
+        #[max_length = 64]
+        code_hash -> Varchar,
+        #[max_length = 255]
+        batch -> Varchar,
+        #[max_length = 32]
+        tier -> Varchar,
+        duration_days -> Int4,
+        max_redemptions -> Int4,
+        redemption_count -> Int4,
+        revoked -> Bool,
+        expiration_timestamp -> Nullable<Timestamptz>,
+        creation_timestamp -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    voucher_redemption (voucher_pk, user_id) {
+        voucher_pk -> Int4,
+        user_id -> Int8,
+        creation_timestamp -> Timestamptz,
+    }
+}
+
+diesel::joinable!(voucher_redemption -> voucher (voucher_pk));
+
+diesel::allow_tables_to_appear_in_same_query!(
+    account_link_code,
+    aiode_supporter,
+    lease,
+    linked_account,
+    payment_event,
+    supporter_announcement,
+    supporter_credits_opt_in,
+    supporter_gift,
+    user_badge,
+    voucher,
+    voucher_redemption,
+);
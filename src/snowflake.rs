@@ -0,0 +1,186 @@
+use std::{fmt, io::Write, num::NonZeroU64};
+
+use diesel::{
+    deserialize::{self, FromSql, FromSqlRow},
+    expression::AsExpression,
+    pg::{Pg, PgValue},
+    serialize::{self, IsNull, Output, ToSql},
+    sql_types::BigInt,
+};
+use serenity::all::{GuildId, RoleId, UserId};
+
+/// A discord id, stored as `BIGINT` by reinterpreting the bits of the u64 as i64. This is lossless for
+/// the entire u64 range, though ids of 2^63 and above are stored as negative numbers and thus do not
+/// keep their order in the database.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, AsExpression, FromSqlRow)]
+#[diesel(sql_type = BigInt)]
+pub struct Snowflake(pub u64);
+
+impl Snowflake {
+    pub fn get(self) -> u64 {
+        self.0
+    }
+
+    pub fn to_bigint(self) -> i64 {
+        self.0 as i64
+    }
+
+    pub fn from_bigint(value: i64) -> Self {
+        Self(value as u64)
+    }
+}
+
+impl ToSql<BigInt, Pg> for Snowflake {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        out.write_all(&self.to_bigint().to_be_bytes())
+            .map(|_| IsNull::No)
+            .map_err(Into::into)
+    }
+}
+
+impl FromSql<BigInt, Pg> for Snowflake {
+    fn from_sql(value: PgValue<'_>) -> deserialize::Result<Self> {
+        <i64 as FromSql<BigInt, Pg>>::from_sql(value).map(Self::from_bigint)
+    }
+}
+
+impl fmt::Display for Snowflake {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl From<u64> for Snowflake {
+    fn from(value: u64) -> Self {
+        Self(value)
+    }
+}
+
+impl From<Snowflake> for u64 {
+    fn from(value: Snowflake) -> Self {
+        value.0
+    }
+}
+
+impl From<UserId> for Snowflake {
+    fn from(value: UserId) -> Self {
+        Self(value.get())
+    }
+}
+
+/// Discord ids are never 0, which serenity's id types do not accept. A 0 read from the database, e.g.
+/// inserted manually, is thus rejected instead of panicking.
+impl TryFrom<Snowflake> for UserId {
+    type Error = InvalidSnowflake;
+
+    fn try_from(value: Snowflake) -> Result<Self, Self::Error> {
+        NonZeroU64::new(value.0)
+            .map(UserId::from)
+            .ok_or(InvalidSnowflake(value))
+    }
+}
+
+/// The snowflake is not a valid discord id.
+#[derive(Debug, PartialEq, Eq)]
+pub struct InvalidSnowflake(pub Snowflake);
+
+impl fmt::Display for InvalidSnowflake {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} is not a valid discord id", self.0)
+    }
+}
+
+impl std::error::Error for InvalidSnowflake {}
+
+impl From<GuildId> for Snowflake {
+    fn from(value: GuildId) -> Self {
+        Self(value.get())
+    }
+}
+
+impl From<RoleId> for Snowflake {
+    fn from(value: RoleId) -> Self {
+        Self(value.get())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use diesel::IntoSql;
+    use diesel_async::RunQueryDsl;
+
+    use super::*;
+    use crate::test_db::test_connection;
+
+    const EXTREMES: [u64; 6] = [
+        0,
+        1,
+        i64::MAX as u64,
+        i64::MAX as u64 + 1,
+        u64::MAX - 1,
+        u64::MAX,
+    ];
+
+    #[test]
+    fn bigint_round_trip() {
+        for value in EXTREMES {
+            let snowflake = Snowflake(value);
+            assert_eq!(Snowflake::from_bigint(snowflake.to_bigint()), snowflake);
+        }
+    }
+
+    #[test]
+    fn bigint_reinterprets_bits() {
+        assert_eq!(Snowflake(0).to_bigint(), 0);
+        assert_eq!(Snowflake(i64::MAX as u64).to_bigint(), i64::MAX);
+        assert_eq!(Snowflake(i64::MAX as u64 + 1).to_bigint(), i64::MIN);
+        assert_eq!(Snowflake(u64::MAX).to_bigint(), -1);
+    }
+
+    #[test]
+    fn wire_format_round_trip() {
+        // the postgres binary format of BIGINT is the big endian i64
+        for value in EXTREMES {
+            let bytes = Snowflake(value).to_bigint().to_be_bytes();
+            assert_eq!(bytes, value.to_be_bytes());
+            assert_eq!(
+                Snowflake::from_bigint(i64::from_be_bytes(bytes)),
+                Snowflake(value)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn database_round_trip() {
+        let Some(mut connection) = test_connection().await else {
+            return;
+        };
+
+        for value in EXTREMES {
+            let snowflake = Snowflake(value);
+            let stored = diesel::select(snowflake.into_sql::<BigInt>())
+                .get_result::<i64>(&mut connection)
+                .await
+                .unwrap();
+            assert_eq!(stored, snowflake.to_bigint());
+
+            let loaded = diesel::select(snowflake.into_sql::<BigInt>())
+                .get_result::<Snowflake>(&mut connection)
+                .await
+                .unwrap();
+            assert_eq!(loaded, snowflake);
+        }
+    }
+
+    #[test]
+    fn zero_is_not_a_user_id() {
+        assert_eq!(
+            UserId::try_from(Snowflake(0)),
+            Err(InvalidSnowflake(Snowflake(0)))
+        );
+        assert_eq!(
+            UserId::try_from(Snowflake(u64::MAX)),
+            Ok(UserId::new(u64::MAX))
+        );
+    }
+}
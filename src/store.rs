@@ -0,0 +1,479 @@
+use chrono::{DateTime, Utc};
+use diesel::{ExpressionMethods, QueryDsl};
+use diesel_async::RunQueryDsl;
+use serenity::async_trait;
+
+use crate::{
+    acquire_db_connection,
+    aiode::{self, GUILD_SUPPORTER_SOURCES, SUPPORTER_SOURCE_BOOSTER},
+    error::Error,
+    model::{AiodeSupporter, NewAiodeSupporter},
+    schema::aiode_supporter,
+    snowflake::Snowflake,
+};
+
+/// The aiode_supporter table operations used by the supporter sync and by grants of time limited
+/// supporter status, implemented by [`PgSupporterStore`] and by an in-memory fake in tests.
+///
+/// Supporters whose status has been granted by other means than the support guild (payments,
+/// vouchers, gifts) must not be affected by role or boost changes, hence operations triggered by
+/// those changes only consider the [`GUILD_SUPPORTER_SOURCES`].
+#[async_trait]
+pub trait SupporterStore: Send + Sync {
+    /// Inserts the supporters that do not exist yet, returns the number of inserted supporters.
+    async fn insert_supporters(&self, supporters: &[NewAiodeSupporter]) -> Result<usize, Error>;
+
+    /// Clears pending revocations of the given supporters tracked via the support guild, returns the
+    /// number of cancelled revocations.
+    async fn cancel_revocations(&self, user_ids: &[Snowflake]) -> Result<usize, Error>;
+
+    /// Marks the supporter as pending revocation unless a revocation is already pending or the
+    /// supporter's source is not one of `sources`, returns whether the revocation has been scheduled.
+    async fn schedule_revocation(
+        &self,
+        user_id: Snowflake,
+        sources: &[&str],
+        revocation_deadline: DateTime<Utc>,
+    ) -> Result<bool, Error>;
+
+    /// Changes the source of a supporter tracked via the support guild, e.g. from role to booster.
+    async fn set_supporter_source(&self, user_id: Snowflake, source: &str) -> Result<bool, Error>;
+
+    /// Deletes the supporter if its source is one of `sources`, returns whether it has been deleted.
+    async fn delete_supporter(&self, user_id: Snowflake, sources: &[&str]) -> Result<bool, Error>;
+
+    /// Deletes supporters whose status stems from boosting, except the given users, returns the
+    /// number of deleted supporters.
+    async fn delete_boosters_except(&self, user_ids: &[Snowflake]) -> Result<usize, Error>;
+
+    /// Marks supporters whose status stems from boosting, except the given users, as pending
+    /// revocation unless they already are, returns the number of newly scheduled revocations.
+    async fn schedule_booster_revocations_except(
+        &self,
+        user_ids: &[Snowflake],
+        revocation_deadline: DateTime<Utc>,
+    ) -> Result<usize, Error>;
+
+    /// Returns the supporters of the given sources whose revocation deadline is at or before `now`.
+    async fn due_revocations(
+        &self,
+        sources: &[&str],
+        now: DateTime<Utc>,
+    ) -> Result<Vec<AiodeSupporter>, Error>;
+
+    /// Deletes the supporter if its revocation deadline is still at or before `now`, i.e. the
+    /// revocation has not been cancelled in the meantime, returns whether it has been deleted.
+    async fn delete_revoked_supporter(
+        &self,
+        user_id: Snowflake,
+        now: DateTime<Utc>,
+    ) -> Result<bool, Error>;
+
+    /// Deletes the supporter if its expiration timestamp is still at or before `now`, i.e. the status
+    /// has not been renewed in the meantime, returns whether it has been deleted.
+    async fn delete_expired_supporter(
+        &self,
+        user_id: Snowflake,
+        now: DateTime<Utc>,
+    ) -> Result<bool, Error>;
+
+    /// Grants time limited supporter status, see [`aiode::time_limited_status`], returns the new
+    /// expiration timestamp or `None` if the user already is a permanent supporter.
+    async fn grant_time_limited_supporter(
+        &self,
+        user_id: Snowflake,
+        tier: &str,
+        duration: chrono::Duration,
+        source: &str,
+    ) -> Result<Option<DateTime<Utc>>, Error>;
+}
+
+/// [`SupporterStore`] backed by the database.
+pub struct PgSupporterStore;
+
+#[async_trait]
+impl SupporterStore for PgSupporterStore {
+    async fn insert_supporters(&self, supporters: &[NewAiodeSupporter]) -> Result<usize, Error> {
+        let mut connection = acquire_db_connection().await?;
+        // split items into chunks to avoid hitting the parameter limit
+        let mut res = 0;
+        for supporter_chunk in supporters.chunks(4096) {
+            res += diesel::insert_into(aiode_supporter::table)
+                .values(supporter_chunk)
+                .on_conflict_do_nothing()
+                .execute(&mut connection)
+                .await?;
+        }
+        Ok(res)
+    }
+
+    async fn cancel_revocations(&self, user_ids: &[Snowflake]) -> Result<usize, Error> {
+        let mut connection = acquire_db_connection().await?;
+        let res = diesel::update(aiode_supporter::table)
+            .filter(aiode_supporter::revocation_deadline.is_not_null())
+            .filter(aiode_supporter::user_id.eq_any(user_ids))
+            .filter(aiode_supporter::source.eq_any(GUILD_SUPPORTER_SOURCES))
+            .set(aiode_supporter::revocation_deadline.eq(None::<DateTime<Utc>>))
+            .execute(&mut connection)
+            .await?;
+        Ok(res)
+    }
+
+    async fn schedule_revocation(
+        &self,
+        user_id: Snowflake,
+        sources: &[&str],
+        revocation_deadline: DateTime<Utc>,
+    ) -> Result<bool, Error> {
+        let mut connection = acquire_db_connection().await?;
+        let res = diesel::update(aiode_supporter::table)
+            .filter(aiode_supporter::user_id.eq(user_id))
+            .filter(aiode_supporter::source.eq_any(sources))
+            .filter(aiode_supporter::revocation_deadline.is_null())
+            .set(aiode_supporter::revocation_deadline.eq(revocation_deadline))
+            .execute(&mut connection)
+            .await?;
+        Ok(res > 0)
+    }
+
+    async fn set_supporter_source(&self, user_id: Snowflake, source: &str) -> Result<bool, Error> {
+        let mut connection = acquire_db_connection().await?;
+        let res = diesel::update(aiode_supporter::table)
+            .filter(aiode_supporter::user_id.eq(user_id))
+            .filter(aiode_supporter::source.eq_any(GUILD_SUPPORTER_SOURCES))
+            .set(aiode_supporter::source.eq(source))
+            .execute(&mut connection)
+            .await?;
+        Ok(res > 0)
+    }
+
+    async fn delete_supporter(&self, user_id: Snowflake, sources: &[&str]) -> Result<bool, Error> {
+        let mut connection = acquire_db_connection().await?;
+        let res = diesel::delete(aiode_supporter::table)
+            .filter(aiode_supporter::user_id.eq(user_id))
+            .filter(aiode_supporter::source.eq_any(sources))
+            .execute(&mut connection)
+            .await?;
+        Ok(res > 0)
+    }
+
+    async fn delete_boosters_except(&self, user_ids: &[Snowflake]) -> Result<usize, Error> {
+        let mut connection = acquire_db_connection().await?;
+        let res = diesel::delete(aiode_supporter::table)
+            .filter(aiode_supporter::source.eq(SUPPORTER_SOURCE_BOOSTER))
+            .filter(aiode_supporter::user_id.ne_all(user_ids))
+            .execute(&mut connection)
+            .await?;
+        Ok(res)
+    }
+
+    async fn schedule_booster_revocations_except(
+        &self,
+        user_ids: &[Snowflake],
+        revocation_deadline: DateTime<Utc>,
+    ) -> Result<usize, Error> {
+        let mut connection = acquire_db_connection().await?;
+        let res = diesel::update(aiode_supporter::table)
+            .filter(aiode_supporter::source.eq(SUPPORTER_SOURCE_BOOSTER))
+            .filter(aiode_supporter::user_id.ne_all(user_ids))
+            .filter(aiode_supporter::revocation_deadline.is_null())
+            .set(aiode_supporter::revocation_deadline.eq(revocation_deadline))
+            .execute(&mut connection)
+            .await?;
+        Ok(res)
+    }
+
+    async fn due_revocations(
+        &self,
+        sources: &[&str],
+        now: DateTime<Utc>,
+    ) -> Result<Vec<AiodeSupporter>, Error> {
+        let mut connection = acquire_db_connection().await?;
+        let supporters = aiode_supporter::table
+            .filter(aiode_supporter::revocation_deadline.le(now))
+            .filter(aiode_supporter::source.eq_any(sources))
+            .load::<AiodeSupporter>(&mut connection)
+            .await?;
+        Ok(supporters)
+    }
+
+    async fn delete_revoked_supporter(
+        &self,
+        user_id: Snowflake,
+        now: DateTime<Utc>,
+    ) -> Result<bool, Error> {
+        let mut connection = acquire_db_connection().await?;
+        let res = diesel::delete(aiode_supporter::table)
+            .filter(aiode_supporter::user_id.eq(user_id))
+            .filter(aiode_supporter::revocation_deadline.le(now))
+            .execute(&mut connection)
+            .await?;
+        Ok(res > 0)
+    }
+
+    async fn delete_expired_supporter(
+        &self,
+        user_id: Snowflake,
+        now: DateTime<Utc>,
+    ) -> Result<bool, Error> {
+        let mut connection = acquire_db_connection().await?;
+        let res = diesel::delete(aiode_supporter::table)
+            .filter(aiode_supporter::user_id.eq(user_id))
+            .filter(aiode_supporter::expiration_timestamp.le(now))
+            .execute(&mut connection)
+            .await?;
+        Ok(res > 0)
+    }
+
+    async fn grant_time_limited_supporter(
+        &self,
+        user_id: Snowflake,
+        tier: &str,
+        duration: chrono::Duration,
+        source: &str,
+    ) -> Result<Option<DateTime<Utc>>, Error> {
+        let mut connection = acquire_db_connection().await?;
+        aiode::upsert_time_limited_supporter(&mut connection, user_id.get(), tier, duration, source)
+            .await
+    }
+}
+
+#[cfg(test)]
+pub mod fake {
+    use std::{
+        collections::{btree_map::Entry, BTreeMap},
+        sync::Mutex,
+    };
+
+    use super::*;
+
+    /// In-memory [`SupporterStore`].
+    #[derive(Default)]
+    pub struct InMemorySupporterStore {
+        pub supporters: Mutex<BTreeMap<Snowflake, AiodeSupporter>>,
+    }
+
+    impl InMemorySupporterStore {
+        pub fn with_supporters(supporters: impl IntoIterator<Item = AiodeSupporter>) -> Self {
+            Self {
+                supporters: Mutex::new(
+                    supporters
+                        .into_iter()
+                        .map(|supporter| (supporter.user_id, supporter))
+                        .collect(),
+                ),
+            }
+        }
+
+        pub fn get(&self, user_id: u64) -> Option<AiodeSupporter> {
+            self.supporters
+                .lock()
+                .unwrap()
+                .get(&Snowflake(user_id))
+                .cloned()
+        }
+    }
+
+    #[async_trait]
+    impl SupporterStore for InMemorySupporterStore {
+        async fn insert_supporters(
+            &self,
+            supporters: &[NewAiodeSupporter],
+        ) -> Result<usize, Error> {
+            let mut existing = self.supporters.lock().unwrap();
+            let mut res = 0;
+            for supporter in supporters {
+                if let Entry::Vacant(entry) = existing.entry(supporter.user_id) {
+                    entry.insert(AiodeSupporter {
+                        user_id: supporter.user_id,
+                        creation_timestamp: Utc::now(),
+                        tier: None,
+                        expiration_timestamp: None,
+                        source: supporter.source.clone(),
+                        revocation_deadline: None,
+                    });
+                    res += 1;
+                }
+            }
+            Ok(res)
+        }
+
+        async fn cancel_revocations(&self, user_ids: &[Snowflake]) -> Result<usize, Error> {
+            let mut res = 0;
+            for supporter in self.supporters.lock().unwrap().values_mut() {
+                if user_ids.contains(&supporter.user_id)
+                    && GUILD_SUPPORTER_SOURCES.contains(&supporter.source.as_str())
+                    && supporter.revocation_deadline.take().is_some()
+                {
+                    res += 1;
+                }
+            }
+            Ok(res)
+        }
+
+        async fn schedule_revocation(
+            &self,
+            user_id: Snowflake,
+            sources: &[&str],
+            revocation_deadline: DateTime<Utc>,
+        ) -> Result<bool, Error> {
+            match self.supporters.lock().unwrap().get_mut(&user_id) {
+                Some(supporter)
+                    if sources.contains(&supporter.source.as_str())
+                        && supporter.revocation_deadline.is_none() =>
+                {
+                    supporter.revocation_deadline = Some(revocation_deadline);
+                    Ok(true)
+                }
+                _ => Ok(false),
+            }
+        }
+
+        async fn set_supporter_source(
+            &self,
+            user_id: Snowflake,
+            source: &str,
+        ) -> Result<bool, Error> {
+            match self.supporters.lock().unwrap().get_mut(&user_id) {
+                Some(supporter) if GUILD_SUPPORTER_SOURCES.contains(&supporter.source.as_str()) => {
+                    supporter.source = source.to_string();
+                    Ok(true)
+                }
+                _ => Ok(false),
+            }
+        }
+
+        async fn delete_supporter(
+            &self,
+            user_id: Snowflake,
+            sources: &[&str],
+        ) -> Result<bool, Error> {
+            let mut supporters = self.supporters.lock().unwrap();
+            match supporters.get(&user_id) {
+                Some(supporter) if sources.contains(&supporter.source.as_str()) => {
+                    supporters.remove(&user_id);
+                    Ok(true)
+                }
+                _ => Ok(false),
+            }
+        }
+
+        async fn delete_boosters_except(&self, user_ids: &[Snowflake]) -> Result<usize, Error> {
+            let mut supporters = self.supporters.lock().unwrap();
+            let count = supporters.len();
+            supporters.retain(|user_id, supporter| {
+                supporter.source != SUPPORTER_SOURCE_BOOSTER || user_ids.contains(user_id)
+            });
+            Ok(count - supporters.len())
+        }
+
+        async fn schedule_booster_revocations_except(
+            &self,
+            user_ids: &[Snowflake],
+            revocation_deadline: DateTime<Utc>,
+        ) -> Result<usize, Error> {
+            let mut count = 0;
+            for (user_id, supporter) in self.supporters.lock().unwrap().iter_mut() {
+                if supporter.source == SUPPORTER_SOURCE_BOOSTER
+                    && !user_ids.contains(user_id)
+                    && supporter.revocation_deadline.is_none()
+                {
+                    supporter.revocation_deadline = Some(revocation_deadline);
+                    count += 1;
+                }
+            }
+            Ok(count)
+        }
+
+        async fn due_revocations(
+            &self,
+            sources: &[&str],
+            now: DateTime<Utc>,
+        ) -> Result<Vec<AiodeSupporter>, Error> {
+            Ok(self
+                .supporters
+                .lock()
+                .unwrap()
+                .values()
+                .filter(|supporter| {
+                    supporter
+                        .revocation_deadline
+                        .is_some_and(|deadline| deadline <= now)
+                        && sources.contains(&supporter.source.as_str())
+                })
+                .cloned()
+                .collect())
+        }
+
+        async fn delete_revoked_supporter(
+            &self,
+            user_id: Snowflake,
+            now: DateTime<Utc>,
+        ) -> Result<bool, Error> {
+            let mut supporters = self.supporters.lock().unwrap();
+            match supporters.get(&user_id) {
+                Some(supporter)
+                    if supporter
+                        .revocation_deadline
+                        .is_some_and(|deadline| deadline <= now) =>
+                {
+                    supporters.remove(&user_id);
+                    Ok(true)
+                }
+                _ => Ok(false),
+            }
+        }
+
+        async fn delete_expired_supporter(
+            &self,
+            user_id: Snowflake,
+            now: DateTime<Utc>,
+        ) -> Result<bool, Error> {
+            let mut supporters = self.supporters.lock().unwrap();
+            match supporters.get(&user_id) {
+                Some(supporter)
+                    if supporter
+                        .expiration_timestamp
+                        .is_some_and(|expiration| expiration <= now) =>
+                {
+                    supporters.remove(&user_id);
+                    Ok(true)
+                }
+                _ => Ok(false),
+            }
+        }
+
+        async fn grant_time_limited_supporter(
+            &self,
+            user_id: Snowflake,
+            tier: &str,
+            duration: chrono::Duration,
+            source: &str,
+        ) -> Result<Option<DateTime<Utc>>, Error> {
+            let mut supporters = self.supporters.lock().unwrap();
+            let Some(status) = aiode::time_limited_status(
+                supporters.get(&user_id),
+                tier,
+                duration,
+                source,
+                Utc::now(),
+            ) else {
+                return Ok(None);
+            };
+
+            let supporter = supporters.entry(user_id).or_insert_with(|| AiodeSupporter {
+                user_id,
+                creation_timestamp: Utc::now(),
+                tier: None,
+                expiration_timestamp: None,
+                source: String::new(),
+                revocation_deadline: None,
+            });
+            supporter.tier = Some(status.tier);
+            supporter.expiration_timestamp = Some(status.expiration_timestamp);
+            supporter.source = status.source;
+            supporter.revocation_deadline = None;
+            Ok(Some(status.expiration_timestamp))
+        }
+    }
+}
@@ -0,0 +1,410 @@
+use std::{collections::HashMap, sync::RwLock, time::Duration};
+
+use chrono::{DateTime, Utc};
+use diesel::{ExpressionMethods, QueryDsl};
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use lazy_static::lazy_static;
+use tokio::sync::{broadcast, Notify};
+
+use crate::{
+    acquire_db_connection,
+    aiode::{find_aiode_supporter, SUPPORTER_SOURCE_GIFT},
+    change_feed::{self, ChangeEvent, SupporterChange},
+    error::Error,
+    gift::find_active_gift,
+    model::{AiodeSupporter, SupporterGift},
+    schema::{aiode_supporter, supporter_gift},
+};
+
+/// Interval at which loading the supporters is retried while the cache is not in sync.
+const RELOAD_RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
+lazy_static! {
+    /// `None` until the supporters have been loaded for the first time.
+    static ref SUPPORTER_CACHE: RwLock<Option<SupporterCache>> = RwLock::new(None);
+    /// Wakes [`apply_supporter_changes`] to reload all supporters, see [`request_reload`].
+    static ref RELOAD_REQUESTED: Notify = Notify::new();
+}
+
+struct SupporterCache {
+    supporters: HashMap<u64, CachedSupporter>,
+    /// False if changes may have been missed since the supporters were loaded.
+    in_sync: bool,
+}
+
+#[derive(Clone)]
+pub struct CachedSupporter {
+    pub supporter: AiodeSupporter,
+    /// The active gift of supporters whose status has been gifted.
+    pub gift: Option<SupporterGift>,
+}
+
+pub struct SupporterLookup {
+    pub supporter: Option<CachedSupporter>,
+    /// True if the cache may not reflect the latest changes, e.g. because the database is unavailable.
+    pub stale: bool,
+}
+
+impl SupporterCache {
+    /// Supporters whose time limited status or gift has expired are treated as if they were not
+    /// cached, as they are only removed from the table by the next expiry task.
+    fn lookup(&self, user_id: u64, now: DateTime<Utc>, listening: bool) -> SupporterLookup {
+        let supporter = self
+            .supporters
+            .get(&user_id)
+            .filter(|cached| {
+                cached
+                    .supporter
+                    .expiration_timestamp
+                    .is_none_or(|expiration_timestamp| expiration_timestamp > now)
+            })
+            .cloned()
+            .map(|mut cached| {
+                cached.gift = cached.gift.filter(|gift| gift.expiration_timestamp > now);
+                cached
+            });
+
+        SupporterLookup {
+            supporter,
+            stale: !self.in_sync || !listening,
+        }
+    }
+
+    fn update(&mut self, user_id: u64, cached_supporter: Option<CachedSupporter>) {
+        match cached_supporter {
+            Some(cached_supporter) => self.supporters.insert(user_id, cached_supporter),
+            None => self.supporters.remove(&user_id),
+        };
+    }
+}
+
+/// Looks up the supporter in the cache, returns `None` if the supporters have not been loaded yet.
+pub fn lookup_supporter(user_id: u64) -> Option<SupporterLookup> {
+    let supporter_cache = SUPPORTER_CACHE
+        .read()
+        .expect("supporter cache lock poisoned");
+    let supporter_cache = supporter_cache.as_ref()?;
+
+    Some(supporter_cache.lookup(user_id, Utc::now(), change_feed::is_listening()))
+}
+
+/// Asks the task keeping the cache in sync to reload all supporters, e.g. after a bulk update. The
+/// reload is handled like a [`ChangeEvent::Reset`], in order with the reloads of single supporters.
+pub fn request_reload() {
+    RELOAD_REQUESTED.notify_one();
+}
+
+/// Loads all supporters and the active gifts of gifted supporters.
+async fn load_supporter_cache(connection: &mut AsyncPgConnection) -> Result<SupporterCache, Error> {
+    let supporters = aiode_supporter::table
+        .load::<AiodeSupporter>(connection)
+        .await?;
+    // ordered so that the gift expiring last is kept for each recipient
+    let gifts = supporter_gift::table
+        .filter(supporter_gift::expiration_timestamp.gt(Utc::now()))
+        .order(supporter_gift::expiration_timestamp)
+        .load::<SupporterGift>(connection)
+        .await?;
+
+    let mut gifts = gifts
+        .into_iter()
+        .map(|gift| (gift.recipient_user_id.get(), gift))
+        .collect::<HashMap<_, _>>();
+    let supporters = supporters
+        .into_iter()
+        .map(|supporter| {
+            let user_id = supporter.user_id.get();
+            let gift = if supporter.source == SUPPORTER_SOURCE_GIFT {
+                gifts.remove(&user_id)
+            } else {
+                None
+            };
+            (user_id, CachedSupporter { supporter, gift })
+        })
+        .collect::<HashMap<_, _>>();
+
+    Ok(SupporterCache {
+        supporters,
+        in_sync: true,
+    })
+}
+
+/// Keeps the cache in sync with the supporter table. The supporters are loaded once the change feed
+/// is listening, whenever changes may have been missed and when requested via [`request_reload`],
+/// retrying periodically until it succeeds.
+pub async fn apply_supporter_changes(mut changes: broadcast::Receiver<ChangeEvent>) {
+    let mut retry_interval = tokio::time::interval(RELOAD_RETRY_INTERVAL);
+    loop {
+        tokio::select! {
+            change = changes.recv() => match change {
+                Ok(ChangeEvent::Changed(SupporterChange { table, operation, user_id })) => {
+                    if table != "aiode_supporter" {
+                        continue;
+                    }
+                    match user_id {
+                        Some(user_id) if operation != "TRUNCATE" => {
+                            reload_supporter(user_id.get()).await
+                        }
+                        _ => reload_supporters().await,
+                    }
+                }
+                Ok(ChangeEvent::Reset) | Err(broadcast::error::RecvError::Lagged(_)) => {
+                    reload_supporters().await
+                }
+                Err(broadcast::error::RecvError::Closed) => return,
+            },
+            _ = RELOAD_REQUESTED.notified() => reload_supporters().await,
+            _ = retry_interval.tick() => {
+                if !is_in_sync() {
+                    reload_supporters().await;
+                }
+            }
+        }
+    }
+}
+
+fn is_in_sync() -> bool {
+    SUPPORTER_CACHE
+        .read()
+        .expect("supporter cache lock poisoned")
+        .as_ref()
+        .is_some_and(|supporter_cache| supporter_cache.in_sync)
+}
+
+fn mark_out_of_sync() {
+    if let Some(ref mut supporter_cache) = *SUPPORTER_CACHE
+        .write()
+        .expect("supporter cache lock poisoned")
+    {
+        supporter_cache.in_sync = false;
+    }
+}
+
+/// Replaces the cache with all supporters.
+async fn reload_supporters() {
+    let supporter_cache = match acquire_db_connection().await {
+        Ok(mut connection) => load_supporter_cache(&mut connection).await,
+        Err(e) => Err(e),
+    };
+    match supporter_cache {
+        Ok(supporter_cache) => {
+            tracing::info!(
+                "Loaded {} supporters into the supporter cache",
+                supporter_cache.supporters.len()
+            );
+            *SUPPORTER_CACHE
+                .write()
+                .expect("supporter cache lock poisoned") = Some(supporter_cache);
+        }
+        Err(e) => {
+            tracing::error!("Failed to load supporters, serving stale supporter status: {e}");
+            mark_out_of_sync();
+        }
+    }
+}
+
+async fn reload_supporter(user_id: u64) {
+    let cached_supporter = match find_cached_supporter(user_id).await {
+        Ok(cached_supporter) => cached_supporter,
+        Err(e) => {
+            tracing::error!(
+                "Failed to reload supporter {user_id}, serving stale supporter status: {e}"
+            );
+            mark_out_of_sync();
+            return;
+        }
+    };
+
+    if let Some(ref mut supporter_cache) = *SUPPORTER_CACHE
+        .write()
+        .expect("supporter cache lock poisoned")
+    {
+        supporter_cache.update(user_id, cached_supporter);
+    }
+}
+
+async fn find_cached_supporter(user_id: u64) -> Result<Option<CachedSupporter>, Error> {
+    let Some(supporter) = find_aiode_supporter(user_id).await? else {
+        return Ok(None);
+    };
+
+    let gift = if supporter.source == SUPPORTER_SOURCE_GIFT {
+        find_active_gift(user_id).await?
+    } else {
+        None
+    };
+    Ok(Some(CachedSupporter { supporter, gift }))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+    use crate::{aiode::SUPPORTER_SOURCE_ROLE, model::NewSupporterGift, test_db::test_connection};
+
+    fn supporter(
+        user_id: u64,
+        source: &str,
+        expiration_timestamp: Option<DateTime<Utc>>,
+    ) -> AiodeSupporter {
+        AiodeSupporter {
+            user_id: user_id.into(),
+            creation_timestamp: Utc::now(),
+            tier: None,
+            expiration_timestamp,
+            source: source.to_string(),
+            revocation_deadline: None,
+        }
+    }
+
+    fn gift(recipient_user_id: u64, expiration_timestamp: DateTime<Utc>) -> NewSupporterGift {
+        NewSupporterGift {
+            gifter_user_id: None,
+            recipient_user_id: recipient_user_id.into(),
+            tier: "premium".to_string(),
+            duration_days: 30,
+            expiration_timestamp,
+        }
+    }
+
+    fn cache_of(supporters: impl IntoIterator<Item = CachedSupporter>) -> SupporterCache {
+        SupporterCache {
+            supporters: supporters
+                .into_iter()
+                .map(|cached| (cached.supporter.user_id.get(), cached))
+                .collect(),
+            in_sync: true,
+        }
+    }
+
+    #[test]
+    fn expired_supporters_are_not_served() {
+        let now = Utc::now();
+        let cache = cache_of([
+            CachedSupporter {
+                supporter: supporter(1, SUPPORTER_SOURCE_ROLE, Some(now - Duration::hours(1))),
+                gift: None,
+            },
+            CachedSupporter {
+                supporter: supporter(2, SUPPORTER_SOURCE_ROLE, Some(now + Duration::hours(1))),
+                gift: None,
+            },
+            CachedSupporter {
+                supporter: supporter(3, SUPPORTER_SOURCE_ROLE, None),
+                gift: None,
+            },
+        ]);
+
+        assert!(cache.lookup(1, now, true).supporter.is_none());
+        assert!(cache.lookup(2, now, true).supporter.is_some());
+        assert!(cache.lookup(3, now, true).supporter.is_some());
+        assert!(cache.lookup(4, now, true).supporter.is_none());
+    }
+
+    #[test]
+    fn expired_gifts_are_not_served() {
+        let now = Utc::now();
+        let cache = cache_of([CachedSupporter {
+            supporter: supporter(1, SUPPORTER_SOURCE_GIFT, None),
+            gift: Some(SupporterGift {
+                pk: 1,
+                gifter_user_id: None,
+                recipient_user_id: 1.into(),
+                tier: "premium".to_string(),
+                duration_days: 30,
+                expiration_timestamp: now + Duration::hours(1),
+                creation_timestamp: now,
+            }),
+        }]);
+
+        assert!(cache.lookup(1, now, true).supporter.unwrap().gift.is_some());
+        let later = now + Duration::hours(2);
+        assert!(cache
+            .lookup(1, later, true)
+            .supporter
+            .unwrap()
+            .gift
+            .is_none());
+    }
+
+    #[test]
+    fn lookups_are_stale_while_changes_may_be_missed() {
+        let now = Utc::now();
+        let mut cache = cache_of([CachedSupporter {
+            supporter: supporter(1, SUPPORTER_SOURCE_ROLE, None),
+            gift: None,
+        }]);
+
+        assert!(!cache.lookup(1, now, true).stale);
+        assert!(cache.lookup(1, now, false).stale);
+
+        cache.in_sync = false;
+        let lookup = cache.lookup(1, now, true);
+        assert!(lookup.stale);
+        // the last known status is still served
+        assert!(lookup.supporter.is_some());
+    }
+
+    #[test]
+    fn reloaded_supporters_replace_cached_ones() {
+        let now = Utc::now();
+        let mut cache = cache_of([CachedSupporter {
+            supporter: supporter(1, SUPPORTER_SOURCE_ROLE, None),
+            gift: None,
+        }]);
+
+        cache.update(
+            2,
+            Some(CachedSupporter {
+                supporter: supporter(2, SUPPORTER_SOURCE_ROLE, None),
+                gift: None,
+            }),
+        );
+        cache.update(1, None);
+
+        assert!(cache.lookup(1, now, true).supporter.is_none());
+        assert!(cache.lookup(2, now, true).supporter.is_some());
+    }
+
+    #[tokio::test]
+    async fn loads_supporters_with_their_latest_active_gift() {
+        let Some(mut connection) = test_connection().await else {
+            return;
+        };
+        let now = Utc::now();
+        diesel::insert_into(aiode_supporter::table)
+            .values(
+                [
+                    supporter(1, SUPPORTER_SOURCE_GIFT, Some(now + Duration::days(60))),
+                    supporter(2, SUPPORTER_SOURCE_ROLE, None),
+                ]
+                .as_slice(),
+            )
+            .execute(&mut connection)
+            .await
+            .unwrap();
+        diesel::insert_into(supporter_gift::table)
+            .values(
+                [
+                    gift(1, now - Duration::days(1)),
+                    gift(1, now + Duration::days(60)),
+                    gift(1, now + Duration::days(30)),
+                    // not a gifted supporter
+                    gift(2, now + Duration::days(30)),
+                ]
+                .as_slice(),
+            )
+            .execute(&mut connection)
+            .await
+            .unwrap();
+
+        let cache = load_supporter_cache(&mut connection).await.unwrap();
+
+        assert!(cache.in_sync);
+        assert_eq!(cache.supporters.len(), 2);
+        let gift = cache.supporters[&1].gift.as_ref().unwrap();
+        assert!(gift.expiration_timestamp > now + Duration::days(59));
+        assert!(cache.supporters[&2].gift.is_none());
+    }
+}
@@ -1,127 +1,334 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Mutex,
+    time::Duration,
+};
+
+use chrono::Utc;
+use diesel::{ExpressionMethods, QueryDsl};
 use diesel_async::RunQueryDsl;
+use futures::FutureExt;
 use lazy_static::lazy_static;
-use rusty_pool::ThreadPool;
-use serenity::all::{GuildId, RoleId};
-use tokio::runtime::Handle;
+use serenity::all::{GuildId, UserId};
+use tokio::{
+    sync::Semaphore,
+    task::AbortHandle,
+    time::{Instant, MissedTickBehavior},
+};
+use tracing::Instrument;
 
 use crate::{
-    acquire_db_connection, error::Error, model::NewAiodeSupporter, schema::aiode_supporter,
-    AIODE_SUPPORTER_ROLE_ID, AIODE_SUPPORT_GUILD_ID, DISCORD_TOKEN,
+    acquire_db_connection,
+    aiode::{self, expire_aiode_supporter, sync_supporters, SupportGuildConfig},
+    alert, announcement, badge, credits,
+    error::Error,
+    guild::fetch_guild_members,
+    lease,
+    module::{ScheduledTask, TaskFn},
+    schema::aiode_supporter,
+    snowflake::Snowflake,
+    store::PgSupporterStore,
+    supporter_cache, AIODE_BOOSTERS_ARE_SUPPORTERS, AIODE_SUPPORTER_ROLE_ID,
+    AIODE_SUPPORT_GUILD_ID, MAX_CONCURRENT_TASKS,
 };
 
 lazy_static! {
-    pub static ref TASK_POOL: ThreadPool = {
-        let task_pool_worker_count = std::env::var("GLYPH_TASK_POOL_WORKER_COUNT")
-            .map(|s| {
-                s.parse::<usize>()
-                    .expect("GLYPH_TASK_POOL_WORKER_COUNT invalid")
-            })
-            .unwrap_or(4);
-        rusty_pool::Builder::new()
-            .core_size(task_pool_worker_count)
-            .max_size(task_pool_worker_count)
-            .name(String::from("task_pool"))
-            .build()
-    };
-    pub static ref RUNNING_TASK_IDS: flurry::HashSet<&'static str> = flurry::HashSet::new();
+    /// Limits the number of tasks executing at the same time, tasks wait for a permit once submitted.
+    static ref TASK_PERMITS: Semaphore = Semaphore::new(*MAX_CONCURRENT_TASKS);
+    /// Tasks that have been submitted and not yet finished, used to skip tasks that are already
+    /// running and to cancel them. The abort handle is `None` while the task is being spawned.
+    static ref RUNNING_TASKS: Mutex<HashMap<&'static str, Option<AbortHandle>>> =
+        Mutex::new(HashMap::new());
 }
 
-pub fn submit_task(
-    task_id: &'static str,
-    task: impl Fn(Handle) -> Result<(), Error> + Send + 'static,
-) {
-    let tokio_handle = Handle::current();
-    ThreadPool::execute(&TASK_POOL, move || {
-        let running_task_ids = RUNNING_TASK_IDS.pin();
-        // only run task if not already running
-        if running_task_ids.insert(task_id) {
-            let _sentinel = TaskSentinel {
-                task_id,
-                running_task_ids,
-            };
-
-            log::info!("Starting task {task_id}");
-            let now = std::time::Instant::now();
-            if let Err(e) = task(tokio_handle) {
-                log::error!("Error executing task {task_id}: {}", e);
-            }
-            log::info!("Finished task {task_id} after {:?}", now.elapsed());
-        } else {
-            log::warn!("Skipping task {task_id} because it is already running")
+/// Submits each task at its interval, starting one interval after the scheduler is started.
+pub async fn run_scheduler(tasks: Vec<ScheduledTask>) {
+    futures::future::join_all(tasks.into_iter().map(|task| async move {
+        let mut interval = tokio::time::interval_at(Instant::now() + task.interval, task.interval);
+        // do not catch up on ticks missed while the runtime was busy
+        interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        loop {
+            interval.tick().await;
+            submit_task(task.id, task.interval, task.timeout, task.run);
         }
-    })
+    }))
+    .await;
 }
 
-pub fn refresh_aiode_supporters(tokio_handle: Handle) -> Result<(), Error> {
-    if AIODE_SUPPORT_GUILD_ID.is_some() && AIODE_SUPPORTER_ROLE_ID.is_some() {
-        tokio_handle.block_on(async {
-            let serenity_http = serenity::http::Http::new(&DISCORD_TOKEN);
+/// Spawns the task on the current runtime unless a task with the same id is still running. The task
+/// only executes if this instance acquires its lease, so that only one of several replicas runs each
+/// task per interval, and is cancelled if it does not finish within the given timeout or the lease is
+/// lost.
+pub fn submit_task(task_id: &'static str, interval: Duration, timeout: Duration, task: TaskFn) {
+    {
+        let mut running_tasks = RUNNING_TASKS.lock().expect("running tasks lock poisoned");
+        if running_tasks.contains_key(task_id) {
+            tracing::warn!("Skipping task {task_id} because it is already running");
+            return;
+        }
+        // the lock is released before spawning, as spawning drops the task and thus the sentinel
+        // locking the running tasks right away if the runtime is shutting down
+        running_tasks.insert(task_id, None);
+    }
 
-            let guild_id: GuildId = AIODE_SUPPORT_GUILD_ID.unwrap().into();
-            let role_id: RoleId = AIODE_SUPPORTER_ROLE_ID.unwrap().into();
+    // created before spawning so that the entry is removed even if the task is cancelled before it is
+    // polled
+    let sentinel = TaskSentinel { task_id };
+    let join_handle = tokio::spawn(async move {
+        let _sentinel = sentinel;
+        let _permit = TASK_PERMITS.acquire().await.expect("task semaphore closed");
 
-            let mut supporters: Vec<u64> = Vec::new();
-            let mut last_member = None;
-            let limit = 500;
+        match lease::try_acquire_lease(task_id, lease::TASK_LEASE_DURATION).await {
+            Ok(true) => {}
+            Ok(false) => {
+                tracing::debug!("Skipping task {task_id} because another instance holds its lease");
+                return;
+            }
+            Err(e) => {
+                tracing::error!("Skipping task {task_id} because its lease could not be acquired: {e}");
+                alert::report_error(
+                    format!("task_lease:{task_id}"),
+                    format!(
+                        "Skipping task `{task_id}` because its lease could not be acquired: {e}"
+                    ),
+                );
+                return;
+            }
+        }
+
+        tracing::info!("Starting task {task_id}");
+        let now = std::time::Instant::now();
+        tokio::select! {
+            result = tokio::time::timeout(timeout, task()) => match result {
+                Ok(Ok(())) => alert::report_task_success(task_id),
+                Ok(Err(e)) => {
+                    tracing::error!("Error executing task {task_id}: {}", e);
+                    alert::report_task_failure(task_id, &e.to_string());
+                }
+                Err(_) => {
+                    tracing::error!(
+                        "Cancelled task {task_id} because it exceeded its timeout of {timeout:?}"
+                    );
+                    alert::report_task_failure(
+                        task_id,
+                        &format!("exceeded its timeout of {timeout:?}"),
+                    );
+                }
+            },
+            _ = lease::hold_lease(task_id, lease::TASK_LEASE_DURATION) => {
+                tracing::error!("Cancelled task {task_id} because its lease has been lost");
+            }
+        }
+        tracing::info!("Finished task {task_id} after {:?}", now.elapsed());
 
-            loop {
-                let members = serenity_http
-                    .get_guild_members(guild_id, Some(limit), last_member)
-                    .await?;
+        // keep the lease for most of the interval so that replicas whose schedule is offset from this
+        // instance do not run the task again right away
+        let keep_lease_for = (interval * 9 / 10).saturating_sub(now.elapsed());
+        if let Err(e) = lease::release_lease(task_id, keep_lease_for).await {
+            tracing::error!("Failed to release lease of task {task_id}: {e}");
+        }
+    }
+    .instrument(tracing::info_span!("task", task_id)));
 
-                last_member = members.last().map(|m| m.user.id.into());
+    // the task may already have finished and removed its entry, tasks with the same id are only
+    // submitted by the same scheduler loop, so an existing entry belongs to this task
+    if let Some(abort_handle) = RUNNING_TASKS
+        .lock()
+        .expect("running tasks lock poisoned")
+        .get_mut(task_id)
+    {
+        *abort_handle = Some(join_handle.abort_handle());
+    }
+}
 
-                members
-                    .iter()
-                    .filter(|member| member.roles.contains(&role_id))
-                    .for_each(|member| {
-                        supporters.push(member.user.id.into());
-                    });
+/// Cancels all running tasks.
+pub fn cancel_all_tasks() {
+    let running_tasks = RUNNING_TASKS.lock().expect("running tasks lock poisoned");
+    for (task_id, abort_handle) in running_tasks.iter() {
+        tracing::info!("Cancelling task {task_id}");
+        if let Some(abort_handle) = abort_handle {
+            abort_handle.abort();
+        }
+    }
+}
 
-                if members.len() < limit as usize {
-                    break;
-                }
-            }
+pub async fn refresh_aiode_supporters() -> Result<(), Error> {
+    if AIODE_SUPPORT_GUILD_ID.is_some()
+        && (AIODE_SUPPORTER_ROLE_ID.is_some() || *AIODE_BOOSTERS_ARE_SUPPORTERS)
+    {
+        let guild_id: GuildId = AIODE_SUPPORT_GUILD_ID.unwrap().into();
 
-            let supporters = supporters
-                .iter()
-                .map(|supporter| NewAiodeSupporter {
-                    user_id: supporter.into(),
-                })
-                .collect::<Vec<_>>();
-            if !supporters.is_empty() {
-                let mut connection = acquire_db_connection().await?;
-
-                // split items into chunks to avoid hitting the parameter limit
-                let mut res = 0;
-                for supporter_chunk in supporters.chunks(4096) {
-                    res += diesel::insert_into(aiode_supporter::table)
-                        .values(supporter_chunk)
-                        .on_conflict_do_nothing()
-                        .execute(&mut connection)
-                        .await?;
-                }
+        let members = fetch_guild_members(guild_id).await?;
+        sync_supporters(&PgSupporterStore, &SupportGuildConfig::from_env(), &members).await?;
 
-                if res > 0 {
-                    log::info!("Added {} supporters to the aiode_supporter table", res);
-                }
-            }
+        // catches up on changes the supporter cache may have missed
+        supporter_cache::request_reload();
 
-            Ok(())
-        })
+        Ok(())
     } else {
-        log::warn!("Cannot perform refresh_aiode_supporters because AIODE_SUPPORT_GUILD_ID or AIODE_SUPPORTER_ROLE_ID is not set");
+        tracing::warn!("Cannot perform refresh_aiode_supporters because AIODE_SUPPORT_GUILD_ID is not set or neither AIODE_SUPPORTER_ROLE_ID nor AIODE_BOOSTERS_ARE_SUPPORTERS is set");
         Ok(())
     }
 }
 
-struct TaskSentinel<'a> {
+/// Grants and removes badges according to the roles tracked via `GLYPH_BADGE_ROLES`, catching up on
+/// role changes that were missed while the bot was offline.
+pub async fn refresh_user_badges() -> Result<(), Error> {
+    let config = badge::BadgeConfig::from_env();
+    if config.badge_roles.is_empty() {
+        tracing::warn!("Cannot perform refresh_user_badges because GLYPH_BADGE_ROLES is not set");
+        return Ok(());
+    }
+
+    let mut holders: HashMap<&str, HashSet<UserId>> = config
+        .badge_roles
+        .iter()
+        .map(|badge_role| (badge_role.badge.as_str(), HashSet::new()))
+        .collect();
+
+    for guild_id in config.guild_ids() {
+        for member in fetch_guild_members(guild_id).await? {
+            for badge in config.badges_for_roles(guild_id, &member.roles) {
+                holders.entry(badge).or_default().insert(member.user.id);
+            }
+        }
+    }
+
+    badge::sync_badge_holders(&config, holders).await
+}
+
+/// Removes badges whose revocation grace period has passed, see
+/// [`crate::badge::finalise_badge_revocations`].
+pub async fn finalise_badge_revocations() -> Result<(), Error> {
+    badge::finalise_badge_revocations().await
+}
+
+/// Tasks maintaining the aiode_supporter table, scheduled regardless of the enabled modules as
+/// several modules grant time limited supporter status (vouchers, gifts, payments) or schedule
+/// revocations (supporters, payments).
+pub fn core_tasks() -> Vec<ScheduledTask> {
+    vec![
+        ScheduledTask {
+            id: "expire_aiode_supporters",
+            interval: Duration::from_secs(60),
+            timeout: Duration::from_secs(5 * 60),
+            run: || expire_aiode_supporters().boxed(),
+        },
+        ScheduledTask {
+            id: "finalise_supporter_revocations",
+            interval: Duration::from_secs(5 * 60),
+            timeout: Duration::from_secs(10 * 60),
+            run: || finalise_supporter_revocations().boxed(),
+        },
+    ]
+}
+
+/// Revokes time limited supporter status, e.g. granted by vouchers, once it has expired.
+pub async fn expire_aiode_supporters() -> Result<(), Error> {
+    let now = Utc::now();
+    let mut connection = acquire_db_connection().await?;
+    let expired_supporters = aiode_supporter::table
+        .filter(aiode_supporter::expiration_timestamp.le(now))
+        .select(aiode_supporter::user_id)
+        .load::<Snowflake>(&mut connection)
+        .await?;
+    drop(connection);
+
+    for user_id in expired_supporters {
+        expire_aiode_supporter(user_id.get(), now).await?;
+    }
+
+    Ok(())
+}
+
+/// Revokes supporter status of users whose revocation grace period has passed, see
+/// [`crate::aiode::finalise_supporter_revocations`].
+pub async fn finalise_supporter_revocations() -> Result<(), Error> {
+    aiode::finalise_supporter_revocations().await
+}
+
+pub async fn announce_supporter_milestones() -> Result<(), Error> {
+    announcement::announce_supporter_milestones().await
+}
+
+pub async fn refresh_supporter_credits() -> Result<(), Error> {
+    let supporter_credits = credits::refresh_supporter_credits().await?;
+    tracing::info!(
+        "Refreshed supporter credits of {} supporters",
+        supporter_credits.supporters.len()
+    );
+    Ok(())
+}
+
+/// Removes the task from the running tasks once it finishes, is cancelled or panics.
+struct TaskSentinel {
     task_id: &'static str,
-    running_task_ids: flurry::HashSetRef<'a, &'static str>,
 }
 
-impl Drop for TaskSentinel<'_> {
+impl Drop for TaskSentinel {
     fn drop(&mut self) {
-        self.running_task_ids.remove(self.task_id);
+        RUNNING_TASKS
+            .lock()
+            .expect("running tasks lock poisoned")
+            .remove(self.task_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_running(task_id: &str) -> bool {
+        RUNNING_TASKS
+            .lock()
+            .expect("running tasks lock poisoned")
+            .contains_key(task_id)
+    }
+
+    #[tokio::test]
+    async fn task_cancelled_before_it_is_polled_is_no_longer_running() {
+        let task_id = "cancelled_before_polled";
+        submit_task(
+            task_id,
+            Duration::from_secs(60),
+            Duration::from_secs(60),
+            || async { Ok(()) }.boxed(),
+        );
+        assert!(is_running(task_id));
+
+        // the current thread runtime has not polled the task yet
+        RUNNING_TASKS.lock().expect("running tasks lock poisoned")[task_id]
+            .as_ref()
+            .unwrap()
+            .abort();
+        for _ in 0..100 {
+            if !is_running(task_id) {
+                break;
+            }
+            tokio::task::yield_now().await;
+        }
+
+        assert!(!is_running(task_id));
+    }
+
+    #[test]
+    fn task_dropped_while_spawning_does_not_deadlock() {
+        let task_id = "dropped_while_spawning";
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let handle = runtime.handle().clone();
+        // spawning on a runtime that has been shut down drops the task and its sentinel right away
+        drop(runtime);
+
+        let _guard = handle.enter();
+        submit_task(
+            task_id,
+            Duration::from_secs(60),
+            Duration::from_secs(60),
+            || async { Ok(()) }.boxed(),
+        );
+
+        assert!(!is_running(task_id));
     }
 }
@@ -0,0 +1,567 @@
+use std::{fmt, io::Write, time::Instant};
+
+use tracing::{
+    field::{Field, Visit},
+    span, Event, Level, Span, Subscriber,
+};
+use tracing_appender::{non_blocking::WorkerGuard, rolling};
+use tracing_log::NormalizeEvent;
+use tracing_subscriber::{
+    filter::LevelFilter,
+    fmt::{
+        format::{self, FormatEvent, FormatFields},
+        writer::MakeWriterExt,
+        FmtContext, FormattedFields, MakeWriter,
+    },
+    layer::{Context, SubscriberExt},
+    registry::LookupSpan,
+    util::SubscriberInitExt,
+    EnvFilter, Layer,
+};
+
+use crate::util::OptFmt;
+
+/// Target of the API request spans and the access log.
+pub const API_TARGET: &str = "glyph_bot::api";
+
+/// Flushes buffered log lines and exported spans when dropped.
+pub struct TracingGuard {
+    _file_writer_guard: WorkerGuard,
+    #[cfg(feature = "otlp")]
+    tracer_provider: Option<opentelemetry_sdk::trace::TracerProvider>,
+}
+
+impl Drop for TracingGuard {
+    fn drop(&mut self) {
+        #[cfg(feature = "otlp")]
+        if let Some(ref tracer_provider) = self.tracer_provider {
+            if let Err(e) = tracer_provider.shutdown() {
+                eprintln!("Failed to shut down OTLP exporter: {e}");
+            }
+        }
+    }
+}
+
+/// Sets up logging to stdout and daily log files in `logs/`, the access log and, if enabled, OTLP
+/// span export. Must be called within a tokio runtime if OTLP export is enabled. The directives in
+/// `RUST_LOG` replace the default levels if set.
+pub fn init_tracing() -> TracingGuard {
+    let file_appender = rolling::Builder::new()
+        .rotation(rolling::Rotation::DAILY)
+        .filename_prefix("logs")
+        .filename_suffix("log")
+        .build("logs")
+        .expect("Failed to set up log file appender");
+    let (file_writer, file_writer_guard) = tracing_appender::non_blocking(file_appender);
+    let writer = std::io::stdout.and(file_writer);
+
+    let logging_level = if cfg!(debug_assertions) {
+        LevelFilter::DEBUG
+    } else {
+        LevelFilter::INFO
+    };
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| {
+        EnvFilter::new(format!(
+            "info,glyph_bot={logging_level},serenity::gateway=warn,serenity::http=warn"
+        ))
+    });
+
+    let registry = tracing_subscriber::registry()
+        .with(filter)
+        .with(
+            tracing_subscriber::fmt::layer()
+                .event_format(LogFormat)
+                .with_writer(writer.clone()),
+        )
+        .with(AccessLogLayer {
+            make_writer: writer,
+            max_level: logging_level,
+        });
+
+    #[cfg(feature = "otlp")]
+    {
+        let (otlp_layer, tracer_provider) = match *crate::OTLP_ENDPOINT {
+            Some(ref endpoint) => {
+                let (layer, tracer_provider) = otlp::layer(endpoint);
+                (Some(layer), Some(tracer_provider))
+            }
+            None => (None, None),
+        };
+        registry.with(otlp_layer).init();
+        TracingGuard {
+            _file_writer_guard: file_writer_guard,
+            tracer_provider,
+        }
+    }
+
+    #[cfg(not(feature = "otlp"))]
+    {
+        registry.init();
+        if crate::OTLP_ENDPOINT.is_some() {
+            tracing::warn!("Ignoring GLYPH_OTLP_ENDPOINT because the otlp feature is not enabled");
+        }
+        TracingGuard {
+            _file_writer_guard: file_writer_guard,
+        }
+    }
+}
+
+/// Creates the span of an API request, the request id and response status are recorded once known.
+pub fn request_span(info: warp::trace::Info) -> Span {
+    let span = tracing::info_span!(
+        target: API_TARGET,
+        "request",
+        remote_addr = %OptFmt(info.remote_addr()),
+        method = %info.method(),
+        path = info.path(),
+        version = ?info.version(),
+        referer = %OptFmt(info.referer()),
+        user_agent = %OptFmt(info.user_agent()),
+        request_id = tracing::field::Empty,
+        status = tracing::field::Empty,
+    );
+
+    #[cfg(feature = "otlp")]
+    otlp::set_remote_parent(&span, info.request_headers());
+
+    span
+}
+
+/// Formats events like the previous log setup, `[LEVEL][date][target] message`, with the fields of
+/// the enclosing spans, e.g. the request id, between target and message.
+struct LogFormat;
+
+impl<S, N> FormatEvent<S, N> for LogFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: format::Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        // events bridged from the log crate, e.g. by tokio-postgres, carry their target separately
+        let normalized_metadata = event.normalized_metadata();
+        let metadata = normalized_metadata.as_ref().unwrap_or(event.metadata());
+        write!(
+            writer,
+            "[{}]{}[{}] ",
+            metadata.level(),
+            chrono::Local::now().format("[%Y-%m-%d %H:%M:%S]"),
+            metadata.target()
+        )?;
+
+        if let Some(scope) = ctx.event_scope() {
+            for span in scope.from_root() {
+                write!(writer, "{}", span.name())?;
+                let extensions = span.extensions();
+                if let Some(fields) = extensions.get::<FormattedFields<N>>() {
+                    if !fields.is_empty() {
+                        write!(writer, "{{{fields}}}")?;
+                    }
+                }
+                writer.write_str(": ")?;
+            }
+        }
+
+        ctx.field_format().format_fields(writer.by_ref(), event)?;
+        writeln!(writer)
+    }
+}
+
+/// Writes an access log line for each API request span once it is closed, in the format previously
+/// produced by `warp::log::custom`.
+struct AccessLogLayer<W> {
+    make_writer: W,
+    /// Maximum level of access log lines, which depends on the duration and status of the request.
+    max_level: LevelFilter,
+}
+
+struct AccessLogEntry {
+    started_at: Instant,
+    fields: AccessLogFields,
+}
+
+#[derive(Default)]
+struct AccessLogFields {
+    remote_addr: String,
+    method: String,
+    path: String,
+    version: String,
+    status: Option<u64>,
+    referer: String,
+    user_agent: String,
+}
+
+impl Visit for AccessLogFields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "path" {
+            self.path = value.to_string();
+        }
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        if field.name() == "status" {
+            self.status = Some(value);
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        let value = format!("{value:?}");
+        match field.name() {
+            "remote_addr" => self.remote_addr = value,
+            "method" => self.method = value,
+            "path" => self.path = value,
+            "version" => self.version = value,
+            "referer" => self.referer = value,
+            "user_agent" => self.user_agent = value,
+            _ => {}
+        }
+    }
+}
+
+impl<S, W> Layer<S> for AccessLogLayer<W>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'a> MakeWriter<'a> + 'static,
+{
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        let metadata = attrs.metadata();
+        if metadata.target() != API_TARGET || metadata.name() != "request" {
+            return;
+        }
+
+        let mut fields = AccessLogFields::default();
+        attrs.record(&mut fields);
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(AccessLogEntry {
+                started_at: Instant::now(),
+                fields,
+            });
+        }
+    }
+
+    fn on_record(&self, id: &span::Id, values: &span::Record<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(entry) = span.extensions_mut().get_mut::<AccessLogEntry>() {
+                values.record(&mut entry.fields);
+            }
+        }
+    }
+
+    fn on_close(&self, id: span::Id, ctx: Context<'_, S>) {
+        let Some(entry) = ctx
+            .span(&id)
+            .and_then(|span| span.extensions_mut().remove::<AccessLogEntry>())
+        else {
+            return;
+        };
+
+        let elapsed = entry.started_at.elapsed();
+        let fields = entry.fields;
+        let is_success = fields
+            .status
+            .is_some_and(|status| (200..300).contains(&status));
+        let level = if elapsed.as_secs() >= 10 {
+            Level::WARN
+        } else if elapsed.as_millis() >= 250 || !is_success {
+            Level::INFO
+        } else {
+            Level::DEBUG
+        };
+        if level > self.max_level {
+            return;
+        }
+
+        let line = format!(
+            "[{}]{}[{}] {} \"{} {} {}\" {} \"{}\" \"{}\" {:?}\n",
+            level,
+            chrono::Local::now().format("[%Y-%m-%d %H:%M:%S]"),
+            API_TARGET,
+            fields.remote_addr,
+            fields.method,
+            fields.path,
+            fields.version,
+            OptFmt(fields.status),
+            fields.referer,
+            fields.user_agent,
+            elapsed,
+        );
+        if let Err(e) = self.make_writer.make_writer().write_all(line.as_bytes()) {
+            eprintln!("Failed to write access log: {e}");
+        }
+    }
+}
+
+#[cfg(feature = "otlp")]
+mod otlp {
+    use opentelemetry::{
+        global,
+        propagation::Extractor,
+        trace::{TraceError, TracerProvider as _},
+        KeyValue,
+    };
+    use opentelemetry_otlp::WithExportConfig;
+    use opentelemetry_sdk::{
+        propagation::TraceContextPropagator, runtime, trace::TracerProvider, Resource,
+    };
+    use tracing::{Span, Subscriber};
+    use tracing_opentelemetry::{OpenTelemetryLayer, OpenTelemetrySpanExt};
+    use tracing_subscriber::registry::LookupSpan;
+    use warp::http::HeaderMap;
+
+    use crate::INSTANCE_ID;
+
+    const SERVICE_NAME: &str = "glyph-bot";
+
+    /// Creates the layer exporting spans to the OTLP collector at the endpoint via gRPC.
+    pub fn layer<S>(
+        endpoint: &str,
+    ) -> (
+        OpenTelemetryLayer<S, opentelemetry_sdk::trace::Tracer>,
+        TracerProvider,
+    )
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        let tracer_provider = tracer_provider(endpoint).expect("Failed to set up OTLP exporter");
+        global::set_text_map_propagator(TraceContextPropagator::new());
+        let tracer = tracer_provider.tracer(SERVICE_NAME);
+        (
+            tracing_opentelemetry::layer().with_tracer(tracer),
+            tracer_provider,
+        )
+    }
+
+    fn tracer_provider(endpoint: &str) -> Result<TracerProvider, TraceError> {
+        let exporter = opentelemetry_otlp::SpanExporter::builder()
+            .with_tonic()
+            .with_endpoint(endpoint)
+            .build()?;
+        Ok(TracerProvider::builder()
+            .with_batch_exporter(exporter, runtime::Tokio)
+            .with_resource(Resource::new([
+                KeyValue::new("service.name", SERVICE_NAME),
+                KeyValue::new("service.instance.id", INSTANCE_ID.clone()),
+            ]))
+            .build())
+    }
+
+    /// Continues the trace of the caller if the request carries a W3C `traceparent` header.
+    pub fn set_remote_parent(span: &Span, headers: &HeaderMap) {
+        let parent = global::get_text_map_propagator(|propagator| {
+            propagator.extract(&HeaderExtractor(headers))
+        });
+        span.set_parent(parent);
+    }
+
+    struct HeaderExtractor<'a>(&'a HeaderMap);
+
+    impl Extractor for HeaderExtractor<'_> {
+        fn get(&self, key: &str) -> Option<&str> {
+            self.0.get(key).and_then(|value| value.to_str().ok())
+        }
+
+        fn keys(&self) -> Vec<&str> {
+            self.0.keys().map(|key| key.as_str()).collect()
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::time::Duration;
+
+        use opentelemetry::trace::TraceContextExt;
+        use opentelemetry_proto::tonic::collector::trace::v1::{
+            trace_service_server::{TraceService, TraceServiceServer},
+            ExportTraceServiceRequest, ExportTraceServiceResponse,
+        };
+        use tokio::{net::TcpListener, sync::mpsc};
+        use tokio_stream::wrappers::TcpListenerStream;
+        use tonic::{transport::Server, Request, Response, Status};
+        use tracing_subscriber::layer::SubscriberExt;
+        use warp::http::HeaderValue;
+
+        use super::*;
+
+        /// In-process OTLP collector forwarding the received export requests.
+        struct Collector(mpsc::UnboundedSender<ExportTraceServiceRequest>);
+
+        #[tonic::async_trait]
+        impl TraceService for Collector {
+            async fn export(
+                &self,
+                request: Request<ExportTraceServiceRequest>,
+            ) -> Result<Response<ExportTraceServiceResponse>, Status> {
+                let _ = self.0.send(request.into_inner());
+                Ok(Response::new(ExportTraceServiceResponse {
+                    partial_success: None,
+                }))
+            }
+        }
+
+        #[tokio::test(flavor = "multi_thread")]
+        async fn spans_are_exported_to_the_collector() {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let endpoint = format!("http://{}", listener.local_addr().unwrap());
+            let (sender, mut requests) = mpsc::unbounded_channel();
+            tokio::spawn(
+                Server::builder()
+                    .add_service(TraceServiceServer::new(Collector(sender)))
+                    .serve_with_incoming(TcpListenerStream::new(listener)),
+            );
+
+            let tracer_provider = tracer_provider(&endpoint).unwrap();
+            let subscriber = tracing_subscriber::registry().with(
+                tracing_opentelemetry::layer().with_tracer(tracer_provider.tracer(SERVICE_NAME)),
+            );
+            tracing::subscriber::with_default(subscriber, || {
+                tracing::info_span!("exported_span").in_scope(|| {});
+            });
+            // flushing blocks until the batch has been exported
+            let tracer_provider = tokio::task::spawn_blocking(move || {
+                for result in tracer_provider.force_flush() {
+                    result.unwrap();
+                }
+                tracer_provider
+            })
+            .await
+            .unwrap();
+
+            let request = tokio::time::timeout(Duration::from_secs(10), requests.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            let resource_spans = &request.resource_spans[0];
+            assert!(resource_spans
+                .resource
+                .as_ref()
+                .unwrap()
+                .attributes
+                .iter()
+                .any(|attribute| attribute.key == "service.name"));
+            let span_names = resource_spans
+                .scope_spans
+                .iter()
+                .flat_map(|scope_spans| &scope_spans.spans)
+                .map(|span| span.name.as_str())
+                .collect::<Vec<_>>();
+            assert_eq!(span_names, vec!["exported_span"]);
+
+            tokio::task::spawn_blocking(move || tracer_provider.shutdown())
+                .await
+                .unwrap()
+                .unwrap();
+        }
+
+        #[test]
+        fn remote_parent_continues_the_callers_trace() {
+            global::set_text_map_propagator(TraceContextPropagator::new());
+            let tracer_provider = TracerProvider::builder().build();
+            let subscriber = tracing_subscriber::registry().with(
+                tracing_opentelemetry::layer().with_tracer(tracer_provider.tracer(SERVICE_NAME)),
+            );
+            let mut headers = HeaderMap::new();
+            headers.insert(
+                "traceparent",
+                HeaderValue::from_static("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"),
+            );
+
+            let trace_id = tracing::subscriber::with_default(subscriber, || {
+                let span = tracing::info_span!("request");
+                set_remote_parent(&span, &headers);
+                span.context().span().span_context().trace_id()
+            });
+
+            assert_eq!(trace_id.to_string(), "4bf92f3577b34da6a3ce929d0e0e4736");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use warp::{test::request, Filter};
+
+    use super::*;
+    use crate::{error::recover_with_request_id, module::route};
+
+    /// Collects the written log lines.
+    #[derive(Clone, Default)]
+    struct CapturedLog(Arc<Mutex<Vec<u8>>>);
+
+    impl CapturedLog {
+        fn lines(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    impl Write for CapturedLog {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> MakeWriter<'a> for CapturedLog {
+        type Writer = Self;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    /// Requests the path and returns the access log line without the date, which the line must start
+    /// with, and the request duration at its end.
+    async fn access_log_line(path: &str) -> String {
+        let log = CapturedLog::default();
+        let subscriber = tracing_subscriber::registry().with(AccessLogLayer {
+            make_writer: log.clone(),
+            max_level: LevelFilter::DEBUG,
+        });
+        let _guard = tracing::subscriber::set_default(subscriber);
+        let routes = recover_with_request_id(route(
+            warp::path!("users" / u64).map(|user_id: u64| user_id.to_string()),
+        ))
+        .with(warp::trace(request_span));
+
+        request()
+            .path(path)
+            .header("user-agent", "test-agent")
+            .reply(&routes)
+            .await;
+
+        let lines = log.lines();
+        assert_eq!(lines.lines().count(), 1);
+        let (level, line) = lines.split_once(']').unwrap();
+        let (_date, line) = line.split_once("][").unwrap();
+        let (line, elapsed) = line.trim_end().rsplit_once(' ').unwrap();
+        assert!(elapsed.ends_with('s'), "{elapsed} is not a duration");
+        format!("{level}] [{line}")
+    }
+
+    #[tokio::test]
+    async fn access_log_keeps_previous_format() {
+        let line = access_log_line("/users/1").await;
+
+        // slow test machines may exceed the duration logged at debug level
+        let line = line.replacen("[INFO]", "[DEBUG]", 1);
+        assert_eq!(
+            line,
+            "[DEBUG] [glyph_bot::api] - \"GET /users/1 HTTP/1.1\" 200 \"-\" \"test-agent\""
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_requests_are_logged_at_info_level() {
+        let line = access_log_line("/unknown").await;
+
+        assert!(line.starts_with("[INFO] [glyph_bot::api] - \"GET /unknown HTTP/1.1\" 404 "));
+    }
+}
@@ -0,0 +1,35 @@
+//! Database for tests of queries. Tests using it are skipped unless `GLYPH_TEST_DATABASE_URL` is set,
+//! so that `cargo test` runs without a database.
+
+use std::sync::Once;
+
+use diesel::Connection;
+use diesel_async::{AsyncConnection, AsyncPgConnection};
+use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+
+const MIGRATIONS: EmbeddedMigrations = embed_migrations!();
+
+static RUN_MIGRATIONS: Once = Once::new();
+
+/// Connects to the test database within a transaction that is never committed, so tests neither
+/// see each other's data nor leave any behind. Returns `None` if no test database is configured.
+pub async fn test_connection() -> Option<AsyncPgConnection> {
+    let database_url = std::env::var("GLYPH_TEST_DATABASE_URL").ok()?;
+
+    RUN_MIGRATIONS.call_once(|| {
+        let mut connection = diesel::pg::PgConnection::establish(&database_url)
+            .expect("Failed to connect to the test database");
+        connection
+            .run_pending_migrations(MIGRATIONS)
+            .expect("Failed to run migrations on the test database");
+    });
+
+    let mut connection = AsyncPgConnection::establish(&database_url)
+        .await
+        .expect("Failed to connect to the test database");
+    connection
+        .begin_test_transaction()
+        .await
+        .expect("Failed to begin test transaction");
+    Some(connection)
+}
@@ -1,4 +1,8 @@
-use std::fmt;
+use std::{fmt, future::Future, sync::OnceLock};
+
+use rand::{distributions::Uniform, Rng};
+use sha2::{Digest, Sha256};
+use tokio::sync::mpsc;
 
 pub struct OptFmt<T>(pub Option<T>);
 
@@ -11,3 +15,67 @@ impl<T: fmt::Display> fmt::Display for OptFmt<T> {
         }
     }
 }
+
+// omit characters that are easily confused, such as 0 and O or 1 and I
+const CODE_ALPHABET: &[u8] = b"23456789ABCDEFGHJKLMNPQRSTUVWXYZ";
+
+/// Generates a random code of the given length that is easy to read and type.
+pub fn generate_code(length: usize) -> String {
+    rand::thread_rng()
+        .sample_iter(Uniform::from(0..CODE_ALPHABET.len()))
+        .take(length)
+        .map(|i| CODE_ALPHABET[i] as char)
+        .collect()
+}
+
+/// Hashes a code generated by [`generate_code`] for storage, ignoring case and surrounding whitespace.
+pub fn hash_code(code: &str) -> String {
+    hex::encode(Sha256::digest(code.trim().to_uppercase().as_bytes()))
+}
+
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Hands items to a task that is started at most once and then runs for the rest of the process, e.g.
+/// posting alerts or writing archived events, so that callers never wait for it.
+pub struct Worker<T> {
+    name: &'static str,
+    sender: OnceLock<mpsc::UnboundedSender<T>>,
+}
+
+impl<T: Send + 'static> Worker<T> {
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            sender: OnceLock::new(),
+        }
+    }
+
+    /// Spawns the task receiving the sent items. Panics if the worker has already been started.
+    pub fn start<F, Fut>(&self, run: F)
+    where
+        F: FnOnce(mpsc::UnboundedReceiver<T>) -> Fut,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        if self.sender.set(sender).is_err() {
+            panic!("{} has already been started", self.name);
+        }
+        tokio::spawn(run(receiver));
+    }
+
+    pub fn is_started(&self) -> bool {
+        self.sender.get().is_some()
+    }
+
+    /// Sends the item to the task, dropping it if the worker has not been started or has stopped.
+    pub fn send(&self, item: T) {
+        let Some(sender) = self.sender.get() else {
+            return;
+        };
+        if sender.send(item).is_err() {
+            tracing::error!("{} has stopped, dropping item", self.name);
+        }
+    }
+}
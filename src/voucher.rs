@@ -0,0 +1,516 @@
+use chrono::{DateTime, Utc};
+use diesel::{BoolExpressionMethods, ExpressionMethods, OptionalExtension, QueryDsl};
+use diesel_async::{
+    scoped_futures::ScopedFutureExt, AsyncConnection, AsyncPgConnection, RunQueryDsl,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use warp::{reject::Rejection, reply::Reply};
+
+use crate::{
+    acquire_db_connection,
+    aiode::{assign_aiode_supporter_role, upsert_time_limited_supporter, SUPPORTER_SOURCE_VOUCHER},
+    error::{Error, ErrorResponse},
+    model::{NewVoucher, NewVoucherRedemption, Voucher},
+    schema::{voucher, voucher_redemption},
+    util::{generate_code, hash_code},
+};
+
+const VOUCHER_CODE_LENGTH: usize = 12;
+const MAX_VOUCHER_BATCH_SIZE: u32 = 1000;
+
+#[derive(Deserialize, ToSchema)]
+pub struct CreateVouchersRequest {
+    pub batch: String,
+    pub tier: String,
+    pub duration_days: u32,
+    #[serde(default = "default_max_redemptions")]
+    pub max_redemptions: u32,
+    pub count: u32,
+    /// Point in time after which the codes can no longer be redeemed.
+    pub valid_until: Option<DateTime<Utc>>,
+}
+
+fn default_max_redemptions() -> u32 {
+    1
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CreateVouchersResponse {
+    pub batch: String,
+    /// The plain codes, only available in this response as only their hashes are stored.
+    pub codes: Vec<String>,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct ListVouchersQuery {
+    /// Only list the vouchers of this batch.
+    pub batch: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct VoucherResponse {
+    pub id: i32,
+    pub batch: String,
+    pub tier: String,
+    pub duration_days: i32,
+    pub max_redemptions: i32,
+    pub redemption_count: i32,
+    pub revoked: bool,
+    pub valid_until: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<Voucher> for VoucherResponse {
+    fn from(voucher: Voucher) -> Self {
+        Self {
+            id: voucher.pk,
+            batch: voucher.batch,
+            tier: voucher.tier,
+            duration_days: voucher.duration_days,
+            max_redemptions: voucher.max_redemptions,
+            redemption_count: voucher.redemption_count,
+            revoked: voucher.revoked,
+            valid_until: voucher.expiration_timestamp,
+            created_at: voucher.creation_timestamp,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct VouchersResponse {
+    pub vouchers: Vec<VoucherResponse>,
+}
+
+pub enum RedeemVoucherResult {
+    Redeemed {
+        tier: String,
+        supporter_until: Option<DateTime<Utc>>,
+    },
+    AlreadyRedeemed,
+    Invalid,
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/vouchers",
+    tag = "vouchers",
+    request_body = CreateVouchersRequest,
+    security(("admin_token" = [])),
+    responses(
+        (status = 200, description = "The created voucher codes", body = CreateVouchersResponse),
+        (status = 400, description = "Malformed request or invalid count, duration or redemptions", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 413, description = "Request body too large", body = ErrorResponse),
+        (status = 500, description = "Vouchers could not be created", body = ErrorResponse),
+    ),
+)]
+pub async fn create_vouchers_handler(
+    request: CreateVouchersRequest,
+) -> Result<impl Reply, Rejection> {
+    if request.count == 0 || request.count > MAX_VOUCHER_BATCH_SIZE {
+        return Err(Error::InvalidPayloadError(format!(
+            "count must be between 1 and {MAX_VOUCHER_BATCH_SIZE}"
+        ))
+        .into());
+    }
+    if request.duration_days == 0 || request.max_redemptions == 0 {
+        return Err(Error::InvalidPayloadError(String::from(
+            "duration_days and max_redemptions must be positive",
+        ))
+        .into());
+    }
+    let duration_days = i32::try_from(request.duration_days)
+        .map_err(|e| Error::InvalidPayloadError(e.to_string()))?;
+    let max_redemptions = i32::try_from(request.max_redemptions)
+        .map_err(|e| Error::InvalidPayloadError(e.to_string()))?;
+
+    let codes = (0..request.count)
+        .map(|_| generate_code(VOUCHER_CODE_LENGTH))
+        .collect::<Vec<_>>();
+    let vouchers = codes
+        .iter()
+        .map(|code| NewVoucher {
+            code_hash: hash_code(code),
+            batch: request.batch.clone(),
+            tier: request.tier.clone(),
+            duration_days,
+            max_redemptions,
+            expiration_timestamp: request.valid_until,
+        })
+        .collect::<Vec<_>>();
+
+    let mut connection = acquire_db_connection().await?;
+    diesel::insert_into(voucher::table)
+        .values(&vouchers)
+        .execute(&mut connection)
+        .await
+        .map_err(Error::from)?;
+
+    tracing::info!(
+        "Created {} {} vouchers in batch {}",
+        codes.len(),
+        request.tier,
+        request.batch
+    );
+
+    Ok(warp::reply::json(&CreateVouchersResponse {
+        batch: request.batch,
+        codes,
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/vouchers",
+    tag = "vouchers",
+    params(ListVouchersQuery),
+    security(("admin_token" = [])),
+    responses(
+        (status = 200, description = "Vouchers in order of creation", body = VouchersResponse),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 500, description = "Vouchers could not be loaded", body = ErrorResponse),
+    ),
+)]
+pub async fn list_vouchers_handler(query: ListVouchersQuery) -> Result<impl Reply, Rejection> {
+    let mut connection = acquire_db_connection().await?;
+
+    let mut vouchers_query = voucher::table.order(voucher::pk).into_boxed();
+    if let Some(batch) = query.batch {
+        vouchers_query = vouchers_query.filter(voucher::batch.eq(batch));
+    }
+    let vouchers = vouchers_query
+        .load::<Voucher>(&mut connection)
+        .await
+        .map_err(Error::from)?;
+
+    Ok(warp::reply::json(&VouchersResponse {
+        vouchers: vouchers.into_iter().map(VoucherResponse::from).collect(),
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/vouchers/{voucher_id}/revoke",
+    tag = "vouchers",
+    params(("voucher_id" = i32, Path, description = "Id of the voucher")),
+    security(("admin_token" = [])),
+    responses(
+        (status = 200, description = "The revoked voucher", body = VoucherResponse),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 404, description = "The voucher does not exist", body = ErrorResponse),
+        (status = 500, description = "Voucher could not be revoked", body = ErrorResponse),
+    ),
+)]
+pub async fn revoke_voucher_handler(voucher_pk: i32) -> Result<impl Reply, Rejection> {
+    let mut connection = acquire_db_connection().await?;
+
+    let voucher = revoke_voucher(&mut connection, voucher_pk)
+        .await?
+        .ok_or_else(warp::reject::not_found)?;
+
+    tracing::info!("Revoked voucher {} of batch {}", voucher.pk, voucher.batch);
+
+    Ok(warp::reply::json(&VoucherResponse::from(voucher)))
+}
+
+/// Marks the voucher as revoked, returns `None` if it does not exist.
+async fn revoke_voucher(
+    connection: &mut AsyncPgConnection,
+    voucher_pk: i32,
+) -> Result<Option<Voucher>, Error> {
+    let voucher = diesel::update(voucher::table)
+        .filter(voucher::pk.eq(voucher_pk))
+        .set(voucher::revoked.eq(true))
+        .get_result::<Voucher>(connection)
+        .await
+        .optional()?;
+    Ok(voucher)
+}
+
+/// Redeems the voucher code for the given user, granting time limited supporter status of the
+/// voucher's tier. Each user can redeem a multi-use code only once.
+pub async fn redeem_voucher(user_id: u64, code: &str) -> Result<RedeemVoucherResult, Error> {
+    let mut connection = acquire_db_connection().await?;
+    // the grant is part of the claim's transaction, so a failed grant does not use up the code
+    let (claim, supporter_until) = connection
+        .transaction::<_, Error, _>(|connection| {
+            record_redemption(connection, user_id, code).scope_boxed()
+        })
+        .await?;
+    drop(connection);
+
+    let voucher = match claim {
+        VoucherClaim::Claimed(voucher) => voucher,
+        VoucherClaim::AlreadyRedeemed => return Ok(RedeemVoucherResult::AlreadyRedeemed),
+        VoucherClaim::Invalid => return Ok(RedeemVoucherResult::Invalid),
+    };
+
+    let reason = format!("Redeemed voucher {} of batch {}", voucher.pk, voucher.batch);
+    match supporter_until {
+        Some(supporter_until) => {
+            tracing::info!(
+                "User {user_id} has been granted {} aiode supporter status until {supporter_until}: {reason}",
+                voucher.tier
+            );
+            assign_aiode_supporter_role(user_id, &reason).await;
+        }
+        None => tracing::info!("User {user_id} already is a permanent aiode supporter"),
+    }
+
+    Ok(RedeemVoucherResult::Redeemed {
+        tier: voucher.tier,
+        supporter_until,
+    })
+}
+
+/// Claims the voucher code and grants the user time limited supporter status of the voucher's tier,
+/// to be run within a transaction. The expiration is `None` if the user already is a permanent
+/// supporter.
+async fn record_redemption(
+    connection: &mut AsyncPgConnection,
+    user_id: u64,
+    code: &str,
+) -> Result<(VoucherClaim, Option<DateTime<Utc>>), Error> {
+    let claim = claim_voucher(connection, user_id, code).await?;
+    let VoucherClaim::Claimed(ref voucher) = claim else {
+        return Ok((claim, None));
+    };
+
+    let supporter_until = upsert_time_limited_supporter(
+        connection,
+        user_id,
+        &voucher.tier,
+        chrono::Duration::days(voucher.duration_days.into()),
+        SUPPORTER_SOURCE_VOUCHER,
+    )
+    .await?;
+
+    Ok((claim, supporter_until))
+}
+
+enum VoucherClaim {
+    Claimed(Voucher),
+    AlreadyRedeemed,
+    Invalid,
+}
+
+/// Error of the redemption transaction, which is rolled back on any error.
+enum RedemptionError {
+    AlreadyRedeemed,
+    Query(diesel::result::Error),
+}
+
+impl From<diesel::result::Error> for RedemptionError {
+    fn from(e: diesel::result::Error) -> Self {
+        Self::Query(e)
+    }
+}
+
+/// Records the redemption of the voucher code by the user if the voucher is valid and has redemptions
+/// left, and the user has not redeemed it before.
+async fn claim_voucher(
+    connection: &mut AsyncPgConnection,
+    user_id: u64,
+    code: &str,
+) -> Result<VoucherClaim, Error> {
+    let code_hash = hash_code(code);
+    let claim = connection
+        .transaction::<_, RedemptionError, _>(|connection| {
+            async move {
+                let voucher = diesel::update(voucher::table)
+                    .filter(voucher::code_hash.eq(code_hash))
+                    .filter(voucher::revoked.eq(false))
+                    .filter(voucher::redemption_count.lt(voucher::max_redemptions))
+                    .filter(
+                        voucher::expiration_timestamp
+                            .is_null()
+                            .or(voucher::expiration_timestamp.gt(Utc::now())),
+                    )
+                    .set(voucher::redemption_count.eq(voucher::redemption_count + 1))
+                    .get_result::<Voucher>(connection)
+                    .await
+                    .optional()?;
+
+                let Some(voucher) = voucher else {
+                    return Ok(None);
+                };
+
+                let res = diesel::insert_into(voucher_redemption::table)
+                    .values(NewVoucherRedemption {
+                        voucher_pk: voucher.pk,
+                        user_id: user_id.into(),
+                    })
+                    .on_conflict_do_nothing()
+                    .execute(connection)
+                    .await?;
+
+                if res == 0 {
+                    // rolls back the increased redemption count
+                    return Err(RedemptionError::AlreadyRedeemed);
+                }
+
+                Ok(Some(voucher))
+            }
+            .scope_boxed()
+        })
+        .await;
+
+    match claim {
+        Ok(Some(voucher)) => Ok(VoucherClaim::Claimed(voucher)),
+        Ok(None) => Ok(VoucherClaim::Invalid),
+        Err(RedemptionError::AlreadyRedeemed) => Ok(VoucherClaim::AlreadyRedeemed),
+        Err(RedemptionError::Query(e)) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        model::AiodeSupporter, schema::aiode_supporter, snowflake::Snowflake,
+        test_db::test_connection,
+    };
+
+    async fn insert_voucher(
+        connection: &mut AsyncPgConnection,
+        code: &str,
+        max_redemptions: i32,
+        expiration_timestamp: Option<DateTime<Utc>>,
+    ) -> Voucher {
+        diesel::insert_into(voucher::table)
+            .values(NewVoucher {
+                code_hash: hash_code(code),
+                batch: String::from("test"),
+                tier: String::from("gold"),
+                duration_days: 30,
+                max_redemptions,
+                expiration_timestamp,
+            })
+            .get_result::<Voucher>(connection)
+            .await
+            .unwrap()
+    }
+
+    async fn redemption_count(connection: &mut AsyncPgConnection, voucher_pk: i32) -> i32 {
+        voucher::table
+            .filter(voucher::pk.eq(voucher_pk))
+            .select(voucher::redemption_count)
+            .get_result(connection)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn voucher_is_redeemed_once_per_user() {
+        let Some(mut connection) = test_connection().await else {
+            return;
+        };
+        let voucher = insert_voucher(&mut connection, "CODE-MULTI", 2, None).await;
+
+        assert!(matches!(
+            claim_voucher(&mut connection, 1, "CODE-MULTI").await.unwrap(),
+            VoucherClaim::Claimed(Voucher { pk, .. }) if pk == voucher.pk
+        ));
+        assert!(matches!(
+            claim_voucher(&mut connection, 1, "CODE-MULTI")
+                .await
+                .unwrap(),
+            VoucherClaim::AlreadyRedeemed
+        ));
+        assert_eq!(redemption_count(&mut connection, voucher.pk).await, 1);
+
+        assert!(matches!(
+            claim_voucher(&mut connection, 2, "CODE-MULTI")
+                .await
+                .unwrap(),
+            VoucherClaim::Claimed(_)
+        ));
+        assert!(matches!(
+            claim_voucher(&mut connection, 3, "CODE-MULTI")
+                .await
+                .unwrap(),
+            VoucherClaim::Invalid
+        ));
+        assert_eq!(redemption_count(&mut connection, voucher.pk).await, 2);
+    }
+
+    #[tokio::test]
+    async fn unknown_and_expired_codes_are_invalid() {
+        let Some(mut connection) = test_connection().await else {
+            return;
+        };
+        insert_voucher(
+            &mut connection,
+            "CODE-EXPIRED",
+            1,
+            Some(Utc::now() - chrono::Duration::days(1)),
+        )
+        .await;
+
+        assert!(matches!(
+            claim_voucher(&mut connection, 1, "CODE-UNKNOWN")
+                .await
+                .unwrap(),
+            VoucherClaim::Invalid
+        ));
+        assert!(matches!(
+            claim_voucher(&mut connection, 1, "CODE-EXPIRED")
+                .await
+                .unwrap(),
+            VoucherClaim::Invalid
+        ));
+    }
+
+    #[tokio::test]
+    async fn revoked_voucher_cannot_be_redeemed() {
+        let Some(mut connection) = test_connection().await else {
+            return;
+        };
+        let voucher = insert_voucher(&mut connection, "CODE-REVOKED", 1, None).await;
+
+        let revoked = revoke_voucher(&mut connection, voucher.pk).await.unwrap();
+        assert!(revoked.is_some_and(|voucher| voucher.revoked));
+        assert!(matches!(
+            claim_voucher(&mut connection, 1, "CODE-REVOKED")
+                .await
+                .unwrap(),
+            VoucherClaim::Invalid
+        ));
+        assert!(revoke_voucher(&mut connection, voucher.pk + 1000)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn redemption_grants_supporter_status_with_the_claim() {
+        let Some(mut connection) = test_connection().await else {
+            return;
+        };
+        let voucher = insert_voucher(&mut connection, "CODE-GRANT", 1, None).await;
+
+        let (claim, supporter_until) = record_redemption(&mut connection, 1, "CODE-GRANT")
+            .await
+            .unwrap();
+
+        assert!(matches!(claim, VoucherClaim::Claimed(Voucher { pk, .. }) if pk == voucher.pk));
+        let supporter = aiode_supporter::table
+            .filter(aiode_supporter::user_id.eq(Snowflake::from(1)))
+            .get_result::<AiodeSupporter>(&mut connection)
+            .await
+            .unwrap();
+        assert_eq!(supporter.source, SUPPORTER_SOURCE_VOUCHER);
+        assert_eq!(supporter.tier.as_deref(), Some("gold"));
+        assert!(supporter
+            .expiration_timestamp
+            .is_some_and(|expiration| { expiration > Utc::now() + chrono::Duration::days(29) }));
+        assert!(supporter_until.is_some());
+
+        let (claim, supporter_until) = record_redemption(&mut connection, 2, "CODE-GRANT")
+            .await
+            .unwrap();
+        assert!(matches!(claim, VoucherClaim::Invalid));
+        assert!(supporter_until.is_none());
+    }
+}